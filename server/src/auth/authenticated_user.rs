@@ -1,3 +1,5 @@
+use std::collections::HashSet;
+
 use rocket::{
     async_trait,
     http::Status,
@@ -6,15 +8,181 @@ use rocket::{
 };
 
 use super::{
-    openid::{Claims, MSAJwtValidator},
-    AuthError,
+    bearer::bearer_token,
+    openid::{self, TokenError, ValidatorRegistry},
+    personal_access_token,
+    session::SessionIssuer,
+    AuthError, Scope,
 };
+use crate::{models::common::now_epoch_secs, storage::MongoManager};
 
-pub struct AuthenticatedUser(String);
+pub struct AuthenticatedUser {
+    id: String,
+    /// What the user's token may be used for, if it's a personal access
+    /// token. Tokens from signing in may be used for anything.
+    scopes: Option<HashSet<Scope>>,
+}
 
 impl AuthenticatedUser {
-    pub fn id(self) -> String {
-        self.0
+    /// The cookie and query parameter `StreamingUser` accepts a token from.
+    const ACCESS_TOKEN_PARAM: &'static str = "access_token";
+
+    fn signed_in(id: String) -> Self {
+        Self { id, scopes: None }
+    }
+
+    /// The user's ID, if their token may be used for `scope`.
+    pub fn id_with_scope(self, scope: Scope) -> Result<String, AuthError> {
+        match &self.scopes {
+            Some(scopes) if !scopes.contains(&scope) => Err(AuthError::MissingScope(scope)),
+            _ => Ok(self.id),
+        }
+    }
+
+    /// The user's ID, if they signed in rather than using a personal access
+    /// token. For managing the account itself, e.g. making more tokens.
+    pub fn signed_in_id(self) -> Result<String, AuthError> {
+        match self.scopes {
+            Some(_) => Err(AuthError::SignInRequired),
+            None => Ok(self.id),
+        }
+    }
+
+    /// Validates `token`, yielding the user it was issued to. Accepts our own
+    /// access tokens, authorities' ID tokens, and personal access tokens.
+    async fn from_token(request: &Request<'_>, token: &str) -> Outcome<Self, AuthError> {
+        if personal_access_token::is_personal_access_token(token) {
+            return AuthenticatedUser::from_personal_access_token(request, token).await;
+        }
+
+        let session_issuer = try_outcome!(request
+            .guard::<State<SessionIssuer>>()
+            .await
+            .map_failure(|_| { (Status::ImATeapot, AuthError::FailedToGetJwtValidator,) }));
+
+        if !session_issuer.issued(token) {
+            return AuthenticatedUser::from_id_token(request, token).await;
+        }
+
+        let claims = match session_issuer.validate(token) {
+            Ok(claims) => claims,
+            Err(token_err) => {
+                return Outcome::Failure((Status::ImATeapot, AuthError::InvalidToken(token_err)))
+            }
+        };
+
+        let mongo = try_outcome!(request
+            .guard::<State<MongoManager>>()
+            .await
+            .map_failure(|_| { (Status::ImATeapot, AuthError::FailedToGetJwtValidator,) }));
+
+        AuthenticatedUser::unless_revoked(
+            &mongo,
+            claims.sub,
+            Some(&claims.sid),
+            Some(&claims.jti),
+            Some(claims.iat),
+        )
+        .await
+    }
+
+    /// Validates an authority's ID token, yielding the user its identity is
+    /// linked to, who is created if it's the identity's first sign-in.
+    async fn from_id_token(request: &Request<'_>, token: &str) -> Outcome<Self, AuthError> {
+        let registry_state = try_outcome!(request
+            .guard::<State<ValidatorRegistry>>()
+            .await
+            .map_failure(|_| { (Status::ImATeapot, AuthError::FailedToGetJwtValidator,) }));
+
+        let mongo = try_outcome!(request
+            .guard::<State<MongoManager>>()
+            .await
+            .map_failure(|_| { (Status::ImATeapot, AuthError::FailedToGetJwtValidator,) }));
+
+        let identity = match registry_state.validate(token).await {
+            Ok(identity) => identity,
+            Err(token_err) => {
+                return Outcome::Failure((Status::ImATeapot, AuthError::InvalidToken(token_err)))
+            }
+        };
+
+        let user_id = match mongo
+            .get_or_create_user_id(&identity, now_epoch_secs())
+            .await
+        {
+            Ok(user_id) => user_id,
+            Err(mongo_err) => {
+                return Outcome::Failure((
+                    Status::ImATeapot,
+                    AuthError::UserLookupFailed(mongo_err),
+                ))
+            }
+        };
+
+        // ID tokens have no session or `jti` of ours, but are revoked when
+        // their user signs out everywhere.
+        AuthenticatedUser::unless_revoked(&mongo, user_id, None, None, openid::issued_at(token))
+            .await
+    }
+
+    /// Yields the user with `user_id`, unless the token they presented,
+    /// issued at `issued_at`, has been revoked.
+    async fn unless_revoked(
+        mongo: &MongoManager,
+        user_id: String,
+        session_id: Option<&str>,
+        jti: Option<&str>,
+        issued_at: Option<i64>,
+    ) -> Outcome<Self, AuthError> {
+        match mongo
+            .is_token_revoked(&user_id, session_id, jti, issued_at, now_epoch_secs())
+            .await
+        {
+            Ok(false) => Outcome::Success(AuthenticatedUser::signed_in(user_id)),
+            Ok(true) => Outcome::Failure((
+                Status::ImATeapot,
+                AuthError::InvalidToken(TokenError::Revoked),
+            )),
+            Err(mongo_err) => {
+                Outcome::Failure((Status::ImATeapot, AuthError::UserLookupFailed(mongo_err)))
+            }
+        }
+    }
+
+    async fn from_personal_access_token(
+        request: &Request<'_>,
+        token: &str,
+    ) -> Outcome<Self, AuthError> {
+        let mongo = try_outcome!(request
+            .guard::<State<MongoManager>>()
+            .await
+            .map_failure(|_| { (Status::ImATeapot, AuthError::FailedToGetJwtValidator,) }));
+
+        match mongo
+            .use_personal_access_token(&personal_access_token::hash(token), now_epoch_secs())
+            .await
+        {
+            Ok(Some(personal_access_token)) => Outcome::Success(Self {
+                id: String::from(personal_access_token.user_id()),
+                scopes: Some(personal_access_token.scopes().clone()),
+            }),
+            Ok(None) => {
+                Outcome::Failure((Status::ImATeapot, AuthError::InvalidPersonalAccessToken))
+            }
+            Err(mongo_err) => {
+                Outcome::Failure((Status::ImATeapot, AuthError::UserLookupFailed(mongo_err)))
+            }
+        }
+    }
+
+    async fn from_auth_header(
+        request: &Request<'_>,
+        auth_header: &str,
+    ) -> Outcome<Self, AuthError> {
+        match bearer_token(auth_header) {
+            Ok(token) => AuthenticatedUser::from_token(request, token).await,
+            Err(auth_err) => Outcome::Failure((Status::ImATeapot, auth_err)),
+        }
     }
 }
 
@@ -30,18 +198,51 @@ impl<'a, 'r> FromRequest<'a, 'r> for AuthenticatedUser {
     /// to determine the status code to respond with.
     async fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
         match request.headers().get_one("Authorization") {
-            Some(auth_header) => {
-                let validator_state = try_outcome!(request
-                    .guard::<State<MSAJwtValidator>>()
-                    .await
-                    .map_failure(|_| { (Status::ImATeapot, AuthError::FailedToGetJwtValidator,) }));
-
-                if let Some(token_claims) = validator_state.validate(auth_header).await {
-                    Outcome::Success(Self(token_claims.user_id()))
-                } else {
-                    Outcome::Failure((Status::ImATeapot, AuthError::InvalidToken))
-                }
-            }
+            Some(auth_header) => AuthenticatedUser::from_auth_header(request, auth_header).await,
+            None => Outcome::Failure((Status::ImATeapot, AuthError::MissingAuthHeader)),
+        }
+    }
+}
+
+/// An `AuthenticatedUser` for streaming endpoints, which browsers' `EventSource`
+/// can't set headers for. Falls back to a token in the `access_token` cookie,
+/// then the `access_token` query parameter, if there's no `Authorization`
+/// header. Query parameters tend to end up in logs, so only use this where a
+/// header really can't be sent.
+pub struct StreamingUser(AuthenticatedUser);
+
+impl StreamingUser {
+    pub fn id_with_scope(self, scope: Scope) -> Result<String, AuthError> {
+        self.0.id_with_scope(scope)
+    }
+}
+
+#[async_trait]
+impl<'a, 'r> FromRequest<'a, 'r> for StreamingUser {
+    type Error = AuthError;
+
+    /// Like `AuthenticatedUser`'s, errors from here are fed into `ApiError`.
+    async fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        if let Some(auth_header) = request.headers().get_one("Authorization") {
+            return AuthenticatedUser::from_auth_header(request, auth_header)
+                .await
+                .map(StreamingUser);
+        }
+
+        let token = request
+            .cookies()
+            .get(AuthenticatedUser::ACCESS_TOKEN_PARAM)
+            .map(|cookie| String::from(cookie.value()))
+            .or_else(|| {
+                request
+                    .get_query_value::<String>(AuthenticatedUser::ACCESS_TOKEN_PARAM)
+                    .and_then(Result::ok)
+            });
+
+        match token {
+            Some(token) => AuthenticatedUser::from_token(request, &token)
+                .await
+                .map(StreamingUser),
             None => Outcome::Failure((Status::ImATeapot, AuthError::MissingAuthHeader)),
         }
     }
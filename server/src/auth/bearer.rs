@@ -0,0 +1,59 @@
+use super::AuthError;
+
+/// The scheme we accept in `Authorization` headers, per RFC 6750.
+const BEARER_SCHEME: &str = "Bearer";
+
+/// Pulls the token out of an `Authorization` header's value. The scheme is
+/// matched case-insensitively, as RFC 7235 says it must be.
+pub fn bearer_token(auth_header: &str) -> Result<&str, AuthError> {
+    let auth_header = auth_header.trim();
+    let mut scheme_token = auth_header.splitn(2, |c: char| c.is_ascii_whitespace());
+
+    let scheme = scheme_token.next().unwrap_or_default();
+    if !scheme.eq_ignore_ascii_case(BEARER_SCHEME) {
+        return Err(AuthError::UnsupportedAuthScheme(String::from(scheme)));
+    }
+
+    match scheme_token.next().map(str::trim) {
+        Some(token) if !token.is_empty() && !token.contains(char::is_whitespace) => Ok(token),
+        _ => Err(AuthError::MalformedAuthHeader),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    /// Tests that the token follows a case-insensitive `Bearer` scheme.
+    fn test_parses_bearer_token() {
+        assert_eq!(bearer_token("Bearer abc.def.ghi").ok(), Some("abc.def.ghi"));
+        assert_eq!(bearer_token("bearer abc.def.ghi").ok(), Some("abc.def.ghi"));
+        assert_eq!(
+            bearer_token("BEARER  abc.def.ghi ").ok(),
+            Some("abc.def.ghi")
+        );
+    }
+
+    #[test]
+    /// Tests that other schemes, and bearer headers with no single token, are
+    /// rejected.
+    fn test_rejects_other_schemes_and_malformed_headers() {
+        assert!(matches!(
+            bearer_token("Basic dXNlcjpwYXNz"),
+            Err(AuthError::UnsupportedAuthScheme(scheme)) if scheme == "Basic"
+        ));
+        assert!(matches!(
+            bearer_token("abc.def.ghi"),
+            Err(AuthError::UnsupportedAuthScheme(_))
+        ));
+        assert!(matches!(
+            bearer_token("Bearer"),
+            Err(AuthError::MalformedAuthHeader)
+        ));
+        assert!(matches!(
+            bearer_token("Bearer abc def"),
+            Err(AuthError::MalformedAuthHeader)
+        ));
+    }
+}
@@ -1,6 +1,29 @@
+use super::{openid::TokenError, Role, Scope};
+use crate::storage::MongoError;
+
 #[derive(Debug)]
 pub enum AuthError {
     FailedToGetJwtValidator,
     MissingAuthHeader,
-    InvalidToken,
+    /// The `Authorization` header's scheme, which isn't `Bearer`.
+    UnsupportedAuthScheme(String),
+    /// A `Bearer` header without exactly one token.
+    MalformedAuthHeader,
+    InvalidToken(TokenError),
+    /// A refresh token that's unknown, expired, or its session was revoked.
+    InvalidRefreshToken,
+    /// A refresh token that was already exchanged. Its session is revoked,
+    /// since the token must have leaked.
+    RefreshTokenReused,
+    /// A personal access token that's unknown, expired or revoked.
+    InvalidPersonalAccessToken,
+    /// Looking up who a token belongs to failed.
+    UserLookupFailed(MongoError),
+    /// A personal access token without the scope the route needs.
+    MissingScope(Scope),
+    /// A personal access token used where only signing in will do, e.g. to
+    /// make more tokens.
+    SignInRequired,
+    /// A user without the role the route needs.
+    MissingRole(Role),
 }
@@ -1,6 +1,16 @@
 mod authenticated_user;
+mod bearer;
 mod error;
+mod require;
+mod role;
+mod scope;
 
 pub mod openid;
-pub use authenticated_user::AuthenticatedUser;
+pub mod personal_access_token;
+pub mod revocation;
+pub mod session;
+pub use authenticated_user::{AuthenticatedUser, StreamingUser};
 pub use error::AuthError;
+pub use require::{roles, scopes, RequireRole, RequireScope, RequiredRole, RequiredScope};
+pub use role::Role;
+pub use scope::Scope;
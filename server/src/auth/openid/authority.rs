@@ -2,33 +2,106 @@ use std::marker::PhantomData;
 
 use serde::{de::DeserializeOwned, Deserialize};
 
+use super::{config::AuthorityConfig, jwt::SigningAlgorithm};
+
 /// The well-known URI path for the OpenID discovery metadata document.
 const OPENID_DISCOVERY_PATH: &'static str = ".well-known/openid-configuration";
 
+/// How far, in seconds, our clock may disagree with an authority's by
+/// default.
+const DEFAULT_LEEWAY_SECS: u64 = 60;
+
 /// An OpenID authority.
 pub struct Authority<C: Claims> {
-    domain: &'static str,
-    aud: &'static str,
+    /// The `iss` claim in tokens from this authority.
+    issuer: String,
+    /// Where the authority's discovery metadata document lives.
+    domain: String,
+    /// Our app registrations with this authority.
+    audiences: Vec<String>,
+    /// The algorithms we accept signatures from this authority with.
+    algorithms: Vec<SigningAlgorithm>,
+    /// How far our clock may disagree with the authority's, in seconds.
+    leeway: u64,
+    /// How long after being issued we accept a token for, in seconds, if
+    /// that should be shorter than the token's own lifetime.
+    max_token_age: Option<u64>,
     claims: PhantomData<C>,
 }
 
 /// Claims in an `id_token` from an authority.
-pub trait Claims: DeserializeOwned + Send + Sync {
-    fn user_id(self) -> String;
+pub trait Claims: DeserializeOwned + Clone + Send + Sync {
+    /// The authority's ID for the user. It's only unique among the
+    /// authority's own users, so it's never a user ID by itself; see
+    /// `ProviderIdentity`.
+    fn subject(self) -> String;
 }
 
 impl<C: Claims> Authority<C> {
-    #[cfg(test)]
-    pub fn new(domain: &'static str, aud: &'static str) -> Self {
+    pub fn new(issuer: &str, domain: &str, audiences: Vec<String>) -> Self {
         Self {
-            domain,
-            aud,
+            issuer: String::from(issuer),
+            domain: String::from(domain),
+            audiences,
+            algorithms: SigningAlgorithm::ALL.to_vec(),
+            leeway: DEFAULT_LEEWAY_SECS,
+            max_token_age: None,
             claims: PhantomData,
         }
     }
 
-    pub fn aud(&self) -> &'static str {
-        self.aud
+    pub fn from_config(config: &AuthorityConfig) -> Self {
+        let mut authority = Self::new(&config.issuer, config.domain(), config.audiences.clone());
+
+        if let Some(algorithms) = &config.algorithms {
+            authority = authority.with_algorithms(algorithms.clone());
+        }
+
+        if let Some(leeway) = config.leeway_secs {
+            authority = authority.with_leeway(leeway);
+        }
+
+        authority.with_max_token_age(config.max_token_age_secs)
+    }
+
+    /// Only accept signatures made with `algorithms`, rather than with any
+    /// algorithm we support.
+    pub fn with_algorithms(mut self, algorithms: Vec<SigningAlgorithm>) -> Self {
+        self.algorithms = algorithms;
+
+        self
+    }
+
+    pub fn with_leeway(mut self, leeway: u64) -> Self {
+        self.leeway = leeway;
+
+        self
+    }
+
+    pub fn with_max_token_age(mut self, max_token_age: Option<u64>) -> Self {
+        self.max_token_age = max_token_age;
+
+        self
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    pub fn audiences(&self) -> &[String] {
+        &self.audiences
+    }
+
+    pub fn leeway(&self) -> u64 {
+        self.leeway
+    }
+
+    pub fn max_token_age(&self) -> Option<u64> {
+        self.max_token_age
+    }
+
+    pub fn allows(&self, algorithm: SigningAlgorithm) -> bool {
+        self.algorithms.contains(&algorithm)
     }
 
     pub fn metadata_path(&self) -> String {
@@ -36,25 +109,26 @@ impl<C: Claims> Authority<C> {
     }
 }
 
-impl Authority<MSAClaims> {
-    /// Represents our app registration with the MSA OpenID Connect authority. Uses
-    /// "consumers" tenant because our MSA app is only accessible by personal MSAs.
-    ///
-    /// See: https://docs.microsoft.com/en-us/azure/active-directory/develop/v2-protocols-oidc#fetch-the-openid-connect-metadata-document
-    pub const MSA: Self = Self {
-        domain: "https://login.microsoftonline.com/consumers/v2.0",
-        aud: "97b5900d-bdbe-41bf-8afb-39fdcb0993ee",
-        claims: PhantomData,
-    };
-}
-
-#[derive(Deserialize)]
+/// MSA's claims, which identify the user by `oid`.
+#[derive(Clone, Deserialize)]
 pub struct MSAClaims {
     oid: String,
 }
 
 impl Claims for MSAClaims {
-    fn user_id(self) -> String {
+    fn subject(self) -> String {
         self.oid
     }
 }
+
+/// The standard OpenID Connect claims, which identify the user by `sub`.
+#[derive(Clone, Deserialize)]
+pub struct OidcClaims {
+    sub: String,
+}
+
+impl Claims for OidcClaims {
+    fn subject(self) -> String {
+        self.sub
+    }
+}
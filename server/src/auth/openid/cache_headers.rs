@@ -0,0 +1,128 @@
+use std::time::Duration;
+
+/// How long a response may be cached for, going by its `Cache-Control`,
+/// `Expires` and `Date` headers as RFC 7234 describes. `max-age` wins over
+/// `Expires`, and `no-cache` or `no-store` mean not at all. `None` if the
+/// headers don't say.
+pub fn freshness_lifetime(
+    cache_control: Option<&str>,
+    expires: Option<&str>,
+    date: Option<&str>,
+    now: i64,
+) -> Option<Duration> {
+    if let Some(cache_control) = cache_control {
+        for directive in cache_control.split(',').map(str::trim) {
+            let mut name_value = directive.splitn(2, '=');
+            let name = name_value.next().unwrap_or_default();
+
+            if name.eq_ignore_ascii_case("no-cache") || name.eq_ignore_ascii_case("no-store") {
+                return Some(Duration::from_secs(0));
+            }
+
+            if name.eq_ignore_ascii_case("max-age") {
+                if let Some(Ok(max_age)) = name_value
+                    .next()
+                    .map(|value| value.trim_matches('"').parse::<u64>())
+                {
+                    return Some(Duration::from_secs(max_age));
+                }
+            }
+        }
+    }
+
+    // An `Expires` we can't parse means the response has already expired.
+    let expires = parse_http_date(expires?).unwrap_or(0);
+    let date = date.and_then(parse_http_date).unwrap_or(now);
+
+    Some(Duration::from_secs((expires - date).max(0) as u64))
+}
+
+/// Parses an HTTP-date in the preferred IMF-fixdate format, e.g.
+/// `Sun, 06 Nov 1994 08:49:37 GMT`, into epoch-seconds.
+pub fn parse_http_date(date: &str) -> Option<i64> {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    let parts = date.split_whitespace().collect::<Vec<_>>();
+    if parts.len() != 6 || parts[5] != "GMT" {
+        return None;
+    }
+
+    let day = parts[1].parse::<i64>().ok()?;
+    let month = MONTHS.iter().position(|month| *month == parts[2])? as i64 + 1;
+    let year = parts[3].parse::<i64>().ok()?;
+
+    let time = parts[4]
+        .split(':')
+        .map(|part| part.parse::<i64>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    if time.len() != 3 || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    Some(days_from_civil(year, month, day) * 86_400 + time[0] * 3_600 + time[1] * 60 + time[2])
+}
+
+/// Days since the epoch of a proleptic Gregorian date, per Howard Hinnant's
+/// `days_from_civil`.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    /// Tests that HTTP-dates parse to the right instant.
+    fn test_parses_http_dates() {
+        assert_eq!(
+            parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"),
+            Some(784_111_777)
+        );
+        assert_eq!(parse_http_date("Thu, 01 Jan 1970 00:00:00 GMT"), Some(0));
+        assert_eq!(
+            parse_http_date("Tue, 29 Feb 2000 12:00:00 GMT"),
+            Some(951_825_600)
+        );
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("not a date"), None);
+    }
+
+    #[test]
+    /// Tests that `max-age` wins over `Expires`, which is measured from
+    /// `Date`, and that `no-cache` and `no-store` mean zero.
+    fn test_freshness_lifetime() {
+        let date = "Sun, 06 Nov 1994 08:49:37 GMT";
+        let expires = "Sun, 06 Nov 1994 09:49:37 GMT";
+
+        assert_eq!(
+            freshness_lifetime(Some("public, max-age=3600"), Some(date), None, 0),
+            Some(Duration::from_secs(3_600))
+        );
+        assert_eq!(
+            freshness_lifetime(None, Some(expires), Some(date), 0),
+            Some(Duration::from_secs(3_600))
+        );
+        assert_eq!(
+            freshness_lifetime(None, Some(expires), None, 784_111_777 + 600),
+            Some(Duration::from_secs(3_000))
+        );
+        assert_eq!(
+            freshness_lifetime(Some("no-cache"), Some(expires), Some(date), 0),
+            Some(Duration::from_secs(0))
+        );
+        assert_eq!(
+            freshness_lifetime(None, Some("0"), Some(date), 0),
+            Some(Duration::from_secs(0))
+        );
+        assert_eq!(freshness_lifetime(Some("public"), None, None, 0), None);
+    }
+}
@@ -0,0 +1,457 @@
+use std::{collections::HashSet, fmt, path::PathBuf, time::Duration};
+
+use serde::Deserialize;
+
+use super::{jwt::SigningAlgorithm, key_set::KeySet};
+use crate::auth::session::SessionConfig;
+
+/// Which OpenID authorities users can sign in with, and how the sessions we
+/// issue them after sign-in work. Loaded from the `auth` section of Rocket's
+/// config, so it can differ between profiles.
+#[derive(Debug, Deserialize)]
+pub struct AuthConfig {
+    pub authorities: Vec<AuthorityConfig>,
+    #[serde(default)]
+    pub session: SessionConfig,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuthorityConfig {
+    /// The `iss` claim in tokens from this authority.
+    pub issuer: String,
+    /// Where the authority's discovery metadata document lives. Defaults to
+    /// `issuer`, which is where the spec says it should be.
+    pub domain: Option<String>,
+    /// Our app registrations with this authority. Tokens must be issued to
+    /// one of them.
+    pub audiences: Vec<String>,
+    /// Which claim identifies the user.
+    pub user_id_claim: UserIdClaim,
+    /// The algorithms to accept signatures with. Defaults to all those we
+    /// support, none of which are `none` or HMAC.
+    pub algorithms: Option<Vec<SigningAlgorithm>>,
+    /// The minimum interval between attempted key set refreshes. Defaults to
+    /// `JwtValidator`'s.
+    pub refresh_interval_secs: Option<u64>,
+    /// How far, in seconds, our clock may disagree with the authority's when
+    /// checking `exp`, `nbf` and `iat`. Defaults to a minute.
+    pub leeway_secs: Option<u64>,
+    /// How long after being issued, in seconds, to accept a token for, if
+    /// that should be shorter than the token's own lifetime. Unlimited by
+    /// default.
+    pub max_token_age_secs: Option<u64>,
+    /// How many verified tokens to remember, so repeat requests skip the
+    /// signature check. Zero turns the cache off. Defaults to
+    /// `JwtValidator`'s.
+    pub token_cache_capacity: Option<usize>,
+    /// Where to get the authority's keys. Defaults to the network.
+    pub key_source: Option<KeySource>,
+}
+
+/// Where an authority's keys come from.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeySource {
+    /// The `jwks_uri` named by the authority's discovery document.
+    Network,
+    /// A local JWKS file, re-read when it changes.
+    File(PathBuf),
+    /// A JWKS given inline.
+    Inline(KeySet),
+    /// The network, falling back to a pinned local JWKS file while the
+    /// authority is unreachable.
+    NetworkWithFallback(PathBuf),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UserIdClaim {
+    /// MSA's object ID, which is stable across app registrations.
+    Oid,
+    /// The standard OpenID Connect subject.
+    Sub,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum AuthConfigError {
+    NoAuthorities,
+    DuplicateIssuer(String),
+    /// A URL that doesn't parse, or isn't `https`. With the mock provider
+    /// built in, `http` on localhost is allowed too.
+    InvalidUrl {
+        issuer: String,
+        url: String,
+    },
+    NoAudiences(String),
+    NoAlgorithms(String),
+    ZeroRefreshInterval(String),
+    ZeroMaxTokenAge(String),
+    /// The authority's inline keys, none of which we can use.
+    NoKeys(String),
+}
+
+impl AuthConfig {
+    /// Checks that every authority is usable, so bad config fails at startup
+    /// rather than at the first sign-in.
+    pub fn validate(&self) -> Result<(), AuthConfigError> {
+        if self.authorities.is_empty() {
+            return Err(AuthConfigError::NoAuthorities);
+        }
+
+        let mut issuers = HashSet::new();
+
+        for authority in &self.authorities {
+            if !issuers.insert(&authority.issuer) {
+                return Err(AuthConfigError::DuplicateIssuer(authority.issuer.clone()));
+            }
+
+            authority.validate()?;
+        }
+
+        Ok(())
+    }
+
+    /// The authority users signed in with before they had Sonar user IDs:
+    /// MSA, which identifies users by `oid`. Those users' IDs are their `oid`s.
+    pub fn legacy_authority(&self) -> Option<&AuthorityConfig> {
+        self.authorities
+            .iter()
+            .find(|authority| authority.user_id_claim == UserIdClaim::Oid)
+    }
+}
+
+impl AuthorityConfig {
+    pub fn domain(&self) -> &str {
+        self.domain.as_deref().unwrap_or(&self.issuer)
+    }
+
+    pub fn refresh_interval(&self) -> Option<Duration> {
+        self.refresh_interval_secs.map(Duration::from_secs)
+    }
+
+    fn validate(&self) -> Result<(), AuthConfigError> {
+        for url in &[&self.issuer, self.domain()] {
+            match reqwest::Url::parse(url) {
+                Ok(parsed) if is_allowed_url(&parsed) => {}
+                _ => {
+                    return Err(AuthConfigError::InvalidUrl {
+                        issuer: self.issuer.clone(),
+                        url: String::from(*url),
+                    })
+                }
+            }
+        }
+
+        if self.audiences.is_empty() {
+            return Err(AuthConfigError::NoAudiences(self.issuer.clone()));
+        }
+
+        if let Some(algorithms) = &self.algorithms {
+            if algorithms.is_empty() {
+                return Err(AuthConfigError::NoAlgorithms(self.issuer.clone()));
+            }
+        }
+
+        if self.refresh_interval_secs == Some(0) {
+            return Err(AuthConfigError::ZeroRefreshInterval(self.issuer.clone()));
+        }
+
+        if self.max_token_age_secs == Some(0) {
+            return Err(AuthConfigError::ZeroMaxTokenAge(self.issuer.clone()));
+        }
+
+        if matches!(&self.key_source, Some(KeySource::Inline(key_set)) if key_set.is_empty()) {
+            return Err(AuthConfigError::NoKeys(self.issuer.clone()));
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for AuthConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AuthConfigError::NoAuthorities => write!(f, "no authorities are configured"),
+            AuthConfigError::DuplicateIssuer(issuer) => {
+                write!(f, "issuer {} is configured more than once", issuer)
+            }
+            AuthConfigError::InvalidUrl { issuer, url } => write!(
+                f,
+                "authority {} has an invalid URL {}: must be https",
+                issuer, url
+            ),
+            AuthConfigError::NoAudiences(issuer) => {
+                write!(f, "authority {} has no audiences", issuer)
+            }
+            AuthConfigError::NoAlgorithms(issuer) => {
+                write!(f, "authority {} allows no algorithms", issuer)
+            }
+            AuthConfigError::ZeroRefreshInterval(issuer) => {
+                write!(f, "authority {} has a zero refresh interval", issuer)
+            }
+            AuthConfigError::ZeroMaxTokenAge(issuer) => {
+                write!(f, "authority {} has a zero maximum token age", issuer)
+            }
+            AuthConfigError::NoKeys(issuer) => {
+                write!(f, "authority {} has no usable inline keys", issuer)
+            }
+        }
+    }
+}
+
+/// Whether authorities may be reached at `url`: over `https`, or, with the
+/// mock provider built in, plain `http` that never leaves this machine.
+fn is_allowed_url(url: &reqwest::Url) -> bool {
+    match url.scheme() {
+        "https" => true,
+        #[cfg(feature = "mock-oidc")]
+        "http" => matches!(
+            url.host_str(),
+            Some("localhost") | Some("127.0.0.1") | Some("[::1]")
+        ),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    /// Tests that a well-formed config validates, with `domain` defaulting to
+    /// `issuer`.
+    fn test_valid_config() {
+        let config = utils::config(json!([{
+            "issuer": "https://accounts.google.com",
+            "audiences": ["client-id"],
+            "user_id_claim": "sub"
+        }]));
+
+        assert_eq!(config.validate(), Ok(()));
+        assert_eq!(
+            config.authorities[0].domain(),
+            "https://accounts.google.com"
+        );
+    }
+
+    #[test]
+    /// Tests each way an authority can be misconfigured.
+    fn test_invalid_authorities() {
+        let authority = |issuer: &str, audiences: Vec<&str>, refresh: u64| {
+            json!({
+                "issuer": issuer,
+                "audiences": audiences,
+                "user_id_claim": "sub",
+                "refresh_interval_secs": refresh
+            })
+        };
+
+        assert_eq!(
+            utils::config(json!([])).validate(),
+            Err(AuthConfigError::NoAuthorities)
+        );
+        assert_eq!(
+            utils::config(json!([
+                authority("https://a.example.com", vec!["aud"], 60),
+                authority("https://a.example.com", vec!["aud"], 60)
+            ]))
+            .validate(),
+            Err(AuthConfigError::DuplicateIssuer(String::from(
+                "https://a.example.com"
+            )))
+        );
+        assert_eq!(
+            utils::config(json!([authority("http://a.example.com", vec!["aud"], 60)])).validate(),
+            Err(AuthConfigError::InvalidUrl {
+                issuer: String::from("http://a.example.com"),
+                url: String::from("http://a.example.com"),
+            })
+        );
+        assert_eq!(
+            utils::config(json!([authority("https://a.example.com", vec![], 60)])).validate(),
+            Err(AuthConfigError::NoAudiences(String::from(
+                "https://a.example.com"
+            )))
+        );
+        assert_eq!(
+            utils::config(json!([authority("https://a.example.com", vec!["aud"], 0)])).validate(),
+            Err(AuthConfigError::ZeroRefreshInterval(String::from(
+                "https://a.example.com"
+            )))
+        );
+        assert_eq!(
+            utils::config(json!([{
+                "issuer": "https://a.example.com",
+                "audiences": ["aud"],
+                "user_id_claim": "sub",
+                "max_token_age_secs": 0
+            }]))
+            .validate(),
+            Err(AuthConfigError::ZeroMaxTokenAge(String::from(
+                "https://a.example.com"
+            )))
+        );
+    }
+
+    #[test]
+    /// Tests that plain HTTP is only allowed to this machine, and only with
+    /// the mock provider built in.
+    fn test_http_only_on_localhost() {
+        let authority = |issuer: &str| {
+            utils::config(json!([{
+                "issuer": issuer,
+                "audiences": ["aud"],
+                "user_id_claim": "sub"
+            }]))
+        };
+
+        let mock_oidc = cfg!(feature = "mock-oidc");
+
+        assert_eq!(
+            authority("http://localhost:8000/mock-oidc")
+                .validate()
+                .is_ok(),
+            mock_oidc
+        );
+        assert_eq!(
+            authority("http://127.0.0.1:8000").validate().is_ok(),
+            mock_oidc
+        );
+        assert_eq!(authority("http://[::1]:8000").validate().is_ok(), mock_oidc);
+        assert!(matches!(
+            authority("http://localhost.example.com").validate(),
+            Err(AuthConfigError::InvalidUrl { .. })
+        ));
+    }
+
+    #[test]
+    /// Tests that `none` and HMAC algorithms can't be allowed, and that at
+    /// least one algorithm must be.
+    fn test_algorithm_allowlist() {
+        let authority = |algorithms: Vec<&str>| {
+            json!({
+                "authorities": [{
+                    "issuer": "https://a.example.com",
+                    "audiences": ["aud"],
+                    "user_id_claim": "sub",
+                    "algorithms": algorithms
+                }]
+            })
+        };
+
+        assert!(serde_json::from_value::<AuthConfig>(authority(vec!["none"])).is_err());
+        assert!(serde_json::from_value::<AuthConfig>(authority(vec!["HS256"])).is_err());
+        assert_eq!(
+            serde_json::from_value::<AuthConfig>(authority(vec![]))
+                .expect("Failed to parse config")
+                .validate(),
+            Err(AuthConfigError::NoAlgorithms(String::from(
+                "https://a.example.com"
+            )))
+        );
+        assert_eq!(
+            serde_json::from_value::<AuthConfig>(authority(vec!["RS256", "EdDSA"]))
+                .expect("Failed to parse config")
+                .validate(),
+            Ok(())
+        );
+    }
+
+    #[test]
+    /// Tests each key source parses, defaulting to the network, and that
+    /// inline keys must include one we can use.
+    fn test_key_sources() {
+        let authority = |key_source: serde_json::Value| {
+            let mut authority = json!({
+                "issuer": "https://a.example.com",
+                "audiences": ["aud"],
+                "user_id_claim": "sub"
+            });
+            if !key_source.is_null() {
+                authority["key_source"] = key_source;
+            }
+
+            utils::config(json!([authority]))
+        };
+
+        let config = authority(json!(null));
+        assert!(config.authorities[0].key_source.is_none());
+
+        let config = authority(json!({ "file": "/etc/sonar/jwks.json" }));
+        assert!(matches!(
+            &config.authorities[0].key_source,
+            Some(KeySource::File(path)) if path == &PathBuf::from("/etc/sonar/jwks.json")
+        ));
+
+        let config = authority(json!({ "network_with_fallback": "/etc/sonar/jwks.json" }));
+        assert!(matches!(
+            config.authorities[0].key_source,
+            Some(KeySource::NetworkWithFallback(_))
+        ));
+
+        let config = authority(json!({ "inline": { "keys": [
+            { "kty": "RSA", "kid": "pinned", "n": "AQAB", "e": "AQAB" }
+        ] } }));
+        assert_eq!(config.validate(), Ok(()));
+
+        let config = authority(json!({ "inline": { "keys": [
+            { "kty": "oct", "kid": "hmac", "k": "AQAB" }
+        ] } }));
+        assert_eq!(
+            config.validate(),
+            Err(AuthConfigError::NoKeys(String::from(
+                "https://a.example.com"
+            )))
+        );
+    }
+
+    #[test]
+    /// Tests that the legacy authority is the one identifying users by `oid`.
+    fn test_legacy_authority() {
+        let config = utils::config(json!([
+            {
+                "issuer": "https://accounts.google.com",
+                "audiences": ["aud"],
+                "user_id_claim": "sub"
+            },
+            {
+                "issuer": "https://login.microsoftonline.com/consumers/v2.0",
+                "audiences": ["aud"],
+                "user_id_claim": "oid"
+            }
+        ]));
+
+        assert_eq!(
+            config
+                .legacy_authority()
+                .map(|authority| authority.issuer.as_str()),
+            Some("https://login.microsoftonline.com/consumers/v2.0")
+        );
+        assert!(utils::config(json!([])).legacy_authority().is_none());
+    }
+
+    #[test]
+    /// Tests that an unsupported user ID claim is rejected when parsing.
+    fn test_rejects_unknown_user_id_claim() {
+        let parsed = serde_json::from_value::<AuthConfig>(json!({
+            "authorities": [{
+                "issuer": "https://a.example.com",
+                "audiences": ["aud"],
+                "user_id_claim": "email"
+            }]
+        }));
+
+        assert!(parsed.is_err());
+    }
+
+    mod utils {
+        use super::*;
+
+        pub fn config(authorities: serde_json::Value) -> AuthConfig {
+            serde_json::from_value(json!({ "authorities": authorities }))
+                .expect("Failed to parse config")
+        }
+    }
+}
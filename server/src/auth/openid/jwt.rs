@@ -0,0 +1,219 @@
+use std::str::FromStr;
+
+use jsonwebtoken::{
+    dangerous_insecure_decode_with_validation, decode, Algorithm, DecodingKey, Validation,
+};
+use ring::signature::{UnparsedPublicKey, ED25519};
+use serde::{de::DeserializeOwned, Deserialize};
+
+use super::{
+    key_set::{EcCurve, KeyParams, OkpCurve},
+    token_error::TokenError,
+};
+
+/// The parts of a JWT's header we need. Parsed ourselves, since `jsonwebtoken`
+/// rejects tokens whose `alg` it doesn't support (e.g. `EdDSA`) outright.
+#[derive(Deserialize)]
+pub struct RawHeader {
+    pub alg: String,
+    pub kid: Option<String>,
+}
+
+impl RawHeader {
+    pub fn decode(jwt: &str) -> Result<Self, TokenError> {
+        let encoded = jwt.split('.').next().ok_or(TokenError::Malformed)?;
+
+        decode_part(encoded)
+    }
+}
+
+/// Reads `jwt`'s claims without verifying anything.
+pub fn decode_unverified_claims<T: DeserializeOwned>(jwt: &str) -> Result<T, TokenError> {
+    let encoded = jwt.split('.').nth(1).ok_or(TokenError::Malformed)?;
+
+    decode_part(encoded)
+}
+
+/// `jwt`'s `iat`, if it has one. Only trustworthy once `jwt` has been
+/// validated.
+pub fn issued_at(jwt: &str) -> Option<i64> {
+    #[derive(Deserialize)]
+    struct IssuedAtClaim {
+        iat: Option<i64>,
+    }
+
+    decode_unverified_claims::<IssuedAtClaim>(jwt)
+        .ok()
+        .and_then(|claims| claims.iat)
+}
+
+/// Decodes a base64url-encoded JSON part of a JWT.
+fn decode_part<T: DeserializeOwned>(encoded: &str) -> Result<T, TokenError> {
+    let json = decode_base64(encoded)?;
+
+    serde_json::from_slice(&json).map_err(|_| TokenError::Malformed)
+}
+
+fn decode_base64(encoded: &str) -> Result<Vec<u8>, TokenError> {
+    base64::decode_config(encoded, base64::URL_SAFE_NO_PAD).map_err(|_| TokenError::Malformed)
+}
+
+/// The JWS algorithms we accept signatures from. Only asymmetric ones: an
+/// authority signs with a private key and we verify with its public key, so
+/// `none` and the HMAC algorithms, which would have us verify with a shared
+/// secret, are deliberately unrepresentable.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+pub enum SigningAlgorithm {
+    RS256,
+    RS384,
+    RS512,
+    PS256,
+    PS384,
+    PS512,
+    ES256,
+    ES384,
+    EdDSA,
+}
+
+impl SigningAlgorithm {
+    pub const ALL: [SigningAlgorithm; 9] = [
+        SigningAlgorithm::RS256,
+        SigningAlgorithm::RS384,
+        SigningAlgorithm::RS512,
+        SigningAlgorithm::PS256,
+        SigningAlgorithm::PS384,
+        SigningAlgorithm::PS512,
+        SigningAlgorithm::ES256,
+        SigningAlgorithm::ES384,
+        SigningAlgorithm::EdDSA,
+    ];
+
+    /// Parses a token header's `alg`. Fails for `none`, the HMAC algorithms,
+    /// and anything else we don't accept.
+    pub fn from_header(alg: &str) -> Option<Self> {
+        SigningAlgorithm::ALL
+            .iter()
+            .copied()
+            .find(|algorithm| algorithm.name() == alg)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SigningAlgorithm::RS256 => "RS256",
+            SigningAlgorithm::RS384 => "RS384",
+            SigningAlgorithm::RS512 => "RS512",
+            SigningAlgorithm::PS256 => "PS256",
+            SigningAlgorithm::PS384 => "PS384",
+            SigningAlgorithm::PS512 => "PS512",
+            SigningAlgorithm::ES256 => "ES256",
+            SigningAlgorithm::ES384 => "ES384",
+            SigningAlgorithm::EdDSA => "EdDSA",
+        }
+    }
+}
+
+/// Verifies `jwt`'s signature with `key` using `algorithm`, checks its claims
+/// against `validation`, and returns them. Fails unless `algorithm` is one
+/// that `key`'s type is meant for. `validation.algorithms` is ignored in favor
+/// of `algorithm`.
+pub fn decode_with_key<C: DeserializeOwned>(
+    jwt: &str,
+    algorithm: SigningAlgorithm,
+    key: &KeyParams,
+    mut validation: Validation,
+) -> Result<C, TokenError> {
+    match (key, algorithm) {
+        (
+            KeyParams::Rsa { modulus, exponent },
+            SigningAlgorithm::RS256
+            | SigningAlgorithm::RS384
+            | SigningAlgorithm::RS512
+            | SigningAlgorithm::PS256
+            | SigningAlgorithm::PS384
+            | SigningAlgorithm::PS512,
+        ) => {
+            validation.algorithms = vec![jsonwebtoken_algorithm(algorithm)?];
+
+            let decoding_key = DecodingKey::from_rsa_components(modulus, exponent);
+
+            Ok(decode::<C>(jwt, &decoding_key, &validation)?.claims)
+        }
+        (
+            KeyParams::Ec {
+                curve: EcCurve::P256,
+                x,
+                y,
+            },
+            SigningAlgorithm::ES256,
+        )
+        | (
+            KeyParams::Ec {
+                curve: EcCurve::P384,
+                x,
+                y,
+            },
+            SigningAlgorithm::ES384,
+        ) => {
+            validation.algorithms = vec![jsonwebtoken_algorithm(algorithm)?];
+
+            // `jsonwebtoken` wants the public key as an uncompressed point.
+            let mut point = vec![0x04];
+            point.extend(decode_base64(x).map_err(|_| TokenError::UnusableKey)?);
+            point.extend(decode_base64(y).map_err(|_| TokenError::UnusableKey)?);
+
+            Ok(decode::<C>(jwt, &DecodingKey::from_ec_der(&point), &validation)?.claims)
+        }
+        (
+            KeyParams::Okp {
+                curve: OkpCurve::Ed25519,
+                x,
+            },
+            SigningAlgorithm::EdDSA,
+        ) => {
+            let public_key = decode_base64(x).map_err(|_| TokenError::UnusableKey)?;
+
+            decode_ed25519(jwt, &public_key, validation)
+        }
+        _ => Err(TokenError::UnusableKey),
+    }
+}
+
+fn jsonwebtoken_algorithm(algorithm: SigningAlgorithm) -> Result<Algorithm, TokenError> {
+    Algorithm::from_str(algorithm.name())
+        .map_err(|_| TokenError::UnsupportedAlgorithm(String::from(algorithm.name())))
+}
+
+/// `jsonwebtoken` can't verify EdDSA signatures, so we verify them with `ring`
+/// ourselves. To still have `jsonwebtoken` check the claims, we then hand it
+/// the token under a stand-in header it understands.
+fn decode_ed25519<C: DeserializeOwned>(
+    jwt: &str,
+    public_key: &[u8],
+    mut validation: Validation,
+) -> Result<C, TokenError> {
+    /// `{"alg":"HS256"}`, base64url-encoded.
+    const STAND_IN_HEADER: &str = "eyJhbGciOiJIUzI1NiJ9";
+
+    let mut parts = jwt.rsplitn(2, '.');
+    let signature = parts.next().ok_or(TokenError::Malformed)?;
+    let signing_input = parts.next().ok_or(TokenError::Malformed)?;
+    let claims = signing_input
+        .splitn(2, '.')
+        .nth(1)
+        .ok_or(TokenError::Malformed)?;
+
+    let signature = decode_base64(signature)?;
+
+    UnparsedPublicKey::new(&ED25519, public_key)
+        .verify(signing_input.as_bytes(), &signature)
+        .map_err(|_| TokenError::BadSignature)?;
+
+    validation.algorithms = vec![Algorithm::HS256];
+
+    let token_data = dangerous_insecure_decode_with_validation::<C>(
+        &format!("{}.{}.", STAND_IN_HEADER, claims),
+        &validation,
+    )?;
+
+    Ok(token_data.claims)
+}
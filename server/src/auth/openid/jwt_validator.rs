@@ -1,20 +1,37 @@
-use std::time::{Duration, Instant};
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex as SyncMutex, MutexGuard, PoisonError, RwLock,
+    },
+    time::{Duration, Instant},
+};
 
-use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
-use tokio::sync::{Mutex, MutexGuard};
+use jsonwebtoken::Validation;
+use tokio::sync::Mutex;
 
 use super::{
-    authority::{Authority, Claims, MSAClaims},
+    authority::{Authority, Claims},
+    jwt::{self, RawHeader, SigningAlgorithm},
     key_set::{Key, KeySet, KeySetFetcher, NetworkKeySetFetcher},
+    registered_claims::{ClaimsPolicy, RegisteredClaims},
+    token_cache::{self, TokenCache, TokenCacheStats, VerifiedToken},
+    token_error::TokenError,
 };
+use crate::models::common::now_epoch_secs;
 
-pub type MSAJwtValidator = JwtValidator<MSAClaims, NetworkKeySetFetcher>;
+/// How often to refresh keys in the background if the authority doesn't say
+/// how long they may be cached for.
+const DEFAULT_REFRESH_DELAY: Duration = Duration::from_secs(60 * 60);
 
-impl MSAJwtValidator {
-    pub fn new_msa() -> MSAJwtValidator {
-        JwtValidator::new(Authority::MSA)
-    }
-}
+/// The minimum interval between attempted key set refreshes by default.
+pub const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// How many verified tokens to remember by default.
+const DEFAULT_TOKEN_CACHE_CAPACITY: usize = 10_000;
+
+/// The longest we'll go between background refreshes, whatever the authority
+/// says, so rotations are still picked up ahead of use.
+const MAX_REFRESH_DELAY: Duration = Duration::from_secs(24 * 60 * 60);
 
 pub struct JwtValidator<C: Claims, F: KeySetFetcher> {
     /// The OpenID authority to use to validate.
@@ -23,15 +40,34 @@ pub struct JwtValidator<C: Claims, F: KeySetFetcher> {
     fetcher: F,
     /// The minimum interval between attempted key set refreshes.
     refresh_interval: Duration,
-    /// A cached key set from the authority.
-    key_set_cache: Mutex<KeySetCache>,
+    /// The authority's key set as last fetched. Lookups only ever hold the
+    /// lock long enough to clone the `Arc`, and refreshes only to swap in a
+    /// new one, so neither waits on the network.
+    key_set_cache: RwLock<Arc<KeySetCache>>,
+    /// Held for the duration of a refresh, so that only one is ever in flight
+    /// and validators missing a key wait on it rather than starting their own.
+    refresh_state: Mutex<RefreshState>,
+    /// How many refreshes have been attempted, so a validator that waited on
+    /// one can tell it happened. Only changed while holding `refresh_state`.
+    refresh_attempts: AtomicU64,
+    /// Tokens we've already verified, so that clients sending the same token
+    /// on every request don't cost a signature check each time. Only ever
+    /// locked briefly, never across an `await`.
+    token_cache: SyncMutex<TokenCache<C>>,
 }
 
 struct KeySetCache {
     /// The currently-cached keys.
     keys: KeySet,
-    /// When the cache was last updated.
-    last_updated: Instant,
+    /// When `keys` were fetched, if they ever have been.
+    fetched_at: Option<Instant>,
+}
+
+struct RefreshState {
+    /// When a refresh was last attempted.
+    last_attempted: Instant,
+    /// Whether the last attempted refresh succeeded.
+    last_succeeded: bool,
 }
 
 impl<C: Claims> JwtValidator<C, NetworkKeySetFetcher> {
@@ -39,7 +75,7 @@ impl<C: Claims> JwtValidator<C, NetworkKeySetFetcher> {
         JwtValidator::new_with_config(
             authority,
             NetworkKeySetFetcher::new(),
-            Duration::from_secs(5 * 60),
+            DEFAULT_REFRESH_INTERVAL,
         )
     }
 }
@@ -54,72 +90,248 @@ impl<C: Claims, F: KeySetFetcher> JwtValidator<C, F> {
             authority,
             fetcher,
             refresh_interval,
-            key_set_cache: Mutex::new(KeySetCache {
+            key_set_cache: RwLock::new(Arc::new(KeySetCache {
                 keys: KeySet::empty(),
-                last_updated: Instant::now() - refresh_interval,
+                fetched_at: None,
+            })),
+            refresh_state: Mutex::new(RefreshState {
+                last_attempted: Instant::now() - refresh_interval,
+                last_succeeded: false,
             }),
+            refresh_attempts: AtomicU64::new(0),
+            token_cache: SyncMutex::new(TokenCache::new(DEFAULT_TOKEN_CACHE_CAPACITY)),
         }
     }
 
-    /// Returns a boolean indicating if the given JWT validated, using the authority
-    /// this validator was initialized with. May perform a keyset cache refresh if
-    /// the JWT was signed with a key we don't have locally.
-    pub async fn validate(&self, jwt: &str) -> Option<C> {
-        if let Ok(header) = decode_header(jwt) {
-            if let Some(thumbprint) = header.kid {
-                if let Some(key) = self.get_key(&thumbprint).await {
-                    let decoding_key =
-                        DecodingKey::from_rsa_components(&key.modulus, &key.exponent);
+    /// Remember up to `capacity` verified tokens, rather than the default
+    /// number. Zero turns the cache off.
+    pub fn with_token_cache_capacity(self, capacity: usize) -> Self {
+        Self {
+            token_cache: SyncMutex::new(TokenCache::new(capacity)),
+            ..self
+        }
+    }
 
-                    let mut validation = Validation::new(Algorithm::from(header.alg));
-                    validation.set_audience(&[self.authority.aud()]);
+    /// The `iss` of tokens this validator accepts.
+    pub fn issuer(&self) -> &str {
+        self.authority.issuer()
+    }
 
-                    if let Ok(token_data) = decode::<C>(jwt, &decoding_key, &validation) {
-                        return Some(token_data.claims);
-                    }
-                }
-            }
+    /// Validates the given JWT using the authority this validator was
+    /// initialized with, returning its claims or why it was rejected. May
+    /// perform a keyset cache refresh if the JWT was signed with a key we
+    /// don't have locally.
+    pub async fn validate(&self, jwt: &str) -> Result<C, TokenError> {
+        let token_hash = token_cache::hash_token(jwt);
+        let now = now_epoch_secs();
+
+        let cached = self.lock_token_cache().get(&token_hash, now);
+        if let Some(verified) = cached {
+            verified
+                .registered
+                .check(&self.claims_policy(&self.expected_issuer()), now)?;
+
+            return Ok(verified.claims);
         }
 
-        None
+        let header = RawHeader::decode(jwt)?;
+
+        // Trust only what the authority and its key say about the algorithm,
+        // never the token alone.
+        let algorithm = SigningAlgorithm::from_header(&header.alg)
+            .filter(|algorithm| self.authority.allows(*algorithm))
+            .ok_or_else(|| TokenError::UnsupportedAlgorithm(header.alg.clone()))?;
+
+        let thumbprint = header.kid.ok_or(TokenError::MissingClaim("kid"))?;
+        let key = self.get_key(&thumbprint).await?;
+        if !key.is_usable_with(algorithm) {
+            return Err(TokenError::UnusableKey);
+        }
+
+        let mut validation = Validation::default();
+        validation.set_audience(self.authority.audiences());
+        validation.leeway = self.authority.leeway();
+
+        let claims = jwt::decode_with_key::<C>(jwt, algorithm, &key.params, validation)?;
+
+        // The signature checked out, so the registered claims can be trusted.
+        let registered = jwt::decode_unverified_claims::<RegisteredClaims>(jwt)?;
+        registered.check(&self.claims_policy(&self.expected_issuer()), now)?;
+
+        if let Some(exp) = registered.exp() {
+            self.lock_token_cache().insert(
+                token_hash,
+                VerifiedToken {
+                    claims: claims.clone(),
+                    registered,
+                    thumbprint,
+                    expires_at: exp + self.authority.leeway() as i64,
+                },
+            );
+        }
+
+        Ok(claims)
     }
 
-    async fn get_key(&self, thumbprint: &str) -> Option<Key> {
-        let mut cache = self.key_set_cache.lock().await;
+    /// How often tokens have been found in the verified-token cache.
+    pub fn token_cache_stats(&self) -> TokenCacheStats {
+        self.lock_token_cache().stats()
+    }
 
-        match cache.keys.key_with_thumbprint(&thumbprint) {
-            Some(key) => Some(key),
-            None => {
-                if self.try_refresh_key_set(&mut cache).await {
-                    cache.keys.key_with_thumbprint(&thumbprint)
-                } else {
-                    None
-                }
-            }
+    fn claims_policy<'a>(&self, expected_issuer: &'a str) -> ClaimsPolicy<'a> {
+        ClaimsPolicy {
+            issuer: expected_issuer,
+            leeway: self.authority.leeway() as i64,
+            max_age: self.authority.max_token_age().map(|age| age as i64),
         }
     }
 
-    /// Try and refresh the cached key set. Returns a boolean representing if the
-    /// cache was refreshed or not. The cache could fail to refresh if a refresh
-    /// was attempted recently, or if there was an error performing a refresh.
-    async fn try_refresh_key_set<'a>(&self, cache_guard: &mut MutexGuard<'a, KeySetCache>) -> bool {
-        if Instant::now().duration_since(cache_guard.last_updated) >= self.refresh_interval {
-            let maybe_key_set = self.fetcher.fetch(&self.authority).await;
+    fn lock_token_cache(&self) -> MutexGuard<'_, TokenCache<C>> {
+        // As with the key set cache, a panic can't leave it half-updated.
+        self.token_cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
 
-            // Regardless of if we succeeded in getting a fresh key set above,
-            // set the updated time so we don't try again for another 5m.
-            cache_guard.last_updated = Instant::now();
+    /// The `iss` tokens must carry: whatever the authority's discovery
+    /// document says, falling back to the configured issuer.
+    fn expected_issuer(&self) -> String {
+        let cache = self.cached_key_set();
 
-            match maybe_key_set {
-                Ok(fresh_key_set) => {
-                    cache_guard.keys = fresh_key_set;
-                    true
-                }
-                Err(_) => false,
+        String::from(
+            cache
+                .keys
+                .issuer()
+                .unwrap_or_else(|| self.authority.issuer()),
+        )
+    }
+
+    /// Finds the key with `thumbprint`. Cached keys are found without waiting
+    /// on anything; missing ones are waited on from the in-flight refresh, or
+    /// a new one if none is and one wasn't attempted recently.
+    async fn get_key(&self, thumbprint: &str) -> Result<Key, TokenError> {
+        // Read before looking, so a refresh finishing after the lookup can't
+        // go unnoticed.
+        let attempts_before_waiting = self.refresh_attempts.load(Ordering::SeqCst);
+
+        if let Some(key) = self.cached_key_set().keys.key_with_thumbprint(thumbprint) {
+            return Ok(key);
+        }
+
+        let mut refresh_state = self.refresh_state.lock().await;
+
+        let refreshed = if self.refresh_attempts.load(Ordering::SeqCst) != attempts_before_waiting {
+            // Someone else's refresh finished since we looked, so use its
+            // result rather than refreshing again.
+            if refresh_state.last_succeeded {
+                true
+            } else {
+                return Err(TokenError::KeyFetchFailed);
             }
+        } else if Instant::now().duration_since(refresh_state.last_attempted)
+            >= self.refresh_interval
+        {
+            self.fetch_key_set(&mut refresh_state).await?;
+            true
         } else {
             false
+        };
+
+        drop(refresh_state);
+
+        if refreshed {
+            if let Some(key) = self.cached_key_set().keys.key_with_thumbprint(thumbprint) {
+                return Ok(key);
+            }
         }
+
+        Err(TokenError::UnknownKey(String::from(thumbprint)))
+    }
+
+    /// Refreshes the cached key set regardless of when it was last refreshed,
+    /// as the background refresher does, and returns how long to wait before
+    /// the next refresh: as long as the authority says the keys may be cached
+    /// for, within limits. If the refresh fails, the last good key set is
+    /// kept, and the next refresh is sooner.
+    pub async fn refresh_key_set(&self) -> Duration {
+        let mut refresh_state = self.refresh_state.lock().await;
+
+        match self.fetch_key_set(&mut refresh_state).await {
+            Ok(()) => self
+                .cached_key_set()
+                .keys
+                .max_age()
+                .unwrap_or(DEFAULT_REFRESH_DELAY)
+                .max(self.refresh_interval)
+                .min(MAX_REFRESH_DELAY),
+            Err(_) => self.refresh_interval,
+        }
+    }
+
+    /// How long ago the cached key set was fetched, or `None` if one never
+    /// has been.
+    pub fn key_set_age(&self) -> Option<Duration> {
+        self.cached_key_set()
+            .fetched_at
+            .map(|fetched_at| fetched_at.elapsed())
+    }
+
+    fn cached_key_set(&self) -> Arc<KeySetCache> {
+        // Nothing panics while holding the lock, but if something did, the
+        // cache it left behind would still be a whole one.
+        let cache = self
+            .key_set_cache
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        Arc::clone(&cache)
+    }
+
+    /// Fetches a fresh key set into the cache, while holding the refresh
+    /// state. If that fails, the cache keeps the key set it had.
+    async fn fetch_key_set(&self, refresh_state: &mut RefreshState) -> Result<(), TokenError> {
+        let maybe_key_set = self.fetcher.fetch(&self.authority).await;
+
+        // Regardless of if we succeeded in getting a fresh key set above, note
+        // the attempt so we don't try again until the refresh interval has
+        // passed.
+        refresh_state.last_attempted = Instant::now();
+        refresh_state.last_succeeded = maybe_key_set.is_ok();
+
+        let result = match maybe_key_set {
+            Ok(fresh_key_set) => {
+                // Forget tokens verified with keys that were rotated out.
+                let stale_cache = self.cached_key_set();
+                self.lock_token_cache().retain_thumbprints(|thumbprint| {
+                    let fresh_key = fresh_key_set.key_with_thumbprint(thumbprint);
+
+                    fresh_key.is_some()
+                        && fresh_key == stale_cache.keys.key_with_thumbprint(thumbprint)
+                });
+
+                let fresh_cache = Arc::new(KeySetCache {
+                    keys: fresh_key_set,
+                    fetched_at: Some(refresh_state.last_attempted),
+                });
+
+                *self
+                    .key_set_cache
+                    .write()
+                    .unwrap_or_else(PoisonError::into_inner) = fresh_cache;
+
+                Ok(())
+            }
+            Err(err) => {
+                eprintln!("Failed to fetch keys for {}: {:?}", self.issuer(), err);
+                Err(TokenError::KeyFetchFailed)
+            }
+        };
+
+        // Only once the fresh keys are in place, so anyone who sees the new
+        // count also sees them.
+        self.refresh_attempts.fetch_add(1, Ordering::SeqCst);
+
+        result
     }
 }
 
@@ -128,6 +340,10 @@ mod test {
 
     use super::*;
 
+    use jsonwebtoken::Algorithm;
+
+    use crate::auth::openid::KeyUse;
+
     #[tokio::test]
     /// Tests that a JWT encoded with a local key successfully decodes with a
     /// fresh validator. Since the validator starts with an empty keyset, also
@@ -147,7 +363,7 @@ mod test {
                 .validate(&token)
                 .await
                 .expect("Token failed to validate")
-                .user_id(),
+                .subject(),
             String::from("user_id")
         );
     }
@@ -170,11 +386,17 @@ mod test {
         let token = utils::generate_jwt("keyid", "my::aud", 3);
 
         // First validation should fail because fetcher will return an empty keyset.
-        assert!(validator.validate(&token).await.is_none());
+        assert_eq!(
+            validator.validate(&token).await.err(),
+            Some(TokenError::UnknownKey(String::from("keyid")))
+        );
 
         // Second immediate validation should fail because fetcher will decline to
         // refresh the keyset.
-        assert!(validator.validate(&token).await.is_none());
+        assert_eq!(
+            validator.validate(&token).await.err(),
+            Some(TokenError::UnknownKey(String::from("keyid")))
+        );
 
         tokio::time::sleep(Duration::from_millis(1)).await;
 
@@ -185,11 +407,228 @@ mod test {
                 .validate(&token)
                 .await
                 .expect("Token failed to validate")
-                .user_id(),
+                .subject(),
             String::from("user_id")
         );
     }
 
+    #[tokio::test]
+    /// Tests that failing to fetch the authority's keys is reported as such,
+    /// rather than as an unknown key.
+    async fn test_validation_reports_key_fetch_failure() {
+        let validator = JwtValidator::new_with_config(
+            utils::generate_authority("my::aud"),
+            utils::FailingKeySetFetcher,
+            Duration::from_secs(0),
+        );
+
+        let token = utils::generate_jwt("keyid", "my::aud", 3_000);
+
+        assert_eq!(
+            validator.validate(&token).await.err(),
+            Some(TokenError::KeyFetchFailed)
+        );
+    }
+
+    #[tokio::test]
+    /// Tests that background refreshes are scheduled by the key set's max
+    /// age, within limits, and sooner after a failure, which keeps the last
+    /// good key set.
+    async fn test_background_refresh_schedule() {
+        let validator = JwtValidator::new_with_config(
+            utils::generate_authority("my::aud"),
+            utils::TestKeySetFetcher::new_then_failing(
+                utils::generate_keyset("keyid").with_max_age(Duration::from_secs(2 * 60 * 60)),
+            ),
+            Duration::from_secs(60),
+        );
+
+        assert_eq!(validator.key_set_age(), None);
+        assert_eq!(
+            validator.refresh_key_set().await,
+            Duration::from_secs(2 * 60 * 60)
+        );
+        assert!(validator.key_set_age().is_some());
+
+        assert_eq!(validator.refresh_key_set().await, Duration::from_secs(60));
+        assert!(validator.key_set_age().is_some());
+        assert!(validator
+            .validate(&utils::generate_jwt("keyid", "my::aud", 3_000))
+            .await
+            .is_ok());
+
+        let short_lived = JwtValidator::new_with_config(
+            utils::generate_authority("my::aud"),
+            utils::TestKeySetFetcher::new(
+                utils::generate_keyset("keyid").with_max_age(Duration::from_secs(10)),
+            ),
+            Duration::from_secs(60),
+        );
+        let uncached = JwtValidator::new_with_config(
+            utils::generate_authority("my::aud"),
+            utils::TestKeySetFetcher::new(utils::generate_keyset("keyid")),
+            Duration::from_secs(60),
+        );
+
+        assert_eq!(short_lived.refresh_key_set().await, Duration::from_secs(60));
+        assert_eq!(uncached.refresh_key_set().await, DEFAULT_REFRESH_DELAY);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    /// Tests that cached keys are found without waiting while a refresh is in
+    /// flight, and that validators missing a key all wait on that one refresh
+    /// rather than starting their own.
+    async fn test_cached_lookups_never_wait_on_refresh() {
+        let validator = Arc::new(JwtValidator::new_with_config(
+            utils::generate_authority("my::aud"),
+            utils::GatedKeySetFetcher::new(
+                utils::generate_keyset("keyid"),
+                KeySet::with_keys(vec![
+                    utils::generate_rsa_key("keyid"),
+                    utils::generate_rsa_key("rotated"),
+                ]),
+            ),
+            Duration::from_secs(0),
+        ));
+
+        // Let the first fetch through, to cache `keyid`.
+        validator.fetcher.gate.add_permits(1);
+        validator.refresh_key_set().await;
+
+        // Hold the next refresh in flight, with validators waiting on it.
+        let waiting = (0..4)
+            .map(|_| {
+                let validator = Arc::clone(&validator);
+
+                tokio::spawn(async move {
+                    validator
+                        .validate(&utils::generate_jwt("rotated", "my::aud", 3_000))
+                        .await
+                        .map(|_| ())
+                })
+            })
+            .collect::<Vec<_>>();
+
+        while validator.fetcher.fetches.load(Ordering::SeqCst) < 2 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+
+        let cached = tokio::time::timeout(
+            Duration::from_secs(1),
+            validator.validate(&utils::generate_jwt("keyid", "my::aud", 3_000)),
+        )
+        .await;
+        assert!(matches!(cached, Ok(Ok(_))));
+
+        validator.fetcher.gate.add_permits(1);
+
+        for task in waiting {
+            assert_eq!(task.await.expect("Validation panicked"), Ok(()));
+        }
+        assert_eq!(validator.fetcher.fetches.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    /// Tests that a repeated token is served from the verified-token cache,
+    /// and forgotten once its key is rotated out.
+    async fn test_token_cache_hits_until_key_rotates() {
+        let validator = JwtValidator::new_with_config(
+            utils::generate_authority("my::aud"),
+            utils::TestKeySetFetcher::new_with_multiple(
+                utils::generate_keyset("keyid"),
+                utils::generate_keyset("rotated"),
+            ),
+            Duration::from_secs(0),
+        );
+
+        let token = utils::generate_jwt("keyid", "my::aud", 3_000);
+
+        assert!(validator.validate(&token).await.is_ok());
+        assert!(validator.validate(&token).await.is_ok());
+        assert_eq!(
+            validator.token_cache_stats(),
+            TokenCacheStats {
+                hits: 1,
+                misses: 1,
+                entries: 1
+            }
+        );
+
+        validator.refresh_key_set().await;
+
+        assert_eq!(validator.token_cache_stats().entries, 0);
+        assert_eq!(
+            validator.validate(&token).await.err(),
+            Some(TokenError::UnknownKey(String::from("keyid")))
+        );
+    }
+
+    #[tokio::test]
+    /// Tests that a cached token is still held to the authority's maximum
+    /// token age, which can lapse before the token expires.
+    async fn test_token_cache_rechecks_claims() {
+        let validator = JwtValidator::new_with_config(
+            utils::generate_authority("my::aud").with_max_token_age(Some(60)),
+            utils::TestKeySetFetcher::new(utils::generate_keyset("keyid")),
+            Duration::from_secs(0),
+        );
+
+        let mut claims = utils::generate_claims("my::aud", 3_000);
+        claims.iat = now_epoch_secs() - 60 * 60;
+        let token = utils::generate_jwt_with_claims("keyid", &claims);
+
+        // Plant the token as though it was verified while it was young.
+        validator.lock_token_cache().insert(
+            token_cache::hash_token(&token),
+            VerifiedToken {
+                claims,
+                registered: jwt::decode_unverified_claims(&token).expect("Failed to decode claims"),
+                thumbprint: String::from("keyid"),
+                expires_at: now_epoch_secs() + 60 * 60,
+            },
+        );
+
+        assert_eq!(
+            validator.validate(&token).await.err(),
+            Some(TokenError::TooOld)
+        );
+        assert_eq!(validator.token_cache_stats().hits, 1);
+    }
+
+    #[tokio::test]
+    #[ignore]
+    /// Benchmarks validating the same token repeatedly with the verified-token
+    /// cache off and at its default capacity. Ignored, so it only runs (and
+    /// prints) when asked for, with
+    /// `cargo test --release bench_token_cache -- --ignored --nocapture`.
+    async fn bench_token_cache() {
+        const ITERATIONS: u32 = 2_000;
+
+        let token = utils::generate_jwt("keyid", "my::aud", 60_000);
+
+        for &capacity in &[0, DEFAULT_TOKEN_CACHE_CAPACITY] {
+            let validator = JwtValidator::new_with_config(
+                utils::generate_authority("my::aud"),
+                utils::TestKeySetFetcher::new(utils::generate_keyset("keyid")),
+                Duration::from_secs(0),
+            )
+            .with_token_cache_capacity(capacity);
+
+            let start = Instant::now();
+            for _ in 0..ITERATIONS {
+                assert!(validator.validate(&token).await.is_ok());
+            }
+            let elapsed = start.elapsed();
+
+            println!(
+                "capacity {}: {:?} per validation, hit rate {:?}",
+                capacity,
+                elapsed / ITERATIONS,
+                validator.token_cache_stats().hit_rate()
+            );
+        }
+    }
+
     #[tokio::test]
     /// Tests that a JWT with an aud not matching ours is rejected.
     async fn test_validation_rejects_mismatched_aud() {
@@ -201,7 +640,10 @@ mod test {
 
         let token = utils::generate_jwt("keyid", "not::my::aud", 3);
 
-        assert!(validator.validate(&token).await.is_none());
+        assert_eq!(
+            validator.validate(&token).await.err(),
+            Some(TokenError::WrongAudience)
+        );
     }
 
     #[tokio::test]
@@ -213,11 +655,14 @@ mod test {
             Duration::from_secs(0),
         );
 
-        let token = utils::generate_jwt("keyid", "not::my::aud", 1);
+        let mut claims = utils::generate_claims("my::aud", 0);
+        claims.exp = (now_epoch_secs() - 2 * 60) as u64;
+        let token = utils::generate_jwt_with_claims("keyid", &claims);
 
-        tokio::time::sleep(Duration::from_millis(2)).await;
-
-        assert!(validator.validate(&token).await.is_none());
+        assert_eq!(
+            validator.validate(&token).await.err(),
+            Some(TokenError::Expired)
+        );
     }
 
     #[tokio::test]
@@ -236,18 +681,26 @@ mod test {
                 .validate(&token)
                 .await
                 .expect("Token failed to validate")
-                .user_id(),
+                .subject(),
             String::from("user_id")
         );
 
-        // Manually break the signature by swapping the last char
-        let last_char = token.pop().expect("Token was empty");
-        token.push(match last_char {
-            'a' => 'b',
-            _ => 'a',
-        });
+        // Manually break the signature by swapping its first char. Not the
+        // last, whose unused low bits would make the base64 itself invalid.
+        let signature_start = token.rfind('.').expect("Token had no signature") + 1;
+        let first_char = token.remove(signature_start);
+        token.insert(
+            signature_start,
+            match first_char {
+                'a' => 'b',
+                _ => 'a',
+            },
+        );
 
-        assert!(validator.validate(&token).await.is_none());
+        assert_eq!(
+            validator.validate(&token).await.err(),
+            Some(TokenError::BadSignature)
+        );
     }
 
     #[tokio::test]
@@ -259,73 +712,490 @@ mod test {
             Duration::from_secs(0),
         );
 
+        assert_eq!(
+            validator.validate("not_a_jwt_not_even_close").await.err(),
+            Some(TokenError::Malformed)
+        );
+    }
+
+    #[tokio::test]
+    /// Tests that ES256 JWTs validate against EC keys.
+    async fn test_validates_ec_signed_jwt() {
+        let (token, key) = utils::generate_ec_jwt("keyid", "my::aud");
+
+        let validator = JwtValidator::new_with_config(
+            utils::generate_authority("my::aud"),
+            utils::TestKeySetFetcher::new(KeySet::with_keys(vec![key])),
+            Duration::from_secs(0),
+        );
+
+        assert_eq!(
+            validator
+                .validate(&token)
+                .await
+                .expect("Token failed to validate")
+                .subject(),
+            String::from("user_id")
+        );
+    }
+
+    #[tokio::test]
+    /// Tests that EdDSA JWTs validate against Ed25519 keys, and that their
+    /// claims can't be tampered with.
+    async fn test_validates_ed25519_signed_jwt() {
+        let (token, key) = utils::generate_ed25519_jwt("keyid", "my::aud");
+
+        let validator = JwtValidator::new_with_config(
+            utils::generate_authority("my::aud"),
+            utils::TestKeySetFetcher::new(KeySet::with_keys(vec![key])),
+            Duration::from_secs(0),
+        );
+
+        assert_eq!(
+            validator
+                .validate(&token)
+                .await
+                .expect("Token failed to validate")
+                .subject(),
+            String::from("user_id")
+        );
+
+        // Swap in claims for a different audience, keeping the signature.
+        let parts = token.split('.').collect::<Vec<_>>();
+        let (other_token, _) = utils::generate_ed25519_jwt("keyid", "not::my::aud");
+        let other_claims = other_token.split('.').nth(1).expect("Token had no claims");
+        let tampered = format!("{}.{}.{}", parts[0], other_claims, parts[2]);
+
+        assert_eq!(
+            validator.validate(&tampered).await.err(),
+            Some(TokenError::BadSignature)
+        );
+    }
+
+    #[tokio::test]
+    /// Tests that a JWT is rejected if its `kid` names a key of the wrong type
+    /// for its `alg`.
+    async fn test_validation_rejects_mismatched_key_type() {
+        let (token, _) = utils::generate_ec_jwt("keyid", "my::aud");
+
+        let validator = JwtValidator::new_with_config(
+            utils::generate_authority("my::aud"),
+            utils::TestKeySetFetcher::new(utils::generate_keyset("keyid")),
+            Duration::from_secs(0),
+        );
+
+        assert_eq!(
+            validator.validate(&token).await.err(),
+            Some(TokenError::UnusableKey)
+        );
+    }
+
+    #[tokio::test]
+    /// Tests that an unsigned JWT (`alg` `none`) is rejected.
+    async fn test_validation_rejects_alg_none() {
+        let validator = JwtValidator::new_with_config(
+            utils::generate_authority("my::aud"),
+            utils::TestKeySetFetcher::new(utils::generate_keyset("keyid")),
+            Duration::from_secs(0),
+        );
+
+        let token = utils::generate_unsigned_jwt("keyid", "my::aud");
+
+        assert_eq!(
+            validator.validate(&token).await.err(),
+            Some(TokenError::UnsupportedAlgorithm(String::from("none")))
+        );
+    }
+
+    #[tokio::test]
+    /// Tests that an HMAC JWT keyed with the authority's public key is
+    /// rejected, rather than verified with the public key as the secret.
+    async fn test_validation_rejects_hmac_key_confusion() {
+        let validator = JwtValidator::new_with_config(
+            utils::generate_authority("my::aud"),
+            utils::TestKeySetFetcher::new(utils::generate_keyset("keyid")),
+            Duration::from_secs(0),
+        );
+
+        let token = utils::generate_key_confused_jwt("keyid", "my::aud");
+
+        assert_eq!(
+            validator.validate(&token).await.err(),
+            Some(TokenError::UnsupportedAlgorithm(String::from("HS256")))
+        );
+    }
+
+    #[tokio::test]
+    /// Tests that a correctly-signed JWT is rejected if its algorithm isn't
+    /// one the authority allows.
+    async fn test_validation_rejects_disallowed_algorithm() {
+        let validator = JwtValidator::new_with_config(
+            utils::generate_authority("my::aud").with_algorithms(vec![SigningAlgorithm::ES256]),
+            utils::TestKeySetFetcher::new(utils::generate_keyset("keyid")),
+            Duration::from_secs(0),
+        );
+
+        let token = utils::generate_jwt("keyid", "my::aud", 3_000);
+
+        assert_eq!(
+            validator.validate(&token).await.err(),
+            Some(TokenError::UnsupportedAlgorithm(String::from("RS256")))
+        );
+    }
+
+    #[tokio::test]
+    /// Tests that a correctly-signed JWT is rejected if its algorithm isn't
+    /// the one its key declares.
+    async fn test_validation_rejects_alg_not_matching_key() {
+        let mut key = utils::generate_rsa_key("keyid");
+        key.algorithm = Some(SigningAlgorithm::PS256);
+
+        let validator = JwtValidator::new_with_config(
+            utils::generate_authority("my::aud"),
+            utils::TestKeySetFetcher::new(KeySet::with_keys(vec![key])),
+            Duration::from_secs(0),
+        );
+
+        let rs256_token = utils::generate_rsa_jwt("keyid", "my::aud", 3_000, Algorithm::RS256);
+        let ps256_token = utils::generate_rsa_jwt("keyid", "my::aud", 3_000, Algorithm::PS256);
+
+        assert_eq!(
+            validator.validate(&rs256_token).await.err(),
+            Some(TokenError::UnusableKey)
+        );
+        assert!(validator.validate(&ps256_token).await.is_ok());
+    }
+
+    #[tokio::test]
+    /// Tests that a JWT is rejected if its key is meant for encryption.
+    async fn test_validation_rejects_encryption_key() {
+        let mut key = utils::generate_rsa_key("keyid");
+        key.usage = Some(KeyUse::Enc);
+
+        let validator = JwtValidator::new_with_config(
+            utils::generate_authority("my::aud"),
+            utils::TestKeySetFetcher::new(KeySet::with_keys(vec![key])),
+            Duration::from_secs(0),
+        );
+
+        let token = utils::generate_jwt("keyid", "my::aud", 3_000);
+
+        assert_eq!(
+            validator.validate(&token).await.err(),
+            Some(TokenError::UnusableKey)
+        );
+    }
+
+    #[tokio::test]
+    /// Tests that a JWT from another issuer is rejected, and that the issuer
+    /// named by the authority's discovery document is the one expected.
+    async fn test_validation_checks_issuer() {
+        let validator = JwtValidator::new_with_config(
+            utils::generate_authority("my::aud"),
+            utils::TestKeySetFetcher::new(utils::generate_keyset("keyid")),
+            Duration::from_secs(0),
+        );
+
+        let mut claims = utils::generate_claims("my::aud", 3_000);
+        claims.iss = String::from("https://evil.example.com");
+
+        assert_eq!(
+            validator
+                .validate(&utils::generate_jwt_with_claims("keyid", &claims))
+                .await
+                .err(),
+            Some(TokenError::WrongIssuer(String::from(
+                "https://evil.example.com"
+            )))
+        );
+
+        let validator = JwtValidator::new_with_config(
+            utils::generate_authority("my::aud"),
+            utils::TestKeySetFetcher::new(
+                utils::generate_keyset("keyid")
+                    .with_issuer(String::from("https://discovered.example.com")),
+            ),
+            Duration::from_secs(0),
+        );
+
+        let configured = utils::generate_jwt("keyid", "my::aud", 3_000);
+        claims.iss = String::from("https://discovered.example.com");
+        let discovered = utils::generate_jwt_with_claims("keyid", &claims);
+
+        assert_eq!(
+            validator.validate(&configured).await.err(),
+            Some(TokenError::WrongIssuer(String::from("https://example.com")))
+        );
+        assert!(validator.validate(&discovered).await.is_ok());
+    }
+
+    #[tokio::test]
+    /// Tests that `nbf` and `iat` are honored within the leeway, and that an
+    /// authority's maximum token age is enforced.
+    async fn test_validation_checks_token_times() {
+        let validator = JwtValidator::new_with_config(
+            utils::generate_authority("my::aud")
+                .with_leeway(10)
+                .with_max_token_age(Some(3_600)),
+            utils::TestKeySetFetcher::new(utils::generate_keyset("keyid")),
+            Duration::from_secs(0),
+        );
+        let now = now_epoch_secs();
+
+        let mut claims = utils::generate_claims("my::aud", 3_000);
+        claims.nbf = Some(now + 5);
         assert!(validator
-            .validate("not_a_jwt_not_even_close")
+            .validate(&utils::generate_jwt_with_claims("keyid", &claims))
             .await
-            .is_none());
+            .is_ok());
+
+        claims.nbf = Some(now + 60);
+        assert_eq!(
+            validator
+                .validate(&utils::generate_jwt_with_claims("keyid", &claims))
+                .await
+                .err(),
+            Some(TokenError::NotYetValid)
+        );
+
+        let mut claims = utils::generate_claims("my::aud", 3_000);
+        claims.iat = now + 60;
+        assert_eq!(
+            validator
+                .validate(&utils::generate_jwt_with_claims("keyid", &claims))
+                .await
+                .err(),
+            Some(TokenError::IssuedInFuture)
+        );
+
+        claims.iat = now - 2 * 3_600;
+        assert_eq!(
+            validator
+                .validate(&utils::generate_jwt_with_claims("keyid", &claims))
+                .await
+                .err(),
+            Some(TokenError::TooOld)
+        );
     }
 
     mod utils {
-        use std::time::{SystemTime, UNIX_EPOCH};
+        use std::{
+            sync::atomic::AtomicUsize,
+            time::{SystemTime, UNIX_EPOCH},
+        };
 
         use super::*;
 
-        use jsonwebtoken::{encode, EncodingKey, Header};
+        use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+        use ring::{
+            rand::SystemRandom,
+            signature::{EcdsaKeyPair, Ed25519KeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING},
+        };
         use rocket::async_trait;
+        use tokio::sync::Semaphore;
+
+        use crate::auth::openid::{EcCurve, KeyParams, OkpCurve};
         use serde::{Deserialize, Serialize};
 
         pub fn generate_authority(aud: &'static str) -> Authority<TestClaims> {
-            Authority::new("https://example.com", aud)
+            Authority::new(
+                "https://example.com",
+                "https://example.com",
+                vec![String::from(aud)],
+            )
         }
 
         pub fn generate_keyset(thumbprint: &str) -> KeySet {
-            KeySet::with_keys(vec![Key {
-                key_type: String::from("RSA"),
+            KeySet::with_keys(vec![generate_rsa_key(thumbprint)])
+        }
+
+        /// The public half of our test RSA key pair, with no declared `alg` or
+        /// `use`.
+        pub fn generate_rsa_key(thumbprint: &str) -> Key {
+            Key {
                 thumbprint: String::from(thumbprint),
-                modulus: String::from(TEST_RSA_PUB_MODULUS),
-                exponent: String::from(TEST_RSA_PUB_EXPONENT),
-            }])
+                algorithm: None,
+                usage: None,
+                params: KeyParams::Rsa {
+                    modulus: String::from(TEST_RSA_PUB_MODULUS),
+                    exponent: String::from(TEST_RSA_PUB_EXPONENT),
+                },
+            }
         }
 
         pub fn generate_jwt(thumbprint: &str, aud: &'static str, exp_ms: u64) -> String {
-            let mut header = Header::new(Algorithm::RS256);
+            generate_rsa_jwt(thumbprint, aud, exp_ms, Algorithm::RS256)
+        }
+
+        pub fn generate_rsa_jwt(
+            thumbprint: &str,
+            aud: &'static str,
+            exp_ms: u64,
+            algorithm: Algorithm,
+        ) -> String {
+            sign_rsa_jwt(thumbprint, &generate_claims(aud, exp_ms), algorithm)
+        }
+
+        /// An RS256 JWT with the given claims.
+        pub fn generate_jwt_with_claims(thumbprint: &str, claims: &TestClaims) -> String {
+            sign_rsa_jwt(thumbprint, claims, Algorithm::RS256)
+        }
+
+        fn sign_rsa_jwt(thumbprint: &str, claims: &TestClaims, algorithm: Algorithm) -> String {
+            let mut header = Header::new(algorithm);
+            header.kid = Some(String::from(thumbprint));
+
+            let encoding_key = EncodingKey::from_rsa_pem(TEST_RSA_PRIV_KEY.as_bytes())
+                .expect("Failed to load encoding key");
+
+            encode(&header, claims, &encoding_key).expect("Failed to generate token")
+        }
+
+        /// An `HS256` JWT whose HMAC secret is our test RSA public key, as an
+        /// attacker would forge if we let the token pick the algorithm.
+        pub fn generate_key_confused_jwt(thumbprint: &str, aud: &'static str) -> String {
+            let mut header = Header::new(Algorithm::HS256);
             header.kid = Some(String::from(thumbprint));
 
-            let claims = TestClaims {
+            let encoding_key = EncodingKey::from_secret(TEST_RSA_PUB_MODULUS.as_bytes());
+
+            encode(&header, &generate_claims(aud, 3_000), &encoding_key)
+                .expect("Failed to generate token")
+        }
+
+        /// An unsigned JWT with `alg` `none`.
+        pub fn generate_unsigned_jwt(thumbprint: &str, aud: &'static str) -> String {
+            format!(
+                "{}.{}.",
+                encode_part(serde_json::json!({ "alg": "none", "kid": thumbprint })),
+                encode_part(
+                    serde_json::to_value(generate_claims(aud, 3_000))
+                        .expect("Failed to serialize claims")
+                )
+            )
+        }
+
+        /// Generates a fresh P-256 key pair, and returns an ES256 JWT signed
+        /// with it along with the public half as a JWK.
+        pub fn generate_ec_jwt(thumbprint: &str, aud: &'static str) -> (String, Key) {
+            let pkcs8 = EcdsaKeyPair::generate_pkcs8(
+                &ECDSA_P256_SHA256_FIXED_SIGNING,
+                &SystemRandom::new(),
+            )
+            .expect("Failed to generate key pair");
+            let key_pair =
+                EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref())
+                    .expect("Failed to load key pair");
+
+            let mut header = Header::new(Algorithm::ES256);
+            header.kid = Some(String::from(thumbprint));
+
+            let token = encode(
+                &header,
+                &generate_claims(aud, 3_000),
+                &EncodingKey::from_ec_der(pkcs8.as_ref()),
+            )
+            .expect("Failed to generate token");
+
+            // An uncompressed point: 0x04, then x, then y.
+            let point = key_pair.public_key().as_ref();
+            let key = Key {
+                thumbprint: String::from(thumbprint),
+                algorithm: None,
+                usage: None,
+                params: KeyParams::Ec {
+                    curve: EcCurve::P256,
+                    x: base64::encode_config(&point[1..33], base64::URL_SAFE_NO_PAD),
+                    y: base64::encode_config(&point[33..], base64::URL_SAFE_NO_PAD),
+                },
+            };
+
+            (token, key)
+        }
+
+        /// Generates a fresh Ed25519 key pair, and returns an EdDSA JWT signed
+        /// with it along with the public half as a JWK. Encoded by hand, since
+        /// `jsonwebtoken` doesn't support EdDSA.
+        pub fn generate_ed25519_jwt(thumbprint: &str, aud: &'static str) -> (String, Key) {
+            let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+                .expect("Failed to generate key pair");
+            let key_pair =
+                Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).expect("Failed to load key pair");
+
+            let signing_input = format!(
+                "{}.{}",
+                encode_part(serde_json::json!({ "alg": "EdDSA", "kid": thumbprint })),
+                encode_part(
+                    serde_json::to_value(generate_claims(aud, 3_000))
+                        .expect("Failed to serialize claims")
+                )
+            );
+            let signature = key_pair.sign(signing_input.as_bytes());
+
+            let token = format!(
+                "{}.{}",
+                signing_input,
+                base64::encode_config(signature.as_ref(), base64::URL_SAFE_NO_PAD)
+            );
+
+            let key = Key {
+                thumbprint: String::from(thumbprint),
+                algorithm: None,
+                usage: None,
+                params: KeyParams::Okp {
+                    curve: OkpCurve::Ed25519,
+                    x: base64::encode_config(
+                        key_pair.public_key().as_ref(),
+                        base64::URL_SAFE_NO_PAD,
+                    ),
+                },
+            };
+
+            (token, key)
+        }
+
+        fn encode_part(json: serde_json::Value) -> String {
+            base64::encode_config(json.to_string(), base64::URL_SAFE_NO_PAD)
+        }
+
+        pub fn generate_claims(aud: &'static str, exp_ms: u64) -> TestClaims {
+            TestClaims {
+                iss: String::from("https://example.com"),
                 aud: String::from(aud),
                 exp: (SystemTime::now() + Duration::from_millis(exp_ms))
                     .duration_since(UNIX_EPOCH)
                     .expect("Time went backwards!")
                     .as_secs(),
+                iat: now_epoch_secs(),
+                nbf: None,
                 oid: String::from("user_id"),
-            };
-
-            let encoding_key = EncodingKey::from_rsa_pem(TEST_RSA_PRIV_KEY.as_bytes())
-                .expect("Failed to load encoding key");
-
-            encode(&header, &claims, &encoding_key).expect("Failed to generate token")
+            }
         }
 
-        #[derive(Serialize, Deserialize)]
+        #[derive(Clone, Serialize, Deserialize)]
         pub struct TestClaims {
+            pub iss: String,
             aud: String,
-            exp: u64,
+            pub exp: u64,
+            pub iat: i64,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub nbf: Option<i64>,
             oid: String,
         }
 
         impl Claims for TestClaims {
-            fn user_id(self) -> String {
+            fn subject(self) -> String {
                 self.oid
             }
         }
 
         /// This test keyset fetcher will return `first` the first time its
-        /// `fetch` is called, and `rest` for all future calls. Allows validation
-        /// of keyset refresh logic through combinations of keysets prefilled
-        /// by a given test.
+        /// `fetch` is called, and `rest` for all future calls, failing if
+        /// there is no `rest`. Allows validation of keyset refresh logic
+        /// through combinations of keysets prefilled by a given test.
         pub struct TestKeySetFetcher {
             first: KeySet,
-            rest: KeySet,
+            rest: Option<KeySet>,
             fetches: Mutex<usize>,
         }
 
@@ -337,7 +1207,15 @@ mod test {
             pub fn new_with_multiple(first: KeySet, rest: KeySet) -> Self {
                 Self {
                     first,
-                    rest,
+                    rest: Some(rest),
+                    fetches: Mutex::new(0),
+                }
+            }
+
+            pub fn new_then_failing(first: KeySet) -> Self {
+                Self {
+                    first,
+                    rest: None,
                     fetches: Mutex::new(0),
                 }
             }
@@ -356,12 +1234,67 @@ mod test {
 
                 if *fetches == 1 {
                     Ok(self.first.clone())
+                } else {
+                    self.rest.clone().ok_or(())
+                }
+            }
+        }
+
+        /// Returns `first` and then `rest` like `TestKeySetFetcher`, but each
+        /// fetch waits for a permit from `gate`, so tests can hold a refresh
+        /// in flight.
+        pub struct GatedKeySetFetcher {
+            first: KeySet,
+            rest: KeySet,
+            pub gate: Semaphore,
+            pub fetches: AtomicUsize,
+        }
+
+        impl GatedKeySetFetcher {
+            pub fn new(first: KeySet, rest: KeySet) -> Self {
+                Self {
+                    first,
+                    rest,
+                    gate: Semaphore::new(0),
+                    fetches: AtomicUsize::new(0),
+                }
+            }
+        }
+
+        #[async_trait]
+        impl KeySetFetcher for GatedKeySetFetcher {
+            type Error = ();
+
+            async fn fetch<C: Claims>(
+                &self,
+                _authority: &Authority<C>,
+            ) -> Result<KeySet, Self::Error> {
+                let fetches = self.fetches.fetch_add(1, Ordering::SeqCst) + 1;
+                self.gate.acquire().await.forget();
+
+                if fetches == 1 {
+                    Ok(self.first.clone())
                 } else {
                     Ok(self.rest.clone())
                 }
             }
         }
 
+        /// A fetcher whose authority can never be reached.
+        pub struct FailingKeySetFetcher;
+
+        #[async_trait]
+        impl KeySetFetcher for FailingKeySetFetcher {
+            type Error = ();
+
+            async fn fetch<C: Claims>(
+                &self,
+                _authority: &Authority<C>,
+            ) -> Result<KeySet, Self::Error> {
+                Err(())
+            }
+        }
+
         /// These values represent a public/private RSA key pair we can use to encode/decode
         /// test JWTS. The private key is in PEM format, and the public key is represented by
         /// its modulus and exponent values. Together, these are sufficient to encode/decode
@@ -1,12 +1,19 @@
+use std::{fmt::Debug, time::Duration};
+
 use rocket::async_trait;
 use serde::Deserialize;
 use tokio_compat_02::FutureExt;
 
-use super::authority::{Authority, Claims};
+use super::{
+    authority::{Authority, Claims},
+    cache_headers,
+    jwt::SigningAlgorithm,
+};
+use crate::models::common::now_epoch_secs;
 
 #[async_trait]
 pub trait KeySetFetcher {
-    type Error;
+    type Error: Debug;
 
     async fn fetch<C: Claims>(&self, authority: &Authority<C>) -> Result<KeySet, Self::Error>;
 }
@@ -29,40 +36,122 @@ impl KeySetFetcher for NetworkKeySetFetcher {
 
         #[derive(Deserialize)]
         struct Metadata {
+            issuer: Option<String>,
             #[serde(rename(deserialize = "jwks_uri"))]
             key_roster_uri: String,
         }
 
-        let keys_uri = reqwest::get(&authority.metadata_path())
+        let metadata = reqwest::get(&authority.metadata_path())
             .compat() // shim
             .await?
             .json::<Metadata>()
             .compat() // shim
-            .await?
-            .key_roster_uri;
+            .await?;
 
-        reqwest::get(&keys_uri)
+        let response = reqwest::get(&metadata.key_roster_uri)
             .compat() // shim
-            .await?
+            .await?;
+
+        let header = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(String::from)
+        };
+        let max_age = cache_headers::freshness_lifetime(
+            header("cache-control").as_deref(),
+            header("expires").as_deref(),
+            header("date").as_deref(),
+            now_epoch_secs(),
+        );
+
+        let mut key_set = response
             .json::<KeySet>()
             .compat() // shim
-            .await
+            .await?;
+        key_set.max_age = max_age;
+
+        Ok(match metadata.issuer {
+            Some(issuer) => key_set.with_issuer(issuer),
+            None => key_set,
+        })
     }
 }
 
-#[derive(Clone, Deserialize)]
+/// An authority's JSON Web Key Set. Keys we can't use, e.g. of an unknown
+/// type or on an unsupported curve, are skipped rather than failing the
+/// whole set, since authorities can publish new kinds of key at any time.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(from = "RawKeySet")]
 pub struct KeySet {
     keys: Vec<Key>,
+    /// The issuer the authority's discovery document names, which tokens
+    /// signed with these keys must carry. MSA's can be templated.
+    issuer: Option<String>,
+    /// How long the authority says the set may be cached for.
+    max_age: Option<Duration>,
+}
+
+#[derive(Deserialize)]
+struct RawKeySet {
+    keys: Vec<serde_json::Value>,
+}
+
+impl From<RawKeySet> for KeySet {
+    fn from(raw: RawKeySet) -> Self {
+        Self {
+            keys: raw
+                .keys
+                .into_iter()
+                .filter_map(|key| serde_json::from_value(key).ok())
+                .collect(),
+            issuer: None,
+            max_age: None,
+        }
+    }
 }
 
 impl KeySet {
     pub fn empty() -> Self {
-        Self { keys: vec![] }
+        Self {
+            keys: vec![],
+            issuer: None,
+            max_age: None,
+        }
     }
 
     #[cfg(test)]
     pub fn with_keys(keys: Vec<Key>) -> Self {
-        Self { keys }
+        Self {
+            keys,
+            issuer: None,
+            max_age: None,
+        }
+    }
+
+    pub fn with_issuer(mut self, issuer: String) -> Self {
+        self.issuer = Some(issuer);
+
+        self
+    }
+
+    pub fn issuer(&self) -> Option<&str> {
+        self.issuer.as_deref()
+    }
+
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+
+        self
+    }
+
+    pub fn max_age(&self) -> Option<Duration> {
+        self.max_age
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
     }
 
     pub fn key_with_thumbprint(&self, thumbprint: &str) -> Option<Key> {
@@ -73,18 +162,131 @@ impl KeySet {
     }
 }
 
-#[derive(Clone, Deserialize)]
+/// A JSON Web Key.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
 pub struct Key {
-    #[serde(rename(deserialize = "kty"))]
-    /// Expected to always be "RSA".
-    pub key_type: String,
-
     #[serde(rename(deserialize = "kid"))]
     pub thumbprint: String,
 
-    #[serde(rename(deserialize = "n"))]
-    pub modulus: String,
+    /// The only algorithm the key may be used with, if the authority says.
+    #[serde(rename(deserialize = "alg"), default)]
+    pub algorithm: Option<SigningAlgorithm>,
 
-    #[serde(rename(deserialize = "e"))]
-    pub exponent: String,
+    /// What the key may be used for, if the authority says.
+    #[serde(rename(deserialize = "use"), default)]
+    pub usage: Option<KeyUse>,
+
+    #[serde(flatten)]
+    pub params: KeyParams,
+}
+
+/// A key's type-specific parameters, for each of the types we can verify
+/// signatures with.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "kty")]
+pub enum KeyParams {
+    #[serde(rename = "RSA")]
+    Rsa {
+        #[serde(rename(deserialize = "n"))]
+        modulus: String,
+
+        #[serde(rename(deserialize = "e"))]
+        exponent: String,
+    },
+
+    /// An elliptic curve key, for ECDSA.
+    #[serde(rename = "EC")]
+    Ec {
+        #[serde(rename(deserialize = "crv"))]
+        curve: EcCurve,
+
+        x: String,
+        y: String,
+    },
+
+    /// An octet key pair, for EdDSA.
+    #[serde(rename = "OKP")]
+    Okp {
+        #[serde(rename(deserialize = "crv"))]
+        curve: OkpCurve,
+
+        x: String,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyUse {
+    /// For verifying signatures.
+    Sig,
+    /// For encryption, which we never do with an authority's keys.
+    Enc,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub enum EcCurve {
+    #[serde(rename = "P-256")]
+    P256,
+    #[serde(rename = "P-384")]
+    P384,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub enum OkpCurve {
+    Ed25519,
+}
+
+impl Key {
+    /// Whether this key may verify a signature made with `algorithm`: it
+    /// must be meant for signatures, and for that algorithm if it says which.
+    /// Whether its type suits `algorithm` is checked when verifying.
+    pub fn is_usable_with(&self, algorithm: SigningAlgorithm) -> bool {
+        let usable_for_signatures = match self.usage {
+            Some(KeyUse::Sig) | None => true,
+            Some(KeyUse::Enc) => false,
+        };
+
+        let usable_with_algorithm = match self.algorithm {
+            Some(declared) => declared == algorithm,
+            None => true,
+        };
+
+        usable_for_signatures && usable_with_algorithm
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    /// Tests that each supported key type parses, and that unsupported keys
+    /// are skipped without failing the rest of the set.
+    fn test_parses_supported_keys_and_skips_the_rest() {
+        let key_set: KeySet = serde_json::from_value(json!({
+            "keys": [
+                { "kty": "RSA", "kid": "rsa", "n": "AQAB", "e": "AQAB" },
+                { "kty": "RSA", "kid": "rs256", "alg": "RS256", "use": "sig", "n": "AQAB", "e": "AQAB" },
+                { "kty": "EC", "kid": "p256", "crv": "P-256", "x": "AA", "y": "AA" },
+                { "kty": "EC", "kid": "p384", "crv": "P-384", "x": "AA", "y": "AA" },
+                { "kty": "OKP", "kid": "ed25519", "crv": "Ed25519", "x": "AA" },
+                { "kty": "EC", "kid": "secp256k1", "crv": "secp256k1", "x": "AA", "y": "AA" },
+                { "kty": "OKP", "kid": "x25519", "crv": "X25519", "x": "AA" },
+                { "kty": "oct", "kid": "oct", "k": "AA" },
+                { "kty": "RSA", "n": "AQAB", "e": "AQAB" },
+                { "kty": "RSA", "kid": "oaep", "alg": "RSA-OAEP", "use": "enc", "n": "AQAB", "e": "AQAB" }
+            ]
+        }))
+        .expect("Failed to parse key set");
+
+        let thumbprints = key_set
+            .keys
+            .iter()
+            .map(|key| key.thumbprint.as_str())
+            .collect::<Vec<_>>();
+
+        assert_eq!(thumbprints, vec!["rsa", "rs256", "p256", "p384", "ed25519"]);
+    }
 }
@@ -0,0 +1,289 @@
+use std::{
+    convert::Infallible,
+    io,
+    path::PathBuf,
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+
+use ring::digest::{self, SHA256};
+use rocket::async_trait;
+
+use super::{
+    authority::{Authority, Claims},
+    key_set::{KeySet, KeySetFetcher},
+};
+
+/// Reads an authority's keys from a local JWKS file, for deployments that
+/// can't reach the authority. The file is checked for changes each time keys
+/// are refreshed, and re-parsed if it has.
+pub struct FileKeySetFetcher {
+    path: PathBuf,
+    /// The keys as last read, and the SHA-256 of the file they were read
+    /// from. Its contents are compared, rather than when it was modified,
+    /// which is too coarse to catch every change.
+    last_read: Mutex<Option<(Vec<u8>, KeySet)>>,
+}
+
+#[derive(Debug)]
+pub enum FileKeySetError {
+    Io(io::Error),
+    /// The file isn't a JWKS.
+    Parse(serde_json::Error),
+}
+
+impl FileKeySetFetcher {
+    /// How often to check the file for changes, unless the authority's
+    /// refresh interval is configured.
+    pub const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            last_read: Mutex::new(None),
+        }
+    }
+
+    fn lock_last_read(&self) -> MutexGuard<'_, Option<(Vec<u8>, KeySet)>> {
+        // Only ever replaced whole, so a panic can't leave it half-updated.
+        self.last_read
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[async_trait]
+impl KeySetFetcher for FileKeySetFetcher {
+    type Error = FileKeySetError;
+
+    async fn fetch<C: Claims>(&self, _authority: &Authority<C>) -> Result<KeySet, Self::Error> {
+        let json = tokio::fs::read(&self.path)
+            .await
+            .map_err(FileKeySetError::Io)?;
+        let hash = digest::digest(&SHA256, &json).as_ref().to_vec();
+
+        let unchanged = self
+            .lock_last_read()
+            .as_ref()
+            .filter(|(last_hash, _)| *last_hash == hash)
+            .map(|(_, key_set)| key_set.clone());
+
+        if let Some(key_set) = unchanged {
+            return Ok(key_set);
+        }
+
+        let key_set = serde_json::from_slice::<KeySet>(&json)
+            .map_err(FileKeySetError::Parse)?
+            .with_max_age(FileKeySetFetcher::POLL_INTERVAL);
+
+        *self.lock_last_read() = Some((hash, key_set.clone()));
+
+        Ok(key_set)
+    }
+}
+
+/// Serves keys given up front, e.g. inline in config, for authorities whose
+/// keys are pinned.
+pub struct StaticKeySetFetcher {
+    key_set: KeySet,
+}
+
+impl StaticKeySetFetcher {
+    pub fn new(key_set: KeySet) -> Self {
+        Self { key_set }
+    }
+}
+
+#[async_trait]
+impl KeySetFetcher for StaticKeySetFetcher {
+    type Error = Infallible;
+
+    async fn fetch<C: Claims>(&self, _authority: &Authority<C>) -> Result<KeySet, Self::Error> {
+        Ok(self.key_set.clone())
+    }
+}
+
+/// Fetches keys with `primary`, falling back to `fallback` when that fails,
+/// e.g. from the network to a pinned file, so tokens can still be validated
+/// while the authority is unreachable. Keys from `fallback` are only kept
+/// until the next refresh, which tries `primary` again.
+pub struct FallbackKeySetFetcher<P, F> {
+    primary: P,
+    fallback: F,
+}
+
+/// Why both of a `FallbackKeySetFetcher`'s fetchers failed.
+#[derive(Debug)]
+pub struct FallbackKeySetError<P, F> {
+    pub primary: P,
+    pub fallback: F,
+}
+
+impl<P, F> FallbackKeySetFetcher<P, F> {
+    pub fn new(primary: P, fallback: F) -> Self {
+        Self { primary, fallback }
+    }
+}
+
+#[async_trait]
+impl<P, F> KeySetFetcher for FallbackKeySetFetcher<P, F>
+where
+    P: KeySetFetcher + Sync,
+    P::Error: Send,
+    F: KeySetFetcher + Sync,
+{
+    type Error = FallbackKeySetError<P::Error, F::Error>;
+
+    async fn fetch<C: Claims>(&self, authority: &Authority<C>) -> Result<KeySet, Self::Error> {
+        let primary = match self.primary.fetch(authority).await {
+            Ok(key_set) => return Ok(key_set),
+            Err(primary) => primary,
+        };
+
+        eprintln!(
+            "Failed to fetch keys for {}, falling back: {:?}",
+            authority.issuer(),
+            primary
+        );
+
+        self.fallback
+            .fetch(authority)
+            .await
+            .map_err(|fallback| FallbackKeySetError { primary, fallback })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::{env, fs};
+
+    use serde_json::json;
+
+    use crate::auth::openid::OidcClaims;
+
+    #[tokio::test]
+    /// Tests that the file is only re-read once it changes, and that a file
+    /// that isn't a JWKS fails the fetch.
+    async fn test_file_reloaded_on_change() {
+        let path = utils::temp_path("jwks");
+        let fetcher = FileKeySetFetcher::new(path.clone());
+        let authority = utils::authority();
+
+        utils::write_key_set(&path, "first");
+        let first = fetcher.fetch(&authority).await.expect("Failed to fetch");
+        assert!(first.key_with_thumbprint("first").is_some());
+        assert_eq!(first.max_age(), Some(FileKeySetFetcher::POLL_INTERVAL));
+
+        utils::write_key_set(&path, "second-key");
+        let second = fetcher.fetch(&authority).await.expect("Failed to fetch");
+        assert!(second.key_with_thumbprint("first").is_none());
+        assert!(second.key_with_thumbprint("second-key").is_some());
+
+        fs::write(&path, "not a key set").expect("Failed to write file");
+        assert!(matches!(
+            fetcher.fetch(&authority).await,
+            Err(FileKeySetError::Parse(_))
+        ));
+
+        fs::remove_file(&path).expect("Failed to remove file");
+        assert!(matches!(
+            fetcher.fetch(&authority).await,
+            Err(FileKeySetError::Io(_))
+        ));
+    }
+
+    #[tokio::test]
+    /// Tests that a rewrite keeping the file's length, which can also keep
+    /// its modification time, is still picked up.
+    async fn test_file_reloaded_on_same_length_change() {
+        let path = utils::temp_path("jwks");
+        let fetcher = FileKeySetFetcher::new(path.clone());
+        let authority = utils::authority();
+
+        utils::write_key_set(&path, "key-one");
+        let first = fetcher.fetch(&authority).await.expect("Failed to fetch");
+        assert!(first.key_with_thumbprint("key-one").is_some());
+
+        utils::write_key_set(&path, "key-two");
+        let second = fetcher.fetch(&authority).await.expect("Failed to fetch");
+        assert!(second.key_with_thumbprint("key-one").is_none());
+        assert!(second.key_with_thumbprint("key-two").is_some());
+
+        fs::remove_file(&path).expect("Failed to remove file");
+    }
+
+    #[tokio::test]
+    /// Tests that the fallback is only used when the primary fetcher fails,
+    /// and that both failures are reported if it fails too.
+    async fn test_fallback() {
+        let authority = utils::authority();
+        let pinned = StaticKeySetFetcher::new(utils::key_set("pinned"));
+
+        let working = FallbackKeySetFetcher::new(
+            StaticKeySetFetcher::new(utils::key_set("live")),
+            StaticKeySetFetcher::new(utils::key_set("pinned")),
+        );
+        let key_set = working.fetch(&authority).await.expect("Failed to fetch");
+        assert!(key_set.key_with_thumbprint("live").is_some());
+
+        let unreachable =
+            FallbackKeySetFetcher::new(FileKeySetFetcher::new(utils::temp_path("missing")), pinned);
+        let key_set = unreachable
+            .fetch(&authority)
+            .await
+            .expect("Failed to fall back");
+        assert!(key_set.key_with_thumbprint("pinned").is_some());
+
+        let broken = FallbackKeySetFetcher::new(
+            FileKeySetFetcher::new(utils::temp_path("missing")),
+            FileKeySetFetcher::new(utils::temp_path("missing")),
+        );
+        assert!(matches!(
+            broken.fetch(&authority).await,
+            Err(FallbackKeySetError {
+                primary: FileKeySetError::Io(_),
+                fallback: FileKeySetError::Io(_),
+            })
+        ));
+    }
+
+    mod utils {
+        use super::*;
+
+        use ring::rand::{SecureRandom, SystemRandom};
+
+        pub fn authority() -> Authority<OidcClaims> {
+            Authority::new(
+                "https://example.com",
+                "https://example.com",
+                vec![String::from("aud")],
+            )
+        }
+
+        pub fn key_set(thumbprint: &str) -> KeySet {
+            serde_json::from_value(key_set_json(thumbprint)).expect("Failed to parse key set")
+        }
+
+        pub fn write_key_set(path: &PathBuf, thumbprint: &str) {
+            fs::write(path, key_set_json(thumbprint).to_string()).expect("Failed to write file");
+        }
+
+        /// A path in the temp directory no other test uses.
+        pub fn temp_path(name: &str) -> PathBuf {
+            let mut suffix = [0u8; 8];
+            SystemRandom::new()
+                .fill(&mut suffix)
+                .expect("Failed to generate random bytes");
+
+            env::temp_dir().join(format!("sonar-{}-{}.json", name, hex::encode(suffix)))
+        }
+
+        fn key_set_json(thumbprint: &str) -> serde_json::Value {
+            json!({
+                "keys": [{ "kty": "RSA", "kid": thumbprint, "n": "AQAB", "e": "AQAB" }]
+            })
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use ring::{
+    rand::SystemRandom,
+    signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING},
+};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use super::{config::AuthorityConfig, jwt::SigningAlgorithm, UserIdClaim};
+
+/// A stand-in OpenID provider that signs ID tokens for whoever asks, so
+/// sign-in can be developed and tested end-to-end without a real authority or
+/// the network. Only built with the `mock-oidc` feature; see the README.
+pub struct MockProvider {
+    issuer: String,
+    /// The PKCS #8 document of the P-256 key tokens are signed with.
+    pkcs8: Vec<u8>,
+    /// The public half of the signing key, as an uncompressed point.
+    public_key: Vec<u8>,
+}
+
+/// What to mint an ID token with.
+#[derive(Deserialize)]
+pub struct MintRequest {
+    /// The user the token is for. Also their `oid`, unless `claims` says
+    /// otherwise, so the token works whichever claim identifies users.
+    pub sub: String,
+    /// Defaults to `MockProvider::AUDIENCE`.
+    pub aud: Option<String>,
+    /// How long the token lasts. Defaults to an hour.
+    pub ttl_secs: Option<i64>,
+    /// Any other claims, which override ours, e.g. an `exp` in the past.
+    #[serde(flatten)]
+    pub claims: Map<String, Value>,
+}
+
+impl MockProvider {
+    /// Where the provider is mounted, beneath the server's own address.
+    pub const MOUNT_PATH: &'static str = "/mock-oidc";
+    /// The audience tokens are minted for, unless asked otherwise.
+    pub const AUDIENCE: &'static str = "sonar-dev";
+    const KEY_ID: &'static str = "mock";
+    const DEFAULT_TOKEN_TTL_SECS: i64 = 60 * 60;
+
+    /// A provider with a freshly generated signing key, mounted beneath
+    /// `base_url`, e.g. `http://localhost:8000`.
+    pub fn new(base_url: &str) -> Self {
+        let pkcs8 =
+            EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &SystemRandom::new())
+                .expect("Failed to generate mock provider key");
+        let key_pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref())
+            .expect("Failed to load mock provider key");
+
+        Self {
+            issuer: format!(
+                "{}{}",
+                base_url.trim_end_matches('/'),
+                MockProvider::MOUNT_PATH
+            ),
+            pkcs8: pkcs8.as_ref().to_vec(),
+            public_key: key_pair.public_key().as_ref().to_vec(),
+        }
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    /// An authority that trusts this provider's tokens, fetching its keys
+    /// over the network like any other's.
+    pub fn authority_config(&self) -> AuthorityConfig {
+        AuthorityConfig {
+            issuer: self.issuer.clone(),
+            domain: None,
+            audiences: vec![String::from(MockProvider::AUDIENCE)],
+            user_id_claim: UserIdClaim::Sub,
+            algorithms: Some(vec![SigningAlgorithm::ES256]),
+            // The first fetch happens before the server is listening, so it
+            // fails; retry soon after.
+            refresh_interval_secs: Some(1),
+            leeway_secs: None,
+            max_token_age_secs: None,
+            token_cache_capacity: None,
+            key_source: None,
+        }
+    }
+
+    /// The provider's `.well-known/openid-configuration`.
+    pub fn discovery_document(&self) -> Value {
+        json!({
+            "issuer": self.issuer,
+            "jwks_uri": format!("{}/jwks", self.issuer),
+            "token_endpoint": format!("{}/token", self.issuer),
+            "response_types_supported": ["id_token"],
+            "subject_types_supported": ["public"],
+            "id_token_signing_alg_values_supported": ["ES256"]
+        })
+    }
+
+    /// The provider's JSON Web Key Set, with its one public key.
+    pub fn key_set(&self) -> Value {
+        let encode_coordinate =
+            |coordinate: &[u8]| base64::encode_config(coordinate, base64::URL_SAFE_NO_PAD);
+
+        // An uncompressed point: 0x04, then x, then y.
+        json!({
+            "keys": [{
+                "kty": "EC",
+                "kid": MockProvider::KEY_ID,
+                "use": "sig",
+                "alg": "ES256",
+                "crv": "P-256",
+                "x": encode_coordinate(&self.public_key[1..33]),
+                "y": encode_coordinate(&self.public_key[33..])
+            }]
+        })
+    }
+
+    /// Mints and signs an ID token as `request` asks, issued at `now`.
+    pub fn mint(&self, request: MintRequest, now: i64) -> String {
+        let ttl_secs = request
+            .ttl_secs
+            .unwrap_or(MockProvider::DEFAULT_TOKEN_TTL_SECS);
+        let aud = request
+            .aud
+            .unwrap_or_else(|| String::from(MockProvider::AUDIENCE));
+
+        let mut claims = Map::new();
+        claims.insert(String::from("iss"), json!(self.issuer));
+        claims.insert(String::from("sub"), json!(request.sub));
+        claims.insert(String::from("oid"), json!(request.sub));
+        claims.insert(String::from("aud"), json!(aud));
+        claims.insert(String::from("iat"), json!(now));
+        claims.insert(String::from("nbf"), json!(now));
+        claims.insert(String::from("exp"), json!(now + ttl_secs));
+        claims.extend(request.claims);
+
+        let mut header = Header::new(Algorithm::ES256);
+        header.kid = Some(String::from(MockProvider::KEY_ID));
+
+        encode(&header, &claims, &EncodingKey::from_ec_der(&self.pkcs8))
+            .expect("Failed to sign mock token")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use jsonwebtoken::{decode, DecodingKey, Validation};
+
+    use crate::{
+        auth::openid::{AuthConfig, KeySet},
+        models::common::now_epoch_secs,
+    };
+
+    #[test]
+    /// Tests that minted tokens verify with the provider's key, carry its
+    /// issuer and the defaults, and that extra claims override them.
+    fn test_mint() {
+        let provider = MockProvider::new("http://localhost:8000/");
+        let now = now_epoch_secs();
+
+        let request: MintRequest = serde_json::from_value(json!({
+            "sub": "alice",
+            "oid": "legacy-alice",
+            "email": "alice@example.com"
+        }))
+        .expect("Failed to parse request");
+        let token = provider.mint(request, now);
+
+        let mut validation = Validation::new(Algorithm::ES256);
+        validation.set_audience(&[MockProvider::AUDIENCE]);
+        let claims = decode::<Map<String, Value>>(
+            &token,
+            &DecodingKey::from_ec_der(&provider.public_key),
+            &validation,
+        )
+        .expect("Failed to verify token")
+        .claims;
+
+        assert_eq!(claims["iss"], json!("http://localhost:8000/mock-oidc"));
+        assert_eq!(claims["sub"], json!("alice"));
+        assert_eq!(claims["oid"], json!("legacy-alice"));
+        assert_eq!(claims["email"], json!("alice@example.com"));
+        assert_eq!(
+            claims["exp"],
+            json!(now + MockProvider::DEFAULT_TOKEN_TTL_SECS)
+        );
+    }
+
+    #[test]
+    /// Tests that the provider's key set parses, and the authority trusting
+    /// it is valid config.
+    fn test_key_set_and_authority() {
+        let provider = MockProvider::new("http://localhost:8000");
+
+        let key_set: KeySet =
+            serde_json::from_value(provider.key_set()).expect("Failed to parse key set");
+        assert!(key_set.key_with_thumbprint(MockProvider::KEY_ID).is_some());
+
+        let config = AuthConfig {
+            authorities: vec![provider.authority_config()],
+            session: Default::default(),
+        };
+        assert_eq!(config.validate(), Ok(()));
+    }
+}
@@ -1,7 +1,30 @@
 mod authority;
+mod cache_headers;
+mod config;
+mod jwt;
 mod jwt_validator;
 mod key_set;
+mod key_set_fetchers;
+#[cfg(feature = "mock-oidc")]
+mod mock_provider;
+mod registered_claims;
+mod token_cache;
+mod token_error;
+mod validator_registry;
 
-pub use authority::{Authority, Claims, MSAClaims};
-pub use jwt_validator::{JwtValidator, MSAJwtValidator};
-pub use key_set::{Key, KeySet, KeySetFetcher, NetworkKeySetFetcher};
+pub use authority::{Authority, Claims, MSAClaims, OidcClaims};
+pub use config::{AuthConfig, AuthConfigError, AuthorityConfig, KeySource, UserIdClaim};
+pub use jwt::{issued_at, SigningAlgorithm};
+pub use jwt_validator::JwtValidator;
+pub use key_set::{
+    EcCurve, Key, KeyParams, KeySet, KeySetFetcher, KeyUse, NetworkKeySetFetcher, OkpCurve,
+};
+pub use key_set_fetchers::{
+    FallbackKeySetError, FallbackKeySetFetcher, FileKeySetError, FileKeySetFetcher,
+    StaticKeySetFetcher,
+};
+#[cfg(feature = "mock-oidc")]
+pub use mock_provider::{MintRequest, MockProvider};
+pub use token_cache::TokenCacheStats;
+pub use token_error::TokenError;
+pub use validator_registry::{ProviderIdentity, TokenValidator, ValidatorRegistry};
@@ -0,0 +1,260 @@
+use serde::Deserialize;
+
+use super::token_error::TokenError;
+
+/// The placeholder MSA's multi-tenant discovery documents put in their
+/// `issuer`, to be filled in by each token's `tid`.
+const TENANT_ID_PLACEHOLDER: &str = "{tenantid}";
+
+/// Other forms of an issuer that its tokens may carry in `iss`, keyed by the
+/// issuer from its discovery document. Google's ID tokens sometimes leave
+/// the scheme off.
+const ISSUER_ALIASES: &[(&str, &str)] = &[("https://accounts.google.com", "accounts.google.com")];
+
+/// The registered claims we check ourselves, on top of the audience and
+/// expiry checks `jsonwebtoken` makes.
+#[derive(Clone, Deserialize)]
+pub struct RegisteredClaims {
+    iss: Option<String>,
+    /// MSA's tenant ID.
+    tid: Option<String>,
+    exp: Option<i64>,
+    nbf: Option<i64>,
+    iat: Option<i64>,
+}
+
+/// What to check the registered claims against.
+pub struct ClaimsPolicy<'a> {
+    /// The issuer tokens must come from, possibly containing `{tenantid}`.
+    pub issuer: &'a str,
+    /// How far, in seconds, clocks may disagree.
+    pub leeway: i64,
+    /// How long after being issued a token is accepted for, in seconds, if
+    /// that should be shorter than the token's own lifetime.
+    pub max_age: Option<i64>,
+}
+
+/// How an issuer matched an expected one.
+#[derive(Debug, PartialEq)]
+pub enum IssuerMatch<'a> {
+    Exact,
+    /// The expected issuer was templated, and `tenant_id` filled it in.
+    Templated {
+        tenant_id: &'a str,
+    },
+}
+
+/// Matches `iss` against `expected`, which may contain a `{tenantid}`
+/// placeholder standing for a single path segment, or against one of
+/// `expected`'s aliases.
+pub fn match_issuer<'a>(expected: &str, iss: &'a str) -> Option<IssuerMatch<'a>> {
+    if expected == iss || ISSUER_ALIASES.contains(&(expected, iss)) {
+        return Some(IssuerMatch::Exact);
+    }
+
+    let mut parts = expected.splitn(2, TENANT_ID_PLACEHOLDER);
+    let (prefix, suffix) = (parts.next()?, parts.next()?);
+
+    if iss.len() < prefix.len() + suffix.len() || !iss.starts_with(prefix) || !iss.ends_with(suffix)
+    {
+        return None;
+    }
+
+    let tenant_id = &iss[prefix.len()..iss.len() - suffix.len()];
+
+    if tenant_id.is_empty() || tenant_id.contains('/') {
+        None
+    } else {
+        Some(IssuerMatch::Templated { tenant_id })
+    }
+}
+
+impl RegisteredClaims {
+    pub fn exp(&self) -> Option<i64> {
+        self.exp
+    }
+
+    /// Checks the claims against `policy` as of `now`, in epoch-seconds.
+    pub fn check(&self, policy: &ClaimsPolicy, now: i64) -> Result<(), TokenError> {
+        let iss = self.iss.as_deref().ok_or(TokenError::MissingClaim("iss"))?;
+
+        match match_issuer(policy.issuer, iss) {
+            Some(IssuerMatch::Exact) => {}
+            // A templated issuer only stands for the tenant the token says it's from.
+            Some(IssuerMatch::Templated { tenant_id })
+                if self.tid.as_deref() == Some(tenant_id) => {}
+            _ => return Err(TokenError::WrongIssuer(String::from(iss))),
+        }
+
+        if let Some(nbf) = self.nbf {
+            if nbf > now + policy.leeway {
+                return Err(TokenError::NotYetValid);
+            }
+        }
+
+        if let Some(iat) = self.iat {
+            if iat > now + policy.leeway {
+                return Err(TokenError::IssuedInFuture);
+            }
+        }
+
+        if let Some(max_age) = policy.max_age {
+            let iat = self.iat.ok_or(TokenError::MissingClaim("iat"))?;
+
+            if now - iat > max_age + policy.leeway {
+                return Err(TokenError::TooOld);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ISSUER: &str = "https://issuer.example.com";
+    const MSA_TEMPLATE: &str = "https://login.microsoftonline.com/{tenantid}/v2.0";
+
+    #[test]
+    /// Tests exact, aliased and templated issuer matching.
+    fn test_match_issuer() {
+        assert_eq!(match_issuer(ISSUER, ISSUER), Some(IssuerMatch::Exact));
+        assert_eq!(match_issuer(ISSUER, "https://evil.example.com"), None);
+        assert_eq!(
+            match_issuer("https://accounts.google.com", "accounts.google.com"),
+            Some(IssuerMatch::Exact)
+        );
+        assert_eq!(
+            match_issuer("accounts.google.com", "https://accounts.google.com"),
+            None
+        );
+        assert_eq!(
+            match_issuer(
+                MSA_TEMPLATE,
+                "https://login.microsoftonline.com/tenant/v2.0"
+            ),
+            Some(IssuerMatch::Templated {
+                tenant_id: "tenant"
+            })
+        );
+        assert_eq!(
+            match_issuer(MSA_TEMPLATE, "https://login.microsoftonline.com//v2.0"),
+            None
+        );
+        assert_eq!(
+            match_issuer(MSA_TEMPLATE, "https://login.microsoftonline.com/a/b/v2.0"),
+            None
+        );
+        assert_eq!(
+            match_issuer(MSA_TEMPLATE, "https://evil.example.com/tenant/v2.0"),
+            None
+        );
+    }
+
+    #[test]
+    /// Tests that the issuer must be present and match, and that a templated
+    /// issuer must be filled in by the token's own `tid`.
+    fn test_issuer_checks() {
+        let policy = utils::policy(ISSUER, None);
+
+        assert_eq!(
+            utils::claims(Some(ISSUER), None, None, None).check(&policy, 0),
+            Ok(())
+        );
+        assert_eq!(
+            utils::claims(None, None, None, None).check(&policy, 0),
+            Err(TokenError::MissingClaim("iss"))
+        );
+        assert_eq!(
+            utils::claims(Some("https://evil.example.com"), None, None, None).check(&policy, 0),
+            Err(TokenError::WrongIssuer(String::from(
+                "https://evil.example.com"
+            )))
+        );
+
+        let policy = utils::policy(MSA_TEMPLATE, None);
+        let iss = "https://login.microsoftonline.com/tenant/v2.0";
+
+        assert_eq!(
+            utils::claims(Some(iss), Some("tenant"), None, None).check(&policy, 0),
+            Ok(())
+        );
+        assert_eq!(
+            utils::claims(Some(iss), Some("other"), None, None).check(&policy, 0),
+            Err(TokenError::WrongIssuer(String::from(iss)))
+        );
+        assert_eq!(
+            utils::claims(Some(iss), None, None, None).check(&policy, 0),
+            Err(TokenError::WrongIssuer(String::from(iss)))
+        );
+    }
+
+    #[test]
+    /// Tests that `nbf` and `iat` may only be in the future by the leeway.
+    fn test_time_checks_honor_leeway() {
+        let policy = utils::policy(ISSUER, None);
+        let now = 1_000;
+
+        assert_eq!(
+            utils::claims(Some(ISSUER), None, Some(now + 60), Some(now + 60)).check(&policy, now),
+            Ok(())
+        );
+        assert_eq!(
+            utils::claims(Some(ISSUER), None, Some(now + 61), None).check(&policy, now),
+            Err(TokenError::NotYetValid)
+        );
+        assert_eq!(
+            utils::claims(Some(ISSUER), None, None, Some(now + 61)).check(&policy, now),
+            Err(TokenError::IssuedInFuture)
+        );
+    }
+
+    #[test]
+    /// Tests that a maximum age rejects old tokens, and those with no `iat`.
+    fn test_max_age() {
+        let policy = utils::policy(ISSUER, Some(3_600));
+        let now = 10_000;
+
+        assert_eq!(
+            utils::claims(Some(ISSUER), None, None, Some(now - 3_660)).check(&policy, now),
+            Ok(())
+        );
+        assert_eq!(
+            utils::claims(Some(ISSUER), None, None, Some(now - 3_661)).check(&policy, now),
+            Err(TokenError::TooOld)
+        );
+        assert_eq!(
+            utils::claims(Some(ISSUER), None, None, None).check(&policy, now),
+            Err(TokenError::MissingClaim("iat"))
+        );
+    }
+
+    mod utils {
+        use super::*;
+
+        pub fn policy(issuer: &str, max_age: Option<i64>) -> ClaimsPolicy<'_> {
+            ClaimsPolicy {
+                issuer,
+                leeway: 60,
+                max_age,
+            }
+        }
+
+        pub fn claims(
+            iss: Option<&str>,
+            tid: Option<&str>,
+            nbf: Option<i64>,
+            iat: Option<i64>,
+        ) -> RegisteredClaims {
+            RegisteredClaims {
+                iss: iss.map(String::from),
+                tid: tid.map(String::from),
+                exp: None,
+                nbf,
+                iat,
+            }
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use std::collections::{BTreeMap, HashMap};
+
+use ring::digest::{digest, SHA256};
+
+use super::registered_claims::RegisteredClaims;
+
+/// A SHA-256 of a token, so the cache never holds tokens themselves.
+pub type TokenHash = [u8; 32];
+
+pub fn hash_token(jwt: &str) -> TokenHash {
+    let mut hash = [0; 32];
+    hash.copy_from_slice(digest(&SHA256, jwt.as_bytes()).as_ref());
+
+    hash
+}
+
+/// A token we've verified, and what we need to accept it again without
+/// redoing the signature check.
+#[derive(Clone)]
+pub struct VerifiedToken<C> {
+    pub claims: C,
+    /// Re-checked on every hit, since e.g. a maximum age can lapse before
+    /// the token expires.
+    pub registered: RegisteredClaims,
+    /// The key that verified the token, so the entry can be dropped if the
+    /// authority rotates it out.
+    pub thumbprint: String,
+    /// When to stop accepting the token, in epoch-seconds.
+    pub expires_at: i64,
+}
+
+/// A bounded cache of verified tokens, evicting the least recently used.
+pub struct TokenCache<C> {
+    capacity: usize,
+    entries: HashMap<TokenHash, (VerifiedToken<C>, u64)>,
+    /// Entries' hashes, by when they were last used.
+    recency: BTreeMap<u64, TokenHash>,
+    next_use: u64,
+    hits: u64,
+    misses: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TokenCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+}
+
+impl<C: Clone> TokenCache<C> {
+    /// A cache of up to `capacity` tokens. A capacity of zero caches nothing.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            next_use: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Looks up a token as of `now`, in epoch-seconds. An expired entry is
+    /// dropped and counts as a miss.
+    pub fn get(&mut self, hash: &TokenHash, now: i64) -> Option<VerifiedToken<C>> {
+        let use_id = self.next_use;
+
+        let verified = match self.entries.get_mut(hash) {
+            Some((verified, last_used)) if verified.expires_at >= now => {
+                self.recency.remove(last_used);
+                self.recency.insert(use_id, *hash);
+                *last_used = use_id;
+
+                Some(verified.clone())
+            }
+            Some(_) => {
+                self.remove(hash);
+                None
+            }
+            None => None,
+        };
+
+        self.next_use += 1;
+
+        match verified {
+            Some(_) => self.hits += 1,
+            None => self.misses += 1,
+        }
+
+        verified
+    }
+
+    pub fn insert(&mut self, hash: TokenHash, verified: VerifiedToken<C>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        self.remove(&hash);
+
+        while self.entries.len() >= self.capacity {
+            let least_recent = match self.recency.values().next() {
+                Some(least_recent) => *least_recent,
+                None => break,
+            };
+
+            self.remove(&least_recent);
+        }
+
+        self.recency.insert(self.next_use, hash);
+        self.entries.insert(hash, (verified, self.next_use));
+        self.next_use += 1;
+    }
+
+    /// Drops every token verified with a key `keep` says no longer holds.
+    pub fn retain_thumbprints<K: Fn(&str) -> bool>(&mut self, keep: K) {
+        let dropped = self
+            .entries
+            .iter()
+            .filter(|(_, (verified, _))| !keep(&verified.thumbprint))
+            .map(|(hash, _)| *hash)
+            .collect::<Vec<_>>();
+
+        for hash in dropped {
+            self.remove(&hash);
+        }
+    }
+
+    pub fn stats(&self) -> TokenCacheStats {
+        TokenCacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            entries: self.entries.len(),
+        }
+    }
+
+    fn remove(&mut self, hash: &TokenHash) {
+        if let Some((_, last_used)) = self.entries.remove(hash) {
+            self.recency.remove(&last_used);
+        }
+    }
+}
+
+impl TokenCacheStats {
+    /// The fraction of lookups that hit, or `None` if there have been none.
+    pub fn hit_rate(&self) -> Option<f64> {
+        match self.hits + self.misses {
+            0 => None,
+            lookups => Some(self.hits as f64 / lookups as f64),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    /// Tests that the least recently used token is evicted when full, and
+    /// that hits and misses are counted.
+    fn test_evicts_least_recently_used() {
+        let mut cache = TokenCache::new(2);
+
+        cache.insert(hash_token("a"), utils::verified("a", "kid", 100));
+        cache.insert(hash_token("b"), utils::verified("b", "kid", 100));
+
+        // Using `a` makes `b` the least recently used.
+        assert!(cache.get(&hash_token("a"), 0).is_some());
+        cache.insert(hash_token("c"), utils::verified("c", "kid", 100));
+
+        assert!(cache.get(&hash_token("b"), 0).is_none());
+        assert_eq!(
+            cache
+                .get(&hash_token("a"), 0)
+                .map(|verified| verified.claims),
+            Some("a")
+        );
+        assert!(cache.get(&hash_token("c"), 0).is_some());
+
+        assert_eq!(
+            cache.stats(),
+            TokenCacheStats {
+                hits: 3,
+                misses: 1,
+                entries: 2
+            }
+        );
+        assert_eq!(cache.stats().hit_rate(), Some(0.75));
+    }
+
+    #[test]
+    /// Tests that expired tokens miss, and that a zero capacity caches nothing.
+    fn test_expired_tokens_and_zero_capacity() {
+        let mut cache = TokenCache::new(2);
+        cache.insert(hash_token("a"), utils::verified("a", "kid", 100));
+
+        assert!(cache.get(&hash_token("a"), 100).is_some());
+        assert!(cache.get(&hash_token("a"), 101).is_none());
+        assert_eq!(cache.stats().entries, 0);
+
+        let mut disabled = TokenCache::new(0);
+        disabled.insert(hash_token("a"), utils::verified("a", "kid", 100));
+
+        assert!(disabled.get(&hash_token("a"), 0).is_none());
+        assert_eq!(disabled.stats().hit_rate(), Some(0.0));
+    }
+
+    #[test]
+    /// Tests that only tokens verified with dropped keys are forgotten.
+    fn test_retains_tokens_by_key() {
+        let mut cache = TokenCache::new(4);
+        cache.insert(hash_token("a"), utils::verified("a", "old", 100));
+        cache.insert(hash_token("b"), utils::verified("b", "new", 100));
+
+        cache.retain_thumbprints(|thumbprint| thumbprint == "new");
+
+        assert!(cache.get(&hash_token("a"), 0).is_none());
+        assert!(cache.get(&hash_token("b"), 0).is_some());
+    }
+
+    mod utils {
+        use super::*;
+
+        pub fn verified(
+            claims: &'static str,
+            thumbprint: &str,
+            expires_at: i64,
+        ) -> VerifiedToken<&'static str> {
+            VerifiedToken {
+                claims,
+                registered: serde_json::from_str("{}").expect("Failed to parse claims"),
+                thumbprint: String::from(thumbprint),
+                expires_at,
+            }
+        }
+    }
+}
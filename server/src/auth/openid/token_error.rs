@@ -0,0 +1,110 @@
+use std::fmt;
+
+use jsonwebtoken::errors::{Error as JwtError, ErrorKind as JwtErrorKind};
+
+/// Why a token was rejected.
+#[derive(Debug, PartialEq)]
+pub enum TokenError {
+    /// Not a JWT, or one missing the parts we need.
+    Malformed,
+    /// The `alg` named in the header, which we or its authority don't accept.
+    UnsupportedAlgorithm(String),
+    /// The (unverified) `iss`, which no configured authority issues.
+    UnknownIssuer(String),
+    /// The `kid`, which isn't in the authority's key set.
+    UnknownKey(String),
+    /// The key named by `kid` may not verify signatures with the token's `alg`.
+    UnusableKey,
+    /// We needed a fresh key set from the authority, but couldn't get one.
+    KeyFetchFailed,
+    BadSignature,
+    Expired,
+    WrongAudience,
+    /// The `iss`, which isn't the one the authority says it issues.
+    WrongIssuer(String),
+    /// `nbf` is still in the future.
+    NotYetValid,
+    /// `iat` is in the future.
+    IssuedInFuture,
+    /// Issued longer ago than the authority's maximum token age.
+    TooOld,
+    /// A claim we require, but which the token doesn't have.
+    MissingClaim(&'static str),
+    /// The token, its session, or everything its user was issued before it
+    /// was, has been revoked.
+    Revoked,
+}
+
+impl TokenError {
+    /// A short, stable name for the reason, for clients to match on.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            TokenError::Malformed => "malformed",
+            TokenError::UnsupportedAlgorithm(_) => "unsupported_algorithm",
+            TokenError::UnknownIssuer(_) => "unknown_issuer",
+            TokenError::UnknownKey(_) => "unknown_key",
+            TokenError::UnusableKey => "unusable_key",
+            TokenError::KeyFetchFailed => "key_fetch_failed",
+            TokenError::BadSignature => "bad_signature",
+            TokenError::Expired => "expired",
+            TokenError::WrongAudience => "wrong_audience",
+            TokenError::WrongIssuer(_) => "wrong_issuer",
+            TokenError::NotYetValid => "not_yet_valid",
+            TokenError::IssuedInFuture => "issued_in_future",
+            TokenError::TooOld => "too_old",
+            TokenError::MissingClaim(_) => "missing_claim",
+            TokenError::Revoked => "revoked",
+        }
+    }
+
+    /// Whether the client could succeed by refreshing its token with the
+    /// same sign-in, rather than signing in again.
+    pub fn is_refreshable(&self) -> bool {
+        matches!(self, TokenError::Expired | TokenError::TooOld)
+    }
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TokenError::Malformed => write!(f, "the token is malformed"),
+            TokenError::UnsupportedAlgorithm(alg) => {
+                write!(f, "the token's algorithm {} is not accepted", alg)
+            }
+            TokenError::UnknownIssuer(iss) => write!(f, "the token's issuer {} is unknown", iss),
+            TokenError::UnknownKey(kid) => write!(f, "the token's key {} is unknown", kid),
+            TokenError::UnusableKey => {
+                write!(f, "the token's key can't be used with its algorithm")
+            }
+            TokenError::KeyFetchFailed => {
+                write!(f, "the issuer's keys could not be fetched")
+            }
+            TokenError::BadSignature => write!(f, "the token's signature is invalid"),
+            TokenError::Expired => write!(f, "the token has expired"),
+            TokenError::WrongAudience => write!(f, "the token was issued to another audience"),
+            TokenError::WrongIssuer(iss) => {
+                write!(f, "the token's issuer {} is not the expected one", iss)
+            }
+            TokenError::NotYetValid => write!(f, "the token is not valid yet"),
+            TokenError::IssuedInFuture => write!(f, "the token was issued in the future"),
+            TokenError::TooOld => write!(f, "the token was issued too long ago"),
+            TokenError::MissingClaim(claim) => write!(f, "the token has no {} claim", claim),
+            TokenError::Revoked => write!(f, "the token has been revoked"),
+        }
+    }
+}
+
+impl From<JwtError> for TokenError {
+    fn from(err: JwtError) -> Self {
+        match err.kind() {
+            JwtErrorKind::InvalidSignature => TokenError::BadSignature,
+            JwtErrorKind::ExpiredSignature => TokenError::Expired,
+            JwtErrorKind::InvalidAudience => TokenError::WrongAudience,
+            JwtErrorKind::ImmatureSignature => TokenError::NotYetValid,
+            JwtErrorKind::InvalidEcdsaKey
+            | JwtErrorKind::InvalidRsaKey
+            | JwtErrorKind::InvalidKeyFormat => TokenError::UnusableKey,
+            _ => TokenError::Malformed,
+        }
+    }
+}
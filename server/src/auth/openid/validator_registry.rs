@@ -0,0 +1,407 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use rocket::async_trait;
+use serde::Deserialize;
+
+use super::{
+    authority::{Authority, Claims, MSAClaims, OidcClaims},
+    config::{AuthConfig, AuthConfigError, AuthorityConfig, KeySource, UserIdClaim},
+    jwt,
+    jwt_validator::{self, JwtValidator},
+    key_set::{KeySetFetcher, NetworkKeySetFetcher},
+    key_set_fetchers::{FallbackKeySetFetcher, FileKeySetFetcher, StaticKeySetFetcher},
+    registered_claims,
+    token_cache::TokenCacheStats,
+    token_error::TokenError,
+};
+
+/// A `JwtValidator`, with its authority's claims type erased so that
+/// validators for different authorities can live side by side.
+#[async_trait]
+pub trait TokenValidator: Send + Sync {
+    /// The `iss` of tokens this validator accepts.
+    fn issuer(&self) -> &str;
+
+    /// Validates `jwt`, returning who it was issued to, as this validator's
+    /// authority knows them.
+    async fn validate_identity(&self, jwt: &str) -> Result<ProviderIdentity, TokenError>;
+
+    /// Refreshes the authority's keys, returning how long to wait before
+    /// refreshing them again.
+    async fn refresh_key_set(&self) -> Duration;
+
+    /// How long ago the authority's keys were fetched, if they ever were.
+    fn key_set_age(&self) -> Option<Duration>;
+
+    /// How often tokens have been found in the verified-token cache.
+    fn token_cache_stats(&self) -> TokenCacheStats;
+}
+
+#[async_trait]
+impl<C, F> TokenValidator for JwtValidator<C, F>
+where
+    C: Claims,
+    F: KeySetFetcher + Send + Sync,
+{
+    fn issuer(&self) -> &str {
+        JwtValidator::issuer(self)
+    }
+
+    async fn validate_identity(&self, jwt: &str) -> Result<ProviderIdentity, TokenError> {
+        let subject = self.validate(jwt).await.map(Claims::subject)?;
+
+        Ok(ProviderIdentity {
+            issuer: String::from(JwtValidator::issuer(self)),
+            subject,
+        })
+    }
+
+    async fn refresh_key_set(&self) -> Duration {
+        JwtValidator::refresh_key_set(self).await
+    }
+
+    fn key_set_age(&self) -> Option<Duration> {
+        JwtValidator::key_set_age(self)
+    }
+
+    fn token_cache_stats(&self) -> TokenCacheStats {
+        JwtValidator::token_cache_stats(self)
+    }
+}
+
+/// A user as an authority knows them. The authority's ID for them is only
+/// unique among its own users, so it's namespaced by the authority's issuer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProviderIdentity {
+    /// The issuer of the authority, as configured. For templated issuers,
+    /// that's the template.
+    pub issuer: String,
+    /// The authority's ID for the user.
+    pub subject: String,
+}
+
+/// Validators for each of the authorities users can sign in with, keyed by
+/// issuer. Each token is validated by the validator for its (as yet
+/// unverified) `iss`.
+pub struct ValidatorRegistry {
+    validators: HashMap<String, Arc<dyn TokenValidator>>,
+}
+
+impl ValidatorRegistry {
+    pub fn new() -> Self {
+        Self {
+            validators: HashMap::new(),
+        }
+    }
+
+    /// Builds a validator for each authority in `config`, after checking that
+    /// they're all usable.
+    pub fn from_config(config: &AuthConfig) -> Result<Self, AuthConfigError> {
+        config.validate()?;
+
+        let mut registry = ValidatorRegistry::new();
+
+        for authority in &config.authorities {
+            match authority.user_id_claim {
+                UserIdClaim::Oid => registry.register_authority::<MSAClaims>(authority),
+                UserIdClaim::Sub => registry.register_authority::<OidcClaims>(authority),
+            }
+        }
+
+        Ok(registry)
+    }
+
+    /// Registers `validator` for its issuer, replacing any validator already
+    /// registered for it.
+    pub fn register<V: TokenValidator + 'static>(&mut self, validator: V) {
+        self.validators
+            .insert(String::from(validator.issuer()), Arc::new(validator));
+    }
+
+    /// Registers a validator for the configured authority, getting its keys
+    /// from wherever the config says.
+    fn register_authority<C: Claims + 'static>(&mut self, config: &AuthorityConfig) {
+        match &config.key_source {
+            None | Some(KeySource::Network) => self.register(validator::<C, _>(
+                config,
+                NetworkKeySetFetcher::new(),
+                jwt_validator::DEFAULT_REFRESH_INTERVAL,
+            )),
+            // Checking a local file is cheap, so changes are picked up sooner.
+            Some(KeySource::File(path)) => self.register(validator::<C, _>(
+                config,
+                FileKeySetFetcher::new(path.clone()),
+                FileKeySetFetcher::POLL_INTERVAL,
+            )),
+            Some(KeySource::Inline(key_set)) => self.register(validator::<C, _>(
+                config,
+                StaticKeySetFetcher::new(key_set.clone()),
+                jwt_validator::DEFAULT_REFRESH_INTERVAL,
+            )),
+            Some(KeySource::NetworkWithFallback(path)) => self.register(validator::<C, _>(
+                config,
+                FallbackKeySetFetcher::new(
+                    NetworkKeySetFetcher::new(),
+                    FileKeySetFetcher::new(path.clone()),
+                ),
+                jwt_validator::DEFAULT_REFRESH_INTERVAL,
+            )),
+        }
+    }
+
+    /// Spawns a task per authority that keeps its keys fresh in the
+    /// background. Each fetches right away, so the first request doesn't
+    /// wait on the authority, then again whenever the keys go stale.
+    pub fn spawn_key_refreshers(&self) {
+        for validator in self.validators.values() {
+            let validator = Arc::clone(validator);
+
+            tokio::spawn(async move {
+                loop {
+                    let delay = validator.refresh_key_set().await;
+                    tokio::time::sleep(delay).await;
+                }
+            });
+        }
+    }
+
+    /// How long ago each authority's keys were fetched, keyed by issuer.
+    pub fn key_set_ages(&self) -> Vec<(String, Option<Duration>)> {
+        let mut ages = self
+            .validators
+            .iter()
+            .map(|(issuer, validator)| (issuer.clone(), validator.key_set_age()))
+            .collect::<Vec<_>>();
+
+        ages.sort_by(|(a, _), (b, _)| a.cmp(b));
+        ages
+    }
+
+    /// Each authority's verified-token cache stats, keyed by issuer.
+    pub fn token_cache_stats(&self) -> Vec<(String, TokenCacheStats)> {
+        let mut stats = self
+            .validators
+            .iter()
+            .map(|(issuer, validator)| (issuer.clone(), validator.token_cache_stats()))
+            .collect::<Vec<_>>();
+
+        stats.sort_by(|(a, _), (b, _)| a.cmp(b));
+        stats
+    }
+
+    /// Validates `jwt` with the validator for its issuer, returning who it
+    /// was issued to. Tokens from unknown issuers are rejected.
+    pub async fn validate(&self, jwt: &str) -> Result<ProviderIdentity, TokenError> {
+        let issuer = unverified_issuer(jwt)?;
+
+        // Fall back to templated issuers, e.g. MSA's `{tenantid}` one.
+        let validator = self.validators.get(&issuer).or_else(|| {
+            self.validators
+                .iter()
+                .find(|(expected, _)| registered_claims::match_issuer(expected, &issuer).is_some())
+                .map(|(_, validator)| validator)
+        });
+
+        let validator = validator.ok_or(TokenError::UnknownIssuer(issuer))?;
+
+        validator.validate_identity(jwt).await
+    }
+}
+
+/// A validator for the configured authority, getting its keys with
+/// `fetcher`, and refreshing them every `default_refresh_interval` unless the
+/// config says otherwise.
+fn validator<C, F>(
+    config: &AuthorityConfig,
+    fetcher: F,
+    default_refresh_interval: Duration,
+) -> JwtValidator<C, F>
+where
+    C: Claims,
+    F: KeySetFetcher,
+{
+    let validator = JwtValidator::new_with_config(
+        Authority::from_config(config),
+        fetcher,
+        config
+            .refresh_interval()
+            .unwrap_or(default_refresh_interval),
+    );
+
+    match config.token_cache_capacity {
+        Some(capacity) => validator.with_token_cache_capacity(capacity),
+        None => validator,
+    }
+}
+
+/// Reads the `iss` claim out of `jwt` without verifying anything. Only good
+/// for picking which validator to verify the token with.
+fn unverified_issuer(jwt: &str) -> Result<String, TokenError> {
+    #[derive(Deserialize)]
+    struct IssuerClaim {
+        iss: Option<String>,
+    }
+
+    jwt::decode_unverified_claims::<IssuerClaim>(jwt)?
+        .iss
+        .ok_or(TokenError::MissingClaim("iss"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    /// Tests that each token is validated by the validator for its issuer.
+    async fn test_dispatches_on_issuer() {
+        let mut registry = ValidatorRegistry::new();
+        registry.register(utils::FixedValidator("https://one.example.com"));
+        registry.register(utils::FixedValidator("https://two.example.com"));
+
+        assert_eq!(
+            registry
+                .validate(&utils::generate_jwt(Some("https://one.example.com")))
+                .await,
+            Ok(utils::identity("https://one.example.com"))
+        );
+        assert_eq!(
+            registry
+                .validate(&utils::generate_jwt(Some("https://two.example.com")))
+                .await,
+            Ok(utils::identity("https://two.example.com"))
+        );
+    }
+
+    #[tokio::test]
+    /// Tests that tokens are dispatched to a validator with a templated
+    /// issuer, when none has their exact issuer.
+    async fn test_dispatches_on_templated_issuer() {
+        let mut registry = ValidatorRegistry::new();
+        registry.register(utils::FixedValidator(
+            "https://tenants.example.com/{tenantid}/v2.0",
+        ));
+
+        assert_eq!(
+            registry
+                .validate(&utils::generate_jwt(Some(
+                    "https://tenants.example.com/tenant/v2.0"
+                )))
+                .await,
+            Ok(utils::identity(
+                "https://tenants.example.com/{tenantid}/v2.0"
+            ))
+        );
+        assert_eq!(
+            registry
+                .validate(&utils::generate_jwt(Some(
+                    "https://evil.example.com/tenant/v2.0"
+                )))
+                .await,
+            Err(TokenError::UnknownIssuer(String::from(
+                "https://evil.example.com/tenant/v2.0"
+            )))
+        );
+    }
+
+    #[tokio::test]
+    /// Tests that tokens carrying another form of an issuer, like Google's
+    /// without the scheme, are validated as the configured issuer's.
+    async fn test_dispatches_on_issuer_alias() {
+        let mut registry = ValidatorRegistry::new();
+        registry.register(utils::FixedValidator("https://accounts.google.com"));
+
+        assert_eq!(
+            registry
+                .validate(&utils::generate_jwt(Some("accounts.google.com")))
+                .await,
+            Ok(utils::identity("https://accounts.google.com"))
+        );
+    }
+
+    #[tokio::test]
+    /// Tests that tokens from unknown issuers, or with no issuer at all, are
+    /// rejected.
+    async fn test_rejects_unknown_or_missing_issuer() {
+        let mut registry = ValidatorRegistry::new();
+        registry.register(utils::FixedValidator("https://one.example.com"));
+
+        assert_eq!(
+            registry
+                .validate(&utils::generate_jwt(Some("https://evil.example.com")))
+                .await,
+            Err(TokenError::UnknownIssuer(String::from(
+                "https://evil.example.com"
+            )))
+        );
+        assert_eq!(
+            registry.validate(&utils::generate_jwt(None)).await,
+            Err(TokenError::MissingClaim("iss"))
+        );
+        assert_eq!(
+            registry.validate("not_a_jwt_not_even_close").await,
+            Err(TokenError::Malformed)
+        );
+    }
+
+    mod utils {
+        use super::*;
+
+        use jsonwebtoken::{encode, EncodingKey, Header};
+        use serde::Serialize;
+
+        /// Accepts every token, and identifies its user by the validator's
+        /// issuer, so tests can tell which validator was used.
+        pub struct FixedValidator(pub &'static str);
+
+        #[async_trait]
+        impl TokenValidator for FixedValidator {
+            fn issuer(&self) -> &str {
+                self.0
+            }
+
+            async fn validate_identity(&self, _jwt: &str) -> Result<ProviderIdentity, TokenError> {
+                Ok(identity(self.0))
+            }
+
+            async fn refresh_key_set(&self) -> Duration {
+                Duration::from_secs(60)
+            }
+
+            fn key_set_age(&self) -> Option<Duration> {
+                None
+            }
+
+            fn token_cache_stats(&self) -> TokenCacheStats {
+                TokenCacheStats {
+                    hits: 0,
+                    misses: 0,
+                    entries: 0,
+                }
+            }
+        }
+
+        /// What a `FixedValidator` for `issuer` identifies every user as.
+        pub fn identity(issuer: &str) -> ProviderIdentity {
+            ProviderIdentity {
+                issuer: String::from(issuer),
+                subject: String::from(issuer),
+            }
+        }
+
+        /// The registry never checks signatures itself, so any key will do.
+        pub fn generate_jwt(iss: Option<&str>) -> String {
+            #[derive(Serialize)]
+            struct IssuerClaims<'a> {
+                #[serde(skip_serializing_if = "Option::is_none")]
+                iss: Option<&'a str>,
+            }
+
+            encode(
+                &Header::default(),
+                &IssuerClaims { iss },
+                &EncodingKey::from_secret(b"secret"),
+            )
+            .expect("Failed to generate token")
+        }
+    }
+}
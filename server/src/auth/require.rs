@@ -0,0 +1,144 @@
+use std::marker::PhantomData;
+
+use rocket::{
+    async_trait,
+    http::Status,
+    request::{FromRequest, Outcome, Request},
+    State,
+};
+
+use super::{AuthError, AuthenticatedUser, Role, Scope};
+use crate::storage::MongoManager;
+
+/// A `Scope` as a type, so routes can require it with `RequireScope`.
+pub trait RequiredScope: Send + Sync + 'static {
+    const SCOPE: Scope;
+}
+
+/// A `Role` as a type, so routes can require it with `RequireRole`.
+pub trait RequiredRole: Send + Sync + 'static {
+    const ROLE: Role;
+}
+
+/// Each `Scope`, for `RequireScope`.
+pub mod scopes {
+    use super::{RequiredScope, Scope};
+
+    pub struct LocationRead;
+    pub struct LocationWrite;
+    pub struct ContactsRead;
+    pub struct SharingWrite;
+    pub struct Webhooks;
+
+    impl RequiredScope for LocationRead {
+        const SCOPE: Scope = Scope::LocationRead;
+    }
+
+    impl RequiredScope for LocationWrite {
+        const SCOPE: Scope = Scope::LocationWrite;
+    }
+
+    impl RequiredScope for ContactsRead {
+        const SCOPE: Scope = Scope::ContactsRead;
+    }
+
+    impl RequiredScope for SharingWrite {
+        const SCOPE: Scope = Scope::SharingWrite;
+    }
+
+    impl RequiredScope for Webhooks {
+        const SCOPE: Scope = Scope::Webhooks;
+    }
+}
+
+/// Each `Role` beyond `Role::User`, which everyone has, for `RequireRole`.
+pub mod roles {
+    use super::{RequiredRole, Role};
+
+    pub struct Support;
+    pub struct Admin;
+
+    impl RequiredRole for Support {
+        const ROLE: Role = Role::Support;
+    }
+
+    impl RequiredRole for Admin {
+        const ROLE: Role = Role::Admin;
+    }
+}
+
+/// An `AuthenticatedUser` whose token may be used for the scope `S`, e.g.
+/// `RequireScope<scopes::Webhooks>`. Like `AuthenticatedUser`, routes take
+/// it as a `Result` and feed errors into `ApiError`.
+pub struct RequireScope<S: RequiredScope> {
+    id: String,
+    scope: PhantomData<S>,
+}
+
+impl<S: RequiredScope> RequireScope<S> {
+    pub fn id(self) -> String {
+        self.id
+    }
+}
+
+#[async_trait]
+impl<'a, 'r, S: RequiredScope> FromRequest<'a, 'r> for RequireScope<S> {
+    type Error = AuthError;
+
+    async fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        let user = try_outcome!(request.guard::<AuthenticatedUser>().await);
+
+        match user.id_with_scope(S::SCOPE) {
+            Ok(id) => Outcome::Success(Self {
+                id,
+                scope: PhantomData,
+            }),
+            Err(auth_err) => Outcome::Failure((Status::ImATeapot, auth_err)),
+        }
+    }
+}
+
+/// A signed-in user whose role grants `R`'s, e.g. `RequireRole<roles::Admin>`.
+/// Personal access tokens never act with their user's role. Like
+/// `AuthenticatedUser`, routes take it as a `Result` and feed errors into
+/// `ApiError`.
+pub struct RequireRole<R: RequiredRole> {
+    id: String,
+    role: PhantomData<R>,
+}
+
+impl<R: RequiredRole> RequireRole<R> {
+    pub fn id(self) -> String {
+        self.id
+    }
+}
+
+#[async_trait]
+impl<'a, 'r, R: RequiredRole> FromRequest<'a, 'r> for RequireRole<R> {
+    type Error = AuthError;
+
+    async fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        let user = try_outcome!(request.guard::<AuthenticatedUser>().await);
+
+        let id = match user.signed_in_id() {
+            Ok(id) => id,
+            Err(auth_err) => return Outcome::Failure((Status::ImATeapot, auth_err)),
+        };
+
+        let mongo = try_outcome!(request
+            .guard::<State<MongoManager>>()
+            .await
+            .map_failure(|_| { (Status::ImATeapot, AuthError::FailedToGetJwtValidator,) }));
+
+        match mongo.get_user_role(&id).await {
+            Ok(role) if role.grants(R::ROLE) => Outcome::Success(Self {
+                id,
+                role: PhantomData,
+            }),
+            Ok(_) => Outcome::Failure((Status::ImATeapot, AuthError::MissingRole(R::ROLE))),
+            Err(mongo_err) => {
+                Outcome::Failure((Status::ImATeapot, AuthError::UserLookupFailed(mongo_err)))
+            }
+        }
+    }
+}
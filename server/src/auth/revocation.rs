@@ -0,0 +1,77 @@
+use std::{cmp, time::Duration};
+
+use crate::{
+    models::{common::now_epoch_secs, storage::Revocation},
+    storage::{MongoManager, MongoResult},
+};
+
+/// How long authorities' ID tokens can last. They're usually good for an
+/// hour; this leaves plenty of margin.
+const MAX_ID_TOKEN_LIFETIME: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Signs the user with `user_id` out on every device: revokes all their
+/// sessions, and every access and ID token they were issued before `now`.
+/// Personal access tokens keep working, and are revoked separately.
+pub async fn sign_out_everywhere(
+    mongo: &MongoManager,
+    user_id: &str,
+    now: i64,
+    access_token_ttl: Duration,
+) -> MongoResult<()> {
+    mongo.revoke_user_sessions(user_id, now).await?;
+
+    mongo
+        .revoke(&revoke_user_tokens(user_id, now, access_token_ttl))
+        .await
+}
+
+/// Revokes every token issued to the user with `user_id` before `now`. Kept
+/// until the longer of our access tokens and authorities' ID tokens issued
+/// just before `now` have expired.
+fn revoke_user_tokens(user_id: &str, now: i64, access_token_ttl: Duration) -> Revocation {
+    let lifetime = cmp::max(access_token_ttl, MAX_ID_TOKEN_LIFETIME);
+
+    Revocation::user(user_id, now, now + lifetime.as_secs() as i64)
+}
+
+/// Revokes the access tokens of the session with `session_id`, after it's
+/// been revoked itself at `now`. Kept until the last of them has expired.
+pub fn revoke_session_tokens(session_id: &str, now: i64, access_token_ttl: Duration) -> Revocation {
+    Revocation::session(session_id, now + access_token_ttl.as_secs() as i64)
+}
+
+/// Deletes revocations in the background once they've expired, since every
+/// token they applied to has too.
+pub struct RevocationSweeper {
+    mongo: MongoManager,
+}
+
+impl RevocationSweeper {
+    /// How often to delete expired revocations. They stop applying at their
+    /// expiry regardless, so this only bounds how long they take up space.
+    const SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+    pub fn new(mongo: MongoManager) -> Self {
+        Self { mongo }
+    }
+
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(RevocationSweeper::SWEEP_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            match self
+                .mongo
+                .delete_expired_revocations(now_epoch_secs())
+                .await
+            {
+                Ok(0) => {}
+                Ok(deleted) => eprintln!("Deleted {} expired revocations", deleted),
+                Err(mongo_err) => {
+                    eprintln!("Failed to delete expired revocations: {:?}", mongo_err)
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+
+/// What a user may do beyond managing their own account, stored on `User`.
+/// Each role may do everything the roles before it may.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Everyone.
+    User,
+    /// Helping users with their accounts, e.g. signing them out everywhere.
+    Support,
+    /// Everything, including granting roles.
+    Admin,
+}
+
+impl Role {
+    /// The role's name, as stored and as it appears in errors.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Support => "support",
+            Role::Admin => "admin",
+        }
+    }
+
+    /// Whether someone with this role may do what `required` may.
+    pub fn grants(self, required: Role) -> bool {
+        self >= required
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    /// Tests that each role grants itself and those before it, and no more.
+    fn test_grants() {
+        assert!(Role::Admin.grants(Role::Support));
+        assert!(Role::Support.grants(Role::Support));
+        assert!(Role::Support.grants(Role::User));
+        assert!(!Role::Support.grants(Role::Admin));
+        assert!(!Role::User.grants(Role::Support));
+    }
+}
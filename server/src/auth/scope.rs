@@ -0,0 +1,35 @@
+use serde::{Deserialize, Serialize};
+
+/// What a personal access token may be used for. Tokens from signing in
+/// aren't scoped, and may be used for anything.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Scope {
+    /// Reading our own location, and requests for it.
+    #[serde(rename = "location:read")]
+    LocationRead,
+    /// Uploading our location.
+    #[serde(rename = "location:write")]
+    LocationWrite,
+    /// Reading contacts' locations, and asking them for fresh ones.
+    #[serde(rename = "contacts:read")]
+    ContactsRead,
+    /// Starting, stopping and pausing sharing with contacts.
+    #[serde(rename = "sharing:write")]
+    SharingWrite,
+    /// Managing webhooks.
+    #[serde(rename = "webhooks")]
+    Webhooks,
+}
+
+impl Scope {
+    /// The scope's name, as clients send it and as it appears in challenges.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::LocationRead => "location:read",
+            Scope::LocationWrite => "location:write",
+            Scope::ContactsRead => "contacts:read",
+            Scope::SharingWrite => "sharing:write",
+            Scope::Webhooks => "webhooks",
+        }
+    }
+}
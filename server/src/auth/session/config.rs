@@ -0,0 +1,56 @@
+use std::fmt;
+
+use serde::Deserialize;
+
+/// How the sessions we issue after sign-in work. Loaded from the `session`
+/// table of the `auth` config section.
+#[derive(Debug, Default, Deserialize)]
+pub struct SessionConfig {
+    /// The keys to sign and verify access tokens with, newest first. The
+    /// first signs new tokens; the rest only verify, so that tokens signed
+    /// before a rotation keep working until they expire. If none are
+    /// configured, a random key is generated at startup, and sessions don't
+    /// survive a restart.
+    #[serde(default)]
+    pub keys: Vec<SessionKeyConfig>,
+    /// How long access tokens last. Defaults to `SessionIssuer`'s.
+    pub access_token_ttl_secs: Option<u64>,
+    /// How long a refresh token lasts if it isn't used. Using one pushes the
+    /// session's expiry back by this much. Defaults to `SessionIssuer`'s.
+    pub refresh_token_ttl_secs: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SessionKeyConfig {
+    /// Put in the `kid` header of tokens signed with this key.
+    pub id: String,
+    /// The base64-encoded HMAC key. Must be at least `KeyRing::MIN_KEY_LEN`
+    /// bytes long.
+    pub secret: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SessionConfigError {
+    DuplicateKeyId(String),
+    /// A key that isn't base64, or is too short.
+    InvalidKey(String),
+    ZeroTokenLifetime,
+}
+
+impl fmt::Display for SessionConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SessionConfigError::DuplicateKeyId(id) => {
+                write!(f, "session key {} is configured more than once", id)
+            }
+            SessionConfigError::InvalidKey(id) => write!(
+                f,
+                "session key {} must be at least 32 bytes, base64-encoded",
+                id
+            ),
+            SessionConfigError::ZeroTokenLifetime => {
+                write!(f, "session tokens must have a non-zero lifetime")
+            }
+        }
+    }
+}
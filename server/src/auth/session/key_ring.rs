@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+
+use jsonwebtoken::{
+    decode, decode_header, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::config::{SessionConfigError, SessionKeyConfig};
+use crate::auth::openid::TokenError;
+
+/// The keys we sign our own tokens with. Since we both sign and verify them,
+/// a shared secret (HS256) is all we need, unlike for an authority's tokens.
+pub struct KeyRing {
+    /// The key new tokens are signed with.
+    signing_key_id: String,
+    /// Every key we accept signatures from, by ID, including the signing key.
+    keys: HashMap<String, Vec<u8>>,
+}
+
+impl KeyRing {
+    /// HS256 is only as strong as its key, so shorter keys are rejected.
+    pub const MIN_KEY_LEN: usize = 32;
+
+    /// Builds a ring from configured keys, the first of which signs.
+    pub fn from_config(configs: &[SessionKeyConfig]) -> Result<Self, SessionConfigError> {
+        let mut keys = HashMap::new();
+
+        for config in configs {
+            let secret = match base64::decode(&config.secret) {
+                Ok(secret) if secret.len() >= KeyRing::MIN_KEY_LEN => secret,
+                _ => return Err(SessionConfigError::InvalidKey(config.id.clone())),
+            };
+
+            if keys.insert(config.id.clone(), secret).is_some() {
+                return Err(SessionConfigError::DuplicateKeyId(config.id.clone()));
+            }
+        }
+
+        match configs.first() {
+            Some(signing_key) => Ok(Self {
+                signing_key_id: signing_key.id.clone(),
+                keys,
+            }),
+            None => Ok(KeyRing::ephemeral()),
+        }
+    }
+
+    /// A ring with a single random key, which nothing signed with survives a
+    /// restart.
+    pub fn ephemeral() -> Self {
+        let mut secret = vec![0u8; KeyRing::MIN_KEY_LEN];
+        SystemRandom::new()
+            .fill(&mut secret)
+            .expect("Failed to generate random bytes");
+
+        let signing_key_id = String::from("ephemeral");
+        let mut keys = HashMap::new();
+        keys.insert(signing_key_id.clone(), secret);
+
+        Self {
+            signing_key_id,
+            keys,
+        }
+    }
+
+    /// Signs `claims` with the signing key.
+    pub fn sign<T: Serialize>(&self, claims: &T) -> String {
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some(self.signing_key_id.clone());
+
+        encode(
+            &header,
+            claims,
+            &EncodingKey::from_secret(&self.keys[&self.signing_key_id]),
+        )
+        .expect("Failed to sign token")
+    }
+
+    /// Verifies `token`'s signature with the key its `kid` names, and that
+    /// it hasn't expired.
+    pub fn verify<T: DeserializeOwned>(&self, token: &str) -> Result<T, TokenError> {
+        let header = decode_header(token)?;
+
+        if header.alg != Algorithm::HS256 {
+            return Err(TokenError::UnsupportedAlgorithm(format!(
+                "{:?}",
+                header.alg
+            )));
+        }
+
+        let key_id = header.kid.ok_or(TokenError::Malformed)?;
+        let secret = self
+            .keys
+            .get(&key_id)
+            .ok_or(TokenError::UnknownKey(key_id))?;
+
+        decode(
+            token,
+            &DecodingKey::from_secret(secret),
+            &Validation::new(Algorithm::HS256),
+        )
+        .map(|data| data.claims)
+        .map_err(TokenError::from)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use serde::Deserialize;
+
+    use crate::models::common::now_epoch_secs;
+
+    #[test]
+    /// Tests that tokens signed before a rotation still verify afterwards, but
+    /// not once their key is dropped.
+    fn test_verifies_with_rotated_keys() {
+        let old_ring = KeyRing::from_config(&[utils::key("old")]).expect("Invalid ring");
+        let claims = utils::claims();
+        let token = old_ring.sign(&claims);
+
+        let rotated_ring =
+            KeyRing::from_config(&[utils::key("new"), utils::key("old")]).expect("Invalid ring");
+        assert_eq!(rotated_ring.verify(&token), Ok(claims));
+
+        let rotated_token = rotated_ring.sign(&utils::claims());
+        assert_eq!(
+            decode_header(&rotated_token).expect("Bad header").kid,
+            Some(String::from("new"))
+        );
+
+        let pruned_ring = KeyRing::from_config(&[utils::key("new")]).expect("Invalid ring");
+        assert_eq!(
+            pruned_ring.verify::<utils::TestClaims>(&token),
+            Err(TokenError::UnknownKey(String::from("old")))
+        );
+    }
+
+    #[test]
+    /// Tests that a token signed with a different secret under the same key
+    /// ID is rejected.
+    fn test_rejects_foreign_signature() {
+        let token = KeyRing::ephemeral().sign(&utils::claims());
+
+        assert_eq!(
+            KeyRing::ephemeral().verify::<utils::TestClaims>(&token),
+            Err(TokenError::BadSignature)
+        );
+    }
+
+    #[test]
+    /// Tests that short, non-base64 and duplicate keys are rejected.
+    fn test_rejects_bad_keys() {
+        let short = SessionKeyConfig {
+            id: String::from("short"),
+            secret: base64::encode([0u8; 16]),
+        };
+        let garbled = SessionKeyConfig {
+            id: String::from("garbled"),
+            secret: String::from("not base64!"),
+        };
+
+        assert_eq!(
+            KeyRing::from_config(&[short]).err(),
+            Some(SessionConfigError::InvalidKey(String::from("short")))
+        );
+        assert_eq!(
+            KeyRing::from_config(&[garbled]).err(),
+            Some(SessionConfigError::InvalidKey(String::from("garbled")))
+        );
+        assert_eq!(
+            KeyRing::from_config(&[utils::key("a"), utils::key("a")]).err(),
+            Some(SessionConfigError::DuplicateKeyId(String::from("a")))
+        );
+    }
+
+    mod utils {
+        use super::*;
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        pub struct TestClaims {
+            pub sub: String,
+            pub exp: i64,
+        }
+
+        pub fn claims() -> TestClaims {
+            TestClaims {
+                sub: String::from("user"),
+                exp: now_epoch_secs() + 60,
+            }
+        }
+
+        pub fn key(id: &str) -> SessionKeyConfig {
+            SessionKeyConfig {
+                id: String::from(id),
+                secret: base64::encode([id.as_bytes()[0]; KeyRing::MIN_KEY_LEN]),
+            }
+        }
+    }
+}
@@ -0,0 +1,9 @@
+mod config;
+mod key_ring;
+mod refresh_token;
+mod session_issuer;
+
+pub use config::{SessionConfig, SessionConfigError, SessionKeyConfig};
+pub use key_ring::KeyRing;
+pub use refresh_token::{generate_refresh_token, hash_refresh_token};
+pub use session_issuer::{SessionClaims, SessionIssuer};
@@ -0,0 +1,21 @@
+use ring::{
+    digest::{digest, SHA256},
+    rand::{SecureRandom, SystemRandom},
+};
+
+/// Generates a fresh, random refresh token. Opaque to clients, and only ever
+/// stored hashed.
+pub fn generate_refresh_token() -> String {
+    let mut token = [0u8; 32];
+    SystemRandom::new()
+        .fill(&mut token)
+        .expect("Failed to generate random bytes");
+
+    base64::encode_config(token, base64::URL_SAFE_NO_PAD)
+}
+
+/// The hex-encoded SHA-256 of `token`, which is what we store and look it up
+/// by. Refresh tokens are random enough that a salt would add nothing.
+pub fn hash_refresh_token(token: &str) -> String {
+    hex::encode(digest(&SHA256, token.as_bytes()).as_ref())
+}
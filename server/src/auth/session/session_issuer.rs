@@ -0,0 +1,194 @@
+use std::time::Duration;
+
+use jsonwebtoken::dangerous_insecure_decode;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    config::{SessionConfig, SessionConfigError},
+    key_ring::KeyRing,
+};
+use crate::auth::openid::TokenError;
+
+/// The claims in access tokens we issue.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct SessionClaims {
+    /// Always `SessionIssuer::ISSUER`.
+    pub iss: String,
+    /// The user the token was issued to.
+    pub sub: String,
+    /// The session the token was issued for.
+    pub sid: String,
+    /// Unique to each token.
+    pub jti: String,
+    pub iat: i64,
+    pub exp: i64,
+}
+
+/// Issues and validates our own access tokens, which clients get by
+/// exchanging an authority's ID token or one of our refresh tokens. They save
+/// clients from going back to the authority every time its short-lived ID
+/// token expires.
+pub struct SessionIssuer {
+    key_ring: KeyRing,
+    access_token_ttl: Duration,
+    refresh_token_ttl: Duration,
+}
+
+impl SessionIssuer {
+    /// The `iss` of our tokens. Not a URL, so it can't collide with an
+    /// authority's.
+    pub const ISSUER: &'static str = "sonar";
+    const DEFAULT_ACCESS_TOKEN_TTL: Duration = Duration::from_secs(15 * 60);
+    const DEFAULT_REFRESH_TOKEN_TTL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+    pub fn new(key_ring: KeyRing) -> Self {
+        Self {
+            key_ring,
+            access_token_ttl: SessionIssuer::DEFAULT_ACCESS_TOKEN_TTL,
+            refresh_token_ttl: SessionIssuer::DEFAULT_REFRESH_TOKEN_TTL,
+        }
+    }
+
+    pub fn from_config(config: &SessionConfig) -> Result<Self, SessionConfigError> {
+        if config.access_token_ttl_secs == Some(0) || config.refresh_token_ttl_secs == Some(0) {
+            return Err(SessionConfigError::ZeroTokenLifetime);
+        }
+
+        if config.keys.is_empty() {
+            eprintln!("No session keys are configured, so sessions won't survive a restart");
+        }
+
+        let mut issuer = SessionIssuer::new(KeyRing::from_config(&config.keys)?);
+
+        if let Some(ttl) = config.access_token_ttl_secs {
+            issuer.access_token_ttl = Duration::from_secs(ttl);
+        }
+
+        if let Some(ttl) = config.refresh_token_ttl_secs {
+            issuer.refresh_token_ttl = Duration::from_secs(ttl);
+        }
+
+        Ok(issuer)
+    }
+
+    pub fn access_token_ttl(&self) -> Duration {
+        self.access_token_ttl
+    }
+
+    /// When a refresh token issued at `now` expires, in epoch-seconds.
+    pub fn refresh_token_expiry(&self, now: i64) -> i64 {
+        now + self.refresh_token_ttl.as_secs() as i64
+    }
+
+    /// Issues an access token for `user_id`'s session `session_id`, valid
+    /// from `now`.
+    pub fn issue_access_token(&self, user_id: &str, session_id: &str, now: i64) -> String {
+        let mut jti = [0u8; 16];
+        SystemRandom::new()
+            .fill(&mut jti)
+            .expect("Failed to generate random bytes");
+
+        self.key_ring.sign(&SessionClaims {
+            iss: String::from(SessionIssuer::ISSUER),
+            sub: String::from(user_id),
+            sid: String::from(session_id),
+            jti: hex::encode(jti),
+            iat: now,
+            exp: now + self.access_token_ttl.as_secs() as i64,
+        })
+    }
+
+    /// Whether `token` claims to be one of ours, without verifying anything.
+    /// Only good for picking whether to validate it here or with an authority.
+    pub fn issued(&self, token: &str) -> bool {
+        #[derive(Deserialize)]
+        struct IssuerClaim {
+            iss: Option<String>,
+        }
+
+        match dangerous_insecure_decode::<IssuerClaim>(token) {
+            Ok(data) => data.claims.iss.as_deref() == Some(SessionIssuer::ISSUER),
+            Err(_) => false,
+        }
+    }
+
+    /// Validates one of our access tokens, returning its claims.
+    pub fn validate(&self, token: &str) -> Result<SessionClaims, TokenError> {
+        let claims = self.key_ring.verify::<SessionClaims>(token)?;
+
+        if claims.iss != SessionIssuer::ISSUER {
+            return Err(TokenError::WrongIssuer(claims.iss));
+        }
+
+        Ok(claims)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::models::common::now_epoch_secs;
+
+    #[test]
+    /// Tests that an issued token validates, and is recognized as ours.
+    fn test_issued_token_validates() {
+        let issuer = SessionIssuer::new(KeyRing::ephemeral());
+        let now = now_epoch_secs();
+
+        let token = issuer.issue_access_token("user", "session", now);
+        let claims = issuer.validate(&token).expect("Failed to validate token");
+
+        assert!(issuer.issued(&token));
+        assert_eq!(claims.sub, "user");
+        assert_eq!(claims.sid, "session");
+        assert_eq!(claims.exp, now + 15 * 60);
+        assert_ne!(
+            claims.jti,
+            issuer
+                .validate(&issuer.issue_access_token("user", "session", now))
+                .expect("Failed to validate token")
+                .jti
+        );
+    }
+
+    #[test]
+    /// Tests that an expired token is rejected as refreshable.
+    fn test_rejects_expired_token() {
+        let issuer = SessionIssuer::new(KeyRing::ephemeral());
+        let long_ago = now_epoch_secs() - 60 * 60;
+
+        let result = issuer.validate(&issuer.issue_access_token("user", "session", long_ago));
+
+        assert_eq!(result, Err(TokenError::Expired));
+        assert!(TokenError::Expired.is_refreshable());
+    }
+
+    #[test]
+    /// Tests that tokens from elsewhere aren't mistaken for ours.
+    fn test_recognizes_only_own_tokens() {
+        let issuer = SessionIssuer::new(KeyRing::ephemeral());
+        let foreign = KeyRing::ephemeral().sign(&serde_json::json!({
+            "iss": "https://accounts.google.com",
+            "sub": "user"
+        }));
+
+        assert!(!issuer.issued(&foreign));
+        assert!(!issuer.issued("not a token"));
+    }
+
+    #[test]
+    /// Tests that zero token lifetimes are rejected.
+    fn test_rejects_zero_lifetimes() {
+        let config = SessionConfig {
+            access_token_ttl_secs: Some(0),
+            ..SessionConfig::default()
+        };
+
+        assert_eq!(
+            SessionIssuer::from_config(&config).err(),
+            Some(SessionConfigError::ZeroTokenLifetime)
+        );
+    }
+}
@@ -0,0 +1,101 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use serde::Serialize;
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+
+use super::EventSink;
+use crate::models::{api::Contact, common::Ping, storage::User};
+
+/// How often to send a keep-alive when no contacts have moved.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Tells the client a contact no longer shares with them.
+#[derive(Serialize)]
+struct ContactRemoved<'a> {
+    id: &'a str,
+}
+
+/// Streams a `Contact` to `sink` each time what the user with ID `my_user_id`
+/// can see of a user who shares with them changes, until the client
+/// disconnects. Updates that don't change the caller's view, e.g. uploads
+/// while paused for them, are dropped so they don't give away activity. A
+/// `contact_removed` event is sent when a contact stops sharing.
+///
+/// `contacts` are those already sharing with the caller, so their current view
+/// isn't resent. `my_ping` seeds the distance and bearing annotations, and is
+/// kept current as the caller's own updates come through.
+pub async fn stream_contacts(
+    my_user_id: String,
+    mut my_ping: Option<Ping>,
+    contacts: Vec<User>,
+    mut updates: Receiver<Arc<User>>,
+    mut sink: EventSink,
+) {
+    let mut keep_alive = tokio::time::interval(KEEP_ALIVE_INTERVAL);
+    // What the caller last saw of each contact.
+    let mut visible_pings = contacts
+        .iter()
+        .map(|user| (String::from(user.id()), user.visible_ping_for(&my_user_id)))
+        .collect::<HashMap<_, _>>();
+
+    loop {
+        let sent = tokio::select! {
+            update = updates.recv() => match update {
+                Ok(user) if user.id() == my_user_id => {
+                    my_ping = user.last_ping();
+                    Ok(())
+                }
+                Ok(user) if user.is_shared_to(&my_user_id) => {
+                    let visible_ping = user.visible_ping_for(&my_user_id);
+
+                    match visible_pings.insert(String::from(user.id()), visible_ping) {
+                        Some(last_visible_ping) if last_visible_ping == visible_ping => Ok(()),
+                        _ => {
+                            let contact =
+                                Contact::visible_to(&user, &my_user_id).relative_to(my_ping);
+
+                            sink.send(&contact).await
+                        }
+                    }
+                }
+                Ok(user) if visible_pings.remove(user.id()).is_some() => {
+                    let removed = ContactRemoved { id: user.id() };
+
+                    sink.send_named("contact_removed", &removed).await
+                }
+                // Not someone we can see, or we fell behind and missed some
+                // updates. Either way, carry on with the next one.
+                Ok(_) | Err(RecvError::Lagged(_)) => Ok(()),
+                Err(RecvError::Closed) => break,
+            },
+            _ = keep_alive.tick() => sink.keep_alive().await,
+        };
+
+        // The client has gone away.
+        if sent.is_err() {
+            break;
+        }
+    }
+}
+
+/// Waits until a user who shares with the user with ID `my_user_id` is
+/// updated, for at most `timeout`. Returns whether such an update arrived.
+pub async fn wait_for_contact_update(
+    my_user_id: &str,
+    updates: &mut Receiver<Arc<User>>,
+    timeout: Duration,
+) -> bool {
+    let contact_updated = async {
+        loop {
+            match updates.recv().await {
+                Ok(user) if user.is_shared_to(my_user_id) => return true,
+                Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return false,
+            }
+        }
+    };
+
+    tokio::time::timeout(timeout, contact_updated)
+        .await
+        .unwrap_or(false)
+}
@@ -0,0 +1,71 @@
+use rocket::{
+    http::ContentType,
+    response::{content::Content, Responder, Result as ResponderResult, Stream},
+    Request,
+};
+use serde::Serialize;
+use tokio::io::{self, AsyncWriteExt, DuplexStream};
+
+/// A Server-Sent Events response body. Created in a pair with an `EventSink`;
+/// whatever is sent into the sink is streamed to the client until either side
+/// is dropped.
+///
+/// See: https://html.spec.whatwg.org/multipage/server-sent-events.html
+pub struct EventStream {
+    reader: DuplexStream,
+}
+
+pub struct EventSink {
+    writer: DuplexStream,
+}
+
+impl EventStream {
+    /// How many bytes of events can be buffered before `EventSink::send`
+    /// waits for the client to catch up.
+    const BUFFER_SIZE: usize = 16 * 1024;
+
+    pub fn new() -> (EventSink, EventStream) {
+        let (writer, reader) = io::duplex(EventStream::BUFFER_SIZE);
+
+        (EventSink { writer }, EventStream { reader })
+    }
+}
+
+impl EventSink {
+    /// Sends `event` to the client as a JSON `data:` frame. Fails if the
+    /// client has disconnected.
+    pub async fn send<T: Serialize>(&mut self, event: &T) -> io::Result<()> {
+        let json = serde_json::to_string(event)?;
+
+        self.writer
+            .write_all(format!("data: {}\n\n", json).as_bytes())
+            .await
+    }
+
+    /// Sends `event` to the client as a JSON `data:` frame, named `name` so
+    /// clients can tell it from unnamed events. Fails if the client has
+    /// disconnected.
+    pub async fn send_named<T: Serialize>(&mut self, name: &str, event: &T) -> io::Result<()> {
+        let json = serde_json::to_string(event)?;
+
+        self.writer
+            .write_all(format!("event: {}\ndata: {}\n\n", name, json).as_bytes())
+            .await
+    }
+
+    /// Sends a comment frame, which clients ignore but which stops proxies
+    /// from timing out an idle connection. Fails if the client has disconnected.
+    pub async fn keep_alive(&mut self) -> io::Result<()> {
+        self.writer.write_all(b": keep-alive\n\n").await
+    }
+}
+
+impl<'r, 'o: 'r> Responder<'r, 'o> for EventStream {
+    fn respond_to(self, req: &'r Request<'_>) -> ResponderResult<'o> {
+        Content(
+            ContentType::new("text", "event-stream"),
+            Stream::from(self.reader),
+        )
+        .respond_to(req)
+    }
+}
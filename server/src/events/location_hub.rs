@@ -0,0 +1,35 @@
+use std::sync::Arc;
+
+use tokio::sync::broadcast::{self, Receiver, Sender};
+
+use crate::models::storage::User;
+
+/// An in-process pub/sub hub for user updates. Every subscriber receives every
+/// updated user, and is responsible for filtering down to the users (and the
+/// view of those users) it is allowed to see.
+#[derive(Clone)]
+pub struct LocationHub {
+    sender: Sender<Arc<User>>,
+}
+
+impl LocationHub {
+    /// How many updates a slow subscriber can fall behind by before it starts
+    /// missing them.
+    const CAPACITY: usize = 256;
+
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(LocationHub::CAPACITY);
+
+        Self { sender }
+    }
+
+    /// Publishes `user` to all current subscribers. A no-op if there are none.
+    pub fn publish(&self, user: Arc<User>) {
+        // Only fails if there are no subscribers, which is fine.
+        let _ = self.sender.send(user);
+    }
+
+    pub fn subscribe(&self) -> Receiver<Arc<User>> {
+        self.sender.subscribe()
+    }
+}
@@ -0,0 +1,7 @@
+mod contact_feed;
+mod event_stream;
+mod location_hub;
+
+pub use contact_feed::{stream_contacts, wait_for_contact_update};
+pub use event_stream::{EventSink, EventStream};
+pub use location_hub::LocationHub;
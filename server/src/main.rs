@@ -1,49 +1,168 @@
 #[macro_use]
 extern crate rocket;
 
+use std::time::{Duration, Instant};
+
+use mongodb::bson::oid::ObjectId;
 use rocket::{routes, State};
 use rocket_contrib::json::Json;
 
 mod auth;
+mod events;
 mod models;
+mod notifications;
+mod retry;
 mod routes;
 mod storage;
+mod webhooks;
 
-use auth::{openid::JwtValidator, AuthError, AuthenticatedUser};
-use models::{api::Contact, common::Location};
+use auth::{
+    openid::{self, AuthConfig, TokenError, ValidatorRegistry},
+    personal_access_token,
+    revocation::{self, RevocationSweeper},
+    roles, scopes,
+    session::{self, SessionIssuer},
+    AuthError, AuthenticatedUser, RequireRole, RequireScope, Scope, StreamingUser,
+};
+use events::EventStream;
+use models::{
+    api::{
+        ApiError, Contact, ContactChanges, DeviceRegistration, IdentityInfo, IdentityLink,
+        KeySetAge, LocationRequestInfo, PauseRequest, PersonalAccessTokenInfo,
+        PersonalAccessTokenRequest, RevocationRequest, RoleAssignment, TokenCacheInfo,
+        TokenRequest, TokenResponse, WebhookDeliveryInfo, WebhookInfo, WebhookRegistration,
+    },
+    common::{now_epoch_secs, Location, Notification, Ping, WebhookEvent},
+    storage::{
+        LocationRequest, PersonalAccessToken, RefreshTokenRotation, Revocation, Session, Webhook,
+    },
+};
+use notifications::NotificationDispatcher;
 use routes::{RouteResult, ToRouteResult};
 use storage::MongoManager;
+use webhooks::{WebhookDeliverySweeper, WebhookDispatcher};
 
 #[launch]
 async fn rocket() -> rocket::Rocket {
-    rocket::ignite()
-        .manage(JwtValidator::new_msa())
-        .manage(
-            MongoManager::new("mongodb://localhost:27017")
-                .await
-                .expect("Failed to connect to Mongo"),
+    let mongo = MongoManager::new("mongodb://localhost:27017")
+        .await
+        .expect("Failed to connect to Mongo");
+
+    tokio::spawn(NotificationDispatcher::from_env(mongo.clone()).run());
+    tokio::spawn(WebhookDispatcher::new(mongo.clone()).run());
+    tokio::spawn(WebhookDeliverySweeper::new(mongo.clone()).run());
+    tokio::spawn(RevocationSweeper::new(mongo.clone()).run());
+
+    let rocket = rocket::ignite();
+
+    let auth_config: AuthConfig = rocket
+        .figment()
+        .extract_inner("auth")
+        .unwrap_or_else(|err| panic!("Failed to load auth config: {}", err));
+    #[cfg(feature = "mock-oidc")]
+    let (rocket, auth_config) = routes::mock_oidc::mount(rocket, auth_config);
+    let validators = ValidatorRegistry::from_config(&auth_config)
+        .unwrap_or_else(|err| panic!("Invalid auth config: {}", err));
+    validators.spawn_key_refreshers();
+    let session_issuer = SessionIssuer::from_config(&auth_config.session)
+        .unwrap_or_else(|err| panic!("Invalid session config: {}", err));
+
+    mongo
+        .create_identity_indexes()
+        .await
+        .expect("Failed to index identities");
+
+    if let Some(legacy_authority) = auth_config.legacy_authority() {
+        mongo
+            .migrate_legacy_user_ids(&legacy_authority.issuer)
+            .await
+            .expect("Failed to migrate legacy users");
+    }
+
+    rocket
+        .manage(validators)
+        .manage(session_issuer)
+        .manage(mongo)
+        .mount(
+            "/",
+            routes![
+                get_my_location,
+                upload_my_location,
+                get_my_contacts,
+                pause_my_sharing,
+                resume_my_sharing,
+                stream_my_contacts,
+                get_my_contact_changes,
+                share_my_location,
+                unshare_my_location,
+                register_my_device,
+                unregister_my_device,
+                request_contact_location,
+                get_my_location_requests,
+                get_location_request,
+                create_my_webhook,
+                get_my_webhooks,
+                delete_my_webhook,
+                enable_my_webhook,
+                get_my_webhook_deliveries,
+                create_my_personal_access_token,
+                get_my_personal_access_tokens,
+                revoke_my_personal_access_token,
+                link_my_identity,
+                get_my_identities,
+                unlink_my_identity,
+                exchange_token,
+                revoke_token,
+                sign_out_everywhere,
+                get_user_identities,
+                sign_user_out_everywhere,
+                set_user_role,
+                get_key_set_ages,
+                get_token_cache_stats
+            ],
         )
-        .mount("/", routes![get_my_location, upload_my_location])
 }
 
 #[post("/my/location", data = "<location>")]
 async fn upload_my_location(
-    user_auth: Result<AuthenticatedUser, AuthError>,
+    user_auth: Result<RequireScope<scopes::LocationWrite>, AuthError>,
     mongo: State<'_, MongoManager>,
     location: Json<Location>,
 ) -> RouteResult<()> {
     // Early-returns if unable to auth the user.
     let my_user_id = user_auth?.id();
 
+    let ping = Ping::new_at_now(*location);
+    let my_user = mongo.update_user_location(&my_user_id, &ping).await?;
+
     mongo
-        .update_user_location(&my_user_id, *location)
+        .fulfill_location_requests(&my_user, ping.timestamp())
+        .await?;
+
+    // The location is already saved, so a webhook event that fails to queue
+    // doesn't fail the upload.
+    if let Err(mongo_err) = mongo
+        .enqueue_webhook_event(
+            &my_user_id,
+            WebhookEvent::PingUploaded {
+                user_id: my_user_id.clone(),
+                ping,
+            },
+        )
         .await
-        .to_route_result()
+    {
+        eprintln!(
+            "Failed to queue a webhook event for {}: {:?}",
+            my_user_id, mongo_err
+        );
+    }
+
+    ().to_route_result()
 }
 
 #[get("/my/location")]
 async fn get_my_location(
-    user_auth: Result<AuthenticatedUser, AuthError>,
+    user_auth: Result<RequireScope<scopes::LocationRead>, AuthError>,
     mongo: State<'_, MongoManager>,
 ) -> RouteResult<Contact> {
     // Early-returns if unable to auth the user.
@@ -53,3 +172,857 @@ async fn get_my_location(
 
     Contact::from(my_user).to_route_result()
 }
+
+#[get("/my/contacts")]
+async fn get_my_contacts(
+    user_auth: Result<RequireScope<scopes::ContactsRead>, AuthError>,
+    mongo: State<'_, MongoManager>,
+) -> RouteResult<Vec<Contact>> {
+    // Early-returns if unable to auth the user.
+    let my_user_id = user_auth?.id();
+
+    let my_ping = mongo.get_user_by_id(&my_user_id).await?.last_ping();
+
+    mongo
+        .get_users_sharing_to(&my_user_id)
+        .await?
+        .into_iter()
+        .map(|user| Contact::visible_to(&user, &my_user_id).relative_to(my_ping))
+        .collect::<Vec<_>>()
+        .to_route_result()
+}
+
+#[post("/my/pause", data = "<pause>")]
+async fn pause_my_sharing(
+    user_auth: Result<RequireScope<scopes::SharingWrite>, AuthError>,
+    mongo: State<'_, MongoManager>,
+    pause: Json<PauseRequest>,
+) -> RouteResult<()> {
+    // Early-returns if unable to auth the user.
+    let my_user_id = user_auth?.id();
+
+    if mongo
+        .pause_user_sharing(
+            &my_user_id,
+            pause.contact_id.as_deref(),
+            pause.visibility,
+            pause.until,
+        )
+        .await?
+    {
+        Ok(Json(()))
+    } else {
+        Err(ApiError::NotFound)
+    }
+}
+
+#[delete("/my/pause?<contact_id>")]
+async fn resume_my_sharing(
+    user_auth: Result<RequireScope<scopes::SharingWrite>, AuthError>,
+    mongo: State<'_, MongoManager>,
+    contact_id: Option<String>,
+) -> RouteResult<()> {
+    // Early-returns if unable to auth the user.
+    let my_user_id = user_auth?.id();
+
+    if mongo
+        .resume_user_sharing(&my_user_id, contact_id.as_deref())
+        .await?
+    {
+        Ok(Json(()))
+    } else {
+        Err(ApiError::NotFound)
+    }
+}
+
+/// Streams contacts' updates as Server-Sent Events for as long as the client
+/// stays connected, and a `contact_removed` event when one stops sharing. Browsers, which can't set headers on an `EventSource`, can
+/// authenticate with an `access_token` cookie or query parameter instead.
+#[get("/my/contacts/stream")]
+async fn stream_my_contacts(
+    user_auth: Result<StreamingUser, AuthError>,
+    mongo: State<'_, MongoManager>,
+) -> Result<EventStream, ApiError> {
+    // Early-returns if unable to auth the user.
+    let my_user_id = user_auth?.id_with_scope(Scope::ContactsRead)?;
+
+    // Subscribe before reading our own ping and contacts, so we can't miss
+    // an update that lands in between.
+    let updates = mongo.location_hub().subscribe();
+    let my_ping = mongo.get_user_by_id(&my_user_id).await?.last_ping();
+    let contacts = mongo.get_users_sharing_to(&my_user_id).await?;
+
+    let (sink, stream) = EventStream::new();
+    tokio::spawn(events::stream_contacts(
+        my_user_id, my_ping, contacts, updates, sink,
+    ));
+
+    Ok(stream)
+}
+
+/// Long-polls for contacts that changed after the `since` cursor. Responds as
+/// soon as there are any, or with an empty batch after `wait` seconds.
+#[get("/my/contacts/changes?<since>&<wait>")]
+async fn get_my_contact_changes(
+    user_auth: Result<RequireScope<scopes::ContactsRead>, AuthError>,
+    mongo: State<'_, MongoManager>,
+    since: Option<i64>,
+    wait: Option<u64>,
+) -> RouteResult<ContactChanges> {
+    const DEFAULT_WAIT_SECS: u64 = 30;
+    const MAX_WAIT_SECS: u64 = 60;
+
+    // Early-returns if unable to auth the user.
+    let my_user_id = user_auth?.id();
+
+    let since = since.unwrap_or(0);
+    let wait = Duration::from_secs(wait.unwrap_or(DEFAULT_WAIT_SECS).min(MAX_WAIT_SECS));
+
+    // Subscribe before querying, so we can't miss an update that lands in
+    // between.
+    let mut updates = mongo.location_hub().subscribe();
+    let my_ping = mongo.get_user_by_id(&my_user_id).await?.last_ping();
+
+    let deadline = Instant::now() + wait;
+
+    loop {
+        let changed = mongo
+            .get_users_sharing_to_changed_since(&my_user_id, since)
+            .await?;
+        let changes = ContactChanges::new(since, changed, &my_user_id, my_ping);
+
+        // Changes we can't see, e.g. uploads while paused for us, keep us
+        // waiting as if nothing had happened.
+        let remaining = deadline.saturating_duration_since(Instant::now());
+
+        if !changes.is_empty()
+            || remaining == Duration::from_secs(0)
+            || !events::wait_for_contact_update(&my_user_id, &mut updates, remaining).await
+        {
+            return changes.to_route_result();
+        }
+    }
+}
+
+#[post("/my/shares/<contact_id>")]
+async fn share_my_location(
+    user_auth: Result<RequireScope<scopes::SharingWrite>, AuthError>,
+    mongo: State<'_, MongoManager>,
+    contact_id: String,
+) -> RouteResult<()> {
+    // Early-returns if unable to auth the user.
+    let my_user_id = user_auth?.id();
+
+    if mongo.find_user_by_id(&contact_id).await?.is_none() {
+        return Err(ApiError::NotFound);
+    }
+
+    mongo.share_user_location(&my_user_id, &contact_id).await?;
+
+    // The share is already saved, so a webhook event that fails to queue
+    // doesn't fail the request.
+    if let Err(mongo_err) = mongo
+        .enqueue_webhook_event(
+            &my_user_id,
+            WebhookEvent::ShareChanged {
+                user_id: my_user_id.clone(),
+                contact_id,
+                shared: true,
+            },
+        )
+        .await
+    {
+        eprintln!(
+            "Failed to queue a webhook event for {}: {:?}",
+            my_user_id, mongo_err
+        );
+    }
+
+    ().to_route_result()
+}
+
+#[delete("/my/shares/<contact_id>")]
+async fn unshare_my_location(
+    user_auth: Result<RequireScope<scopes::SharingWrite>, AuthError>,
+    mongo: State<'_, MongoManager>,
+    contact_id: String,
+) -> RouteResult<()> {
+    // Early-returns if unable to auth the user.
+    let my_user_id = user_auth?.id();
+
+    mongo
+        .unshare_user_location(&my_user_id, &contact_id)
+        .await?;
+
+    // The unshare is already saved, so a webhook event that fails to queue
+    // doesn't fail the request.
+    if let Err(mongo_err) = mongo
+        .enqueue_webhook_event(
+            &my_user_id,
+            WebhookEvent::ShareChanged {
+                user_id: my_user_id.clone(),
+                contact_id,
+                shared: false,
+            },
+        )
+        .await
+    {
+        eprintln!(
+            "Failed to queue a webhook event for {}: {:?}",
+            my_user_id, mongo_err
+        );
+    }
+
+    ().to_route_result()
+}
+
+#[post("/my/devices", data = "<device>")]
+async fn register_my_device(
+    user_auth: Result<AuthenticatedUser, AuthError>,
+    mongo: State<'_, MongoManager>,
+    device: Json<DeviceRegistration>,
+) -> RouteResult<()> {
+    // Early-returns if unable to auth the user.
+    let my_user_id = user_auth?.signed_in_id()?;
+
+    if mongo
+        .register_user_device(&my_user_id, device.platform, &device.token)
+        .await?
+    {
+        Ok(Json(()))
+    } else {
+        Err(ApiError::NotFound)
+    }
+}
+
+#[delete("/my/devices/<token>")]
+async fn unregister_my_device(
+    user_auth: Result<AuthenticatedUser, AuthError>,
+    mongo: State<'_, MongoManager>,
+    token: String,
+) -> RouteResult<()> {
+    // Early-returns if unable to auth the user.
+    let my_user_id = user_auth?.signed_in_id()?;
+
+    if mongo.unregister_user_device(&my_user_id, &token).await? {
+        Ok(Json(()))
+    } else {
+        Err(ApiError::NotFound)
+    }
+}
+
+/// Asks a contact who shares with us for a fresh location. They're notified,
+/// and the request is fulfilled by their next location upload that we can
+/// see, so not while they've paused sharing with us.
+#[post("/my/contacts/<contact_id>/location-request")]
+async fn request_contact_location(
+    user_auth: Result<RequireScope<scopes::ContactsRead>, AuthError>,
+    mongo: State<'_, MongoManager>,
+    contact_id: String,
+) -> RouteResult<LocationRequestInfo> {
+    // Early-returns if unable to auth the user.
+    let my_user_id = user_auth?.id();
+
+    match mongo.find_user_by_id(&contact_id).await? {
+        Some(contact) if contact.is_shared_to(&my_user_id) => {}
+        _ => return Err(ApiError::NotFound),
+    }
+
+    let now = now_epoch_secs();
+
+    if !mongo
+        .start_location_request_cooldown(&my_user_id, &contact_id, now)
+        .await?
+    {
+        return Err(ApiError::TooManyRequests);
+    }
+
+    let request = LocationRequest::new(&my_user_id, &contact_id, now);
+    mongo.create_location_request(&request).await?;
+
+    let my_user = mongo.get_user_by_id(&my_user_id).await?;
+
+    mongo
+        .enqueue_notification(
+            &contact_id,
+            Notification::LocationRequested {
+                from_user_id: my_user_id,
+                from_display_name: String::from(my_user.display_name()),
+                request_id: request.id().to_hex(),
+            },
+        )
+        .await?;
+
+    LocationRequestInfo::at(&request, now).to_route_result()
+}
+
+/// Requests for our location that are still waiting on an upload.
+#[get("/my/location-requests")]
+async fn get_my_location_requests(
+    user_auth: Result<RequireScope<scopes::LocationRead>, AuthError>,
+    mongo: State<'_, MongoManager>,
+) -> RouteResult<Vec<LocationRequestInfo>> {
+    // Early-returns if unable to auth the user.
+    let my_user_id = user_auth?.id();
+
+    let now = now_epoch_secs();
+
+    mongo
+        .get_pending_location_requests(&my_user_id, now)
+        .await?
+        .iter()
+        .map(|request| LocationRequestInfo::at(request, now))
+        .collect::<Vec<_>>()
+        .to_route_result()
+}
+
+/// A location request's status. Only visible to its requester and target.
+#[get("/my/location-requests/<request_id>")]
+async fn get_location_request(
+    user_auth: Result<RequireScope<scopes::LocationRead>, AuthError>,
+    mongo: State<'_, MongoManager>,
+    request_id: String,
+) -> RouteResult<LocationRequestInfo> {
+    // Early-returns if unable to auth the user.
+    let my_user_id = user_auth?.id();
+
+    let request_id = parse_object_id(&request_id)?;
+
+    match mongo.get_location_request(&request_id).await? {
+        Some(request)
+            if request.requester_id() == my_user_id || request.target_id() == my_user_id =>
+        {
+            LocationRequestInfo::at(&request, now_epoch_secs()).to_route_result()
+        }
+        _ => Err(ApiError::NotFound),
+    }
+}
+
+/// Registers a webhook. The response is the only time the webhook's signing
+/// secret is revealed.
+#[post("/my/webhooks", data = "<registration>")]
+async fn create_my_webhook(
+    user_auth: Result<RequireScope<scopes::Webhooks>, AuthError>,
+    mongo: State<'_, MongoManager>,
+    registration: Json<WebhookRegistration>,
+) -> RouteResult<WebhookInfo> {
+    // Early-returns if unable to auth the user.
+    let my_user_id = user_auth?.id();
+
+    let registration = registration.into_inner();
+
+    if let Err(destination_err) = webhooks::check_destination(&registration.url).await {
+        eprintln!("Refused to register a webhook: {}", destination_err);
+
+        return Err(ApiError::BadRequest(
+            "Webhook URL must be http(s), and its host a public address",
+        ));
+    }
+
+    let webhook = Webhook::new(
+        &my_user_id,
+        registration.url,
+        webhooks::generate_secret(),
+        registration.events,
+        now_epoch_secs(),
+    );
+
+    mongo.create_webhook(&webhook).await?;
+
+    WebhookInfo::with_secret(&webhook).to_route_result()
+}
+
+#[get("/my/webhooks")]
+async fn get_my_webhooks(
+    user_auth: Result<RequireScope<scopes::Webhooks>, AuthError>,
+    mongo: State<'_, MongoManager>,
+) -> RouteResult<Vec<WebhookInfo>> {
+    // Early-returns if unable to auth the user.
+    let my_user_id = user_auth?.id();
+
+    mongo
+        .get_webhooks_for_owner(&my_user_id)
+        .await?
+        .iter()
+        .map(WebhookInfo::from)
+        .collect::<Vec<_>>()
+        .to_route_result()
+}
+
+#[delete("/my/webhooks/<webhook_id>")]
+async fn delete_my_webhook(
+    user_auth: Result<RequireScope<scopes::Webhooks>, AuthError>,
+    mongo: State<'_, MongoManager>,
+    webhook_id: String,
+) -> RouteResult<()> {
+    // Early-returns if unable to auth the user.
+    let my_user_id = user_auth?.id();
+
+    let webhook_id = parse_object_id(&webhook_id)?;
+
+    if mongo.delete_webhook(&my_user_id, &webhook_id).await? {
+        Ok(Json(()))
+    } else {
+        Err(ApiError::NotFound)
+    }
+}
+
+/// Re-enables a webhook that was disabled after too many failed deliveries.
+#[post("/my/webhooks/<webhook_id>/enable")]
+async fn enable_my_webhook(
+    user_auth: Result<RequireScope<scopes::Webhooks>, AuthError>,
+    mongo: State<'_, MongoManager>,
+    webhook_id: String,
+) -> RouteResult<()> {
+    // Early-returns if unable to auth the user.
+    let my_user_id = user_auth?.id();
+
+    let webhook_id = parse_object_id(&webhook_id)?;
+
+    if mongo.enable_webhook(&my_user_id, &webhook_id).await? {
+        Ok(Json(()))
+    } else {
+        Err(ApiError::NotFound)
+    }
+}
+
+/// The webhook's most recent deliveries, newest first.
+#[get("/my/webhooks/<webhook_id>/deliveries?<limit>")]
+async fn get_my_webhook_deliveries(
+    user_auth: Result<RequireScope<scopes::Webhooks>, AuthError>,
+    mongo: State<'_, MongoManager>,
+    webhook_id: String,
+    limit: Option<i64>,
+) -> RouteResult<Vec<WebhookDeliveryInfo>> {
+    const DEFAULT_LIMIT: i64 = 50;
+    const MAX_LIMIT: i64 = 200;
+
+    // Early-returns if unable to auth the user.
+    let my_user_id = user_auth?.id();
+
+    let webhook_id = parse_object_id(&webhook_id)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    mongo
+        .get_webhook_deliveries(&my_user_id, &webhook_id, limit)
+        .await?
+        .into_iter()
+        .map(WebhookDeliveryInfo::from)
+        .collect::<Vec<_>>()
+        .to_route_result()
+}
+
+/// Makes a personal access token, for a script or device to act as us within
+/// the token's scopes. The response is the only time the token is revealed.
+#[post("/my/tokens", data = "<request>")]
+async fn create_my_personal_access_token(
+    user_auth: Result<AuthenticatedUser, AuthError>,
+    mongo: State<'_, MongoManager>,
+    request: Json<PersonalAccessTokenRequest>,
+) -> RouteResult<PersonalAccessTokenInfo> {
+    // Early-returns if unable to auth the user.
+    let my_user_id = user_auth?.signed_in_id()?;
+
+    let request = request.into_inner();
+    let now = now_epoch_secs();
+
+    if request.name.trim().is_empty() {
+        return Err(ApiError::BadRequest("Token name must not be empty"));
+    }
+
+    if request.scopes.is_empty() {
+        return Err(ApiError::BadRequest("Token must have at least one scope"));
+    }
+
+    if matches!(request.expires_at, Some(expires_at) if expires_at <= now) {
+        return Err(ApiError::BadRequest("Token expiry must be in the future"));
+    }
+
+    let token = personal_access_token::generate();
+    let new_token = PersonalAccessToken::new(
+        &my_user_id,
+        request.name,
+        personal_access_token::hash(&token),
+        request.scopes,
+        now,
+        request.expires_at,
+    );
+
+    mongo.create_personal_access_token(&new_token).await?;
+
+    PersonalAccessTokenInfo::with_token(&new_token, token).to_route_result()
+}
+
+#[get("/my/tokens")]
+async fn get_my_personal_access_tokens(
+    user_auth: Result<AuthenticatedUser, AuthError>,
+    mongo: State<'_, MongoManager>,
+) -> RouteResult<Vec<PersonalAccessTokenInfo>> {
+    // Early-returns if unable to auth the user.
+    let my_user_id = user_auth?.signed_in_id()?;
+
+    mongo
+        .get_personal_access_tokens(&my_user_id)
+        .await?
+        .iter()
+        .map(PersonalAccessTokenInfo::from)
+        .collect::<Vec<_>>()
+        .to_route_result()
+}
+
+#[delete("/my/tokens/<token_id>")]
+async fn revoke_my_personal_access_token(
+    user_auth: Result<AuthenticatedUser, AuthError>,
+    mongo: State<'_, MongoManager>,
+    token_id: String,
+) -> RouteResult<()> {
+    // Early-returns if unable to auth the user.
+    let my_user_id = user_auth?.signed_in_id()?;
+
+    let token_id = parse_object_id(&token_id)?;
+
+    if mongo
+        .delete_personal_access_token(&my_user_id, &token_id)
+        .await?
+    {
+        Ok(Json(()))
+    } else {
+        Err(ApiError::NotFound)
+    }
+}
+
+/// Links an account with another authority to ours, so that signing in with
+/// either signs in as us. Takes an ID token from that authority, proving the
+/// account is the caller's. If the account has signed in on its own before,
+/// the user that made is merged into ours, as long as it has nothing to lose.
+#[post("/my/identities", data = "<link>")]
+async fn link_my_identity(
+    user_auth: Result<AuthenticatedUser, AuthError>,
+    validators: State<'_, ValidatorRegistry>,
+    session_issuer: State<'_, SessionIssuer>,
+    mongo: State<'_, MongoManager>,
+    link: Json<IdentityLink>,
+) -> RouteResult<IdentityInfo> {
+    // Early-returns if unable to auth the user.
+    let my_user_id = user_auth?.signed_in_id()?;
+
+    let identity = validators
+        .validate(&link.id_token)
+        .await
+        .map_err(AuthError::InvalidToken)?;
+
+    let now = now_epoch_secs();
+    let linked = mongo.link_identity(&identity, &my_user_id, now).await?;
+
+    if linked.user_id() == my_user_id {
+        return IdentityInfo::from(&linked).to_route_result();
+    }
+
+    let other_user_id = String::from(linked.user_id());
+
+    if !mongo.is_user_mergeable(&other_user_id).await? {
+        return Err(ApiError::Conflict(
+            "Identity is already linked to another user",
+        ));
+    }
+
+    let relinked = mongo
+        .relink_identity(&linked, &my_user_id, now)
+        .await?
+        .ok_or(ApiError::Conflict("Identity was changed while linking"))?;
+
+    // Nothing can sign in as the other user any more, so end their sessions
+    // rather than let them go on using it.
+    revocation::sign_out_everywhere(
+        &mongo,
+        &other_user_id,
+        now,
+        session_issuer.access_token_ttl(),
+    )
+    .await?;
+    mongo.delete_user(&other_user_id).await?;
+
+    IdentityInfo::from(&relinked).to_route_result()
+}
+
+#[get("/my/identities")]
+async fn get_my_identities(
+    user_auth: Result<AuthenticatedUser, AuthError>,
+    mongo: State<'_, MongoManager>,
+) -> RouteResult<Vec<IdentityInfo>> {
+    // Early-returns if unable to auth the user.
+    let my_user_id = user_auth?.signed_in_id()?;
+
+    mongo
+        .get_identities(&my_user_id)
+        .await?
+        .iter()
+        .map(IdentityInfo::from)
+        .collect::<Vec<_>>()
+        .to_route_result()
+}
+
+/// Unlinks one of our identities. The last one can't be unlinked, since we'd
+/// have no way left to sign in.
+#[delete("/my/identities/<identity_id>")]
+async fn unlink_my_identity(
+    user_auth: Result<AuthenticatedUser, AuthError>,
+    mongo: State<'_, MongoManager>,
+    identity_id: String,
+) -> RouteResult<()> {
+    // Early-returns if unable to auth the user.
+    let my_user_id = user_auth?.signed_in_id()?;
+
+    let identity_id = parse_object_id(&identity_id)?;
+    let identities = mongo.get_identities(&my_user_id).await?;
+
+    if !identities
+        .iter()
+        .any(|identity| identity.id() == &identity_id)
+    {
+        return Err(ApiError::NotFound);
+    }
+
+    if identities.len() == 1 {
+        return Err(ApiError::BadRequest("Can't unlink the only identity"));
+    }
+
+    if mongo.unlink_identity(&my_user_id, &identity_id).await? {
+        Ok(Json(()))
+    } else {
+        Err(ApiError::NotFound)
+    }
+}
+
+/// Exchanges an authority's ID token, after sign-in, or a refresh token for
+/// one of our access tokens and a new refresh token. Each refresh token can
+/// only be exchanged once: presenting one again revokes its session.
+#[post("/auth/token", data = "<request>")]
+async fn exchange_token(
+    validators: State<'_, ValidatorRegistry>,
+    session_issuer: State<'_, SessionIssuer>,
+    mongo: State<'_, MongoManager>,
+    request: Json<TokenRequest>,
+) -> RouteResult<TokenResponse> {
+    let now = now_epoch_secs();
+    let refresh_token = session::generate_refresh_token();
+    let refresh_token_hash = session::hash_refresh_token(&refresh_token);
+    let expires_at = session_issuer.refresh_token_expiry(now);
+
+    let session = match request.into_inner() {
+        TokenRequest::IdToken { id_token } => {
+            let identity = validators
+                .validate(&id_token)
+                .await
+                .map_err(AuthError::InvalidToken)?;
+            let user_id = mongo.get_or_create_user_id(&identity, now).await?;
+
+            if mongo
+                .is_token_revoked(&user_id, None, None, openid::issued_at(&id_token), now)
+                .await?
+            {
+                return Err(AuthError::InvalidToken(TokenError::Revoked).into());
+            }
+
+            let session = Session::new(&user_id, refresh_token_hash, now, expires_at);
+            mongo.create_session(&session).await?;
+
+            session
+        }
+        TokenRequest::RefreshToken {
+            refresh_token: presented,
+        } => {
+            match mongo
+                .rotate_refresh_token(
+                    &session::hash_refresh_token(&presented),
+                    &refresh_token_hash,
+                    now,
+                    expires_at,
+                )
+                .await?
+            {
+                RefreshTokenRotation::Rotated(session) => session,
+                RefreshTokenRotation::Reused(session) => {
+                    mongo
+                        .revoke(&revocation::revoke_session_tokens(
+                            &session.id().to_hex(),
+                            now,
+                            session_issuer.access_token_ttl(),
+                        ))
+                        .await?;
+
+                    return Err(AuthError::RefreshTokenReused.into());
+                }
+                RefreshTokenRotation::Invalid => return Err(AuthError::InvalidRefreshToken.into()),
+            }
+        }
+    };
+
+    let access_token =
+        session_issuer.issue_access_token(session.user_id(), &session.id().to_hex(), now);
+
+    TokenResponse::new(
+        access_token,
+        session_issuer.access_token_ttl(),
+        refresh_token,
+    )
+    .to_route_result()
+}
+
+/// Revokes one of our access tokens or a refresh token, in the style of
+/// OAuth 2.0 token revocation. Revoking a refresh token signs its session out,
+/// including its access tokens. Succeeds even if the token was already
+/// invalid, since there's nothing more for the client to do.
+#[post("/auth/revoke", data = "<request>")]
+async fn revoke_token(
+    session_issuer: State<'_, SessionIssuer>,
+    mongo: State<'_, MongoManager>,
+    request: Json<RevocationRequest>,
+) -> RouteResult<()> {
+    let token = request.into_inner().token;
+    let now = now_epoch_secs();
+
+    if session_issuer.issued(&token) {
+        if let Ok(claims) = session_issuer.validate(&token) {
+            mongo
+                .revoke(&Revocation::token(&claims.jti, claims.exp))
+                .await?;
+        }
+    } else if let Some(session) = mongo
+        .revoke_session_by_refresh_token(&session::hash_refresh_token(&token), now)
+        .await?
+    {
+        mongo
+            .revoke(&revocation::revoke_session_tokens(
+                &session.id().to_hex(),
+                now,
+                session_issuer.access_token_ttl(),
+            ))
+            .await?;
+    }
+
+    ().to_route_result()
+}
+
+/// Signs the user out on every device: revokes all their sessions, and every
+/// access and ID token they were issued until now. Personal access tokens
+/// keep working, and are revoked separately.
+#[post("/my/sign-out-everywhere")]
+async fn sign_out_everywhere(
+    user_auth: Result<AuthenticatedUser, AuthError>,
+    session_issuer: State<'_, SessionIssuer>,
+    mongo: State<'_, MongoManager>,
+) -> RouteResult<()> {
+    // Early-returns if unable to auth the user.
+    let my_user_id = user_auth?.signed_in_id()?;
+
+    revocation::sign_out_everywhere(
+        &mongo,
+        &my_user_id,
+        now_epoch_secs(),
+        session_issuer.access_token_ttl(),
+    )
+    .await
+    .to_route_result()
+}
+
+/// Lists the identities linked to any user, for support.
+#[get("/admin/users/<user_id>/identities")]
+async fn get_user_identities(
+    staff_auth: Result<RequireRole<roles::Support>, AuthError>,
+    mongo: State<'_, MongoManager>,
+    user_id: String,
+) -> RouteResult<Vec<IdentityInfo>> {
+    // Early-returns if the caller isn't support.
+    staff_auth?;
+
+    mongo
+        .get_identities(&user_id)
+        .await?
+        .iter()
+        .map(IdentityInfo::from)
+        .collect::<Vec<_>>()
+        .to_route_result()
+}
+
+/// Signs any user out on every device, for support, e.g. when their device
+/// is lost.
+#[post("/admin/users/<user_id>/sign-out-everywhere")]
+async fn sign_user_out_everywhere(
+    staff_auth: Result<RequireRole<roles::Support>, AuthError>,
+    session_issuer: State<'_, SessionIssuer>,
+    mongo: State<'_, MongoManager>,
+    user_id: String,
+) -> RouteResult<()> {
+    // Early-returns if the caller isn't support.
+    let staff_id = staff_auth?.id();
+
+    eprintln!("{} is signing {} out everywhere", staff_id, user_id);
+
+    revocation::sign_out_everywhere(
+        &mongo,
+        &user_id,
+        now_epoch_secs(),
+        session_issuer.access_token_ttl(),
+    )
+    .await
+    .to_route_result()
+}
+
+/// Grants any user a role, or takes it away by granting `user`. Only for
+/// admins.
+#[put("/admin/users/<user_id>/role", data = "<assignment>")]
+async fn set_user_role(
+    admin_auth: Result<RequireRole<roles::Admin>, AuthError>,
+    mongo: State<'_, MongoManager>,
+    user_id: String,
+    assignment: Json<RoleAssignment>,
+) -> RouteResult<()> {
+    // Early-returns if the caller isn't an admin.
+    let admin_id = admin_auth?.id();
+
+    if admin_id == user_id {
+        return Err(ApiError::BadRequest("Admins can't change their own role"));
+    }
+
+    let role = assignment.into_inner().role;
+
+    eprintln!("{} is making {} a {}", admin_id, user_id, role.as_str());
+
+    if mongo.set_user_role(&user_id, role).await? {
+        Ok(Json(()))
+    } else {
+        Err(ApiError::NotFound)
+    }
+}
+
+/// Reports how long ago each sign-in authority's keys were fetched, for
+/// monitoring. Keys that stop being refreshed grow stale here first.
+#[get("/health/auth/keys")]
+async fn get_key_set_ages(validators: State<'_, ValidatorRegistry>) -> RouteResult<Vec<KeySetAge>> {
+    validators
+        .key_set_ages()
+        .into_iter()
+        .map(|(issuer, age)| KeySetAge::new(issuer, age))
+        .collect::<Vec<_>>()
+        .to_route_result()
+}
+
+/// Reports each sign-in authority's verified-token cache hit rate, for
+/// monitoring.
+#[get("/health/auth/token-cache")]
+async fn get_token_cache_stats(
+    validators: State<'_, ValidatorRegistry>,
+) -> RouteResult<Vec<TokenCacheInfo>> {
+    validators
+        .token_cache_stats()
+        .into_iter()
+        .map(|(issuer, stats)| TokenCacheInfo::new(issuer, stats))
+        .collect::<Vec<_>>()
+        .to_route_result()
+}
+
+/// IDs that don't parse can't name anything, so they're treated as not found.
+fn parse_object_id(id: &str) -> Result<ObjectId, ApiError> {
+    ObjectId::with_string(id).map_err(|_| ApiError::NotFound)
+}
@@ -7,14 +7,59 @@ pub struct Contact {
     id: String,
     display_name: String,
     last_ping: Option<Ping>,
+    /// Seconds since `last_ping` was taken, as of this response.
+    last_ping_age_secs: Option<i64>,
+    /// Great-circle distance from the caller to `last_ping`, in meters. Only
+    /// present if the caller has a recent ping of their own.
+    distance_meters: Option<f64>,
+    /// Initial bearing from the caller to `last_ping`, in degrees clockwise
+    /// from true north. Present under the same conditions as `distance_meters`.
+    bearing_degrees: Option<f64>,
 }
 
-impl From<User> for Contact {
-    fn from(stored_user: User) -> Self {
+impl Contact {
+    /// How old the caller's own ping may be, in seconds, for us to still
+    /// compute distance and bearing from it.
+    const CALLER_PING_MAX_AGE_SECS: i64 = 15 * 60;
+
+    /// The view of `stored_user` that the user with ID `viewer_id` is allowed
+    /// to see, which accounts for any sharing pauses.
+    pub fn visible_to(stored_user: &User, viewer_id: &str) -> Self {
+        Contact::with_ping(stored_user, stored_user.visible_ping_for(viewer_id))
+    }
+
+    fn with_ping(stored_user: &User, last_ping: Option<Ping>) -> Self {
         Contact {
             id: String::from(stored_user.id()),
             display_name: String::from(stored_user.display_name()),
-            last_ping: stored_user.last_ping().clone(),
+            last_ping,
+            last_ping_age_secs: last_ping.map(|ping| ping.age_secs()),
+            distance_meters: None,
+            bearing_degrees: None,
+        }
+    }
+
+    /// Annotates this contact with distance and bearing relative to the
+    /// caller's last ping, if both pings are known and the caller's is recent.
+    pub fn relative_to(mut self, caller_ping: Option<Ping>) -> Self {
+        let caller_ping =
+            caller_ping.filter(|ping| ping.age_secs() <= Contact::CALLER_PING_MAX_AGE_SECS);
+
+        if let (Some(caller_ping), Some(contact_ping)) = (caller_ping, self.last_ping) {
+            let (from, to) = (caller_ping.location(), contact_ping.location());
+
+            self.distance_meters = Some(from.distance_to(&to));
+            self.bearing_degrees = Some(from.bearing_to(&to));
         }
+
+        self
+    }
+}
+
+impl From<User> for Contact {
+    /// The unrestricted view of `stored_user`, e.g. for showing a user their
+    /// own location.
+    fn from(stored_user: User) -> Self {
+        Contact::with_ping(&stored_user, stored_user.last_ping())
     }
 }
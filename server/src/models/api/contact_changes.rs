@@ -0,0 +1,47 @@
+use serde::Serialize;
+
+use crate::models::{api::Contact, common::Ping, storage::User};
+
+/// A batch of contacts that changed since a client-provided cursor.
+#[derive(Serialize)]
+pub struct ContactChanges {
+    /// Pass this back as `since` to get only changes after this batch.
+    cursor: i64,
+    /// The contacts that changed, as the caller is allowed to see them.
+    contacts: Vec<Contact>,
+}
+
+impl ContactChanges {
+    /// Builds the batch of `changed_users` as seen by the user with ID
+    /// `viewer_id`, leaving out those whose changes since `since` they can't
+    /// see. The new cursor is the latest visible change in the batch, or
+    /// `since` if there were none.
+    pub fn new(
+        since: i64,
+        changed_users: Vec<User>,
+        viewer_id: &str,
+        viewer_ping: Option<Ping>,
+    ) -> Self {
+        let visible_changes = changed_users
+            .iter()
+            .map(|user| (user, user.visible_change_seq(viewer_id)))
+            .filter(|(_, change_seq)| *change_seq > since)
+            .collect::<Vec<_>>();
+
+        let cursor = visible_changes
+            .iter()
+            .map(|(_, change_seq)| *change_seq)
+            .fold(since, i64::max);
+
+        let contacts = visible_changes
+            .iter()
+            .map(|(user, _)| Contact::visible_to(user, viewer_id).relative_to(viewer_ping))
+            .collect();
+
+        Self { cursor, contacts }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.contacts.is_empty()
+    }
+}
@@ -0,0 +1,10 @@
+use serde::Deserialize;
+
+use crate::models::storage::DevicePlatform;
+
+#[derive(Deserialize)]
+pub struct DeviceRegistration {
+    pub platform: DevicePlatform,
+    /// The platform's push token for the device.
+    pub token: String,
+}
@@ -1,10 +1,15 @@
 use rocket::{
-    http::Status,
+    http::{Header, Status},
     response::{Responder, Result as ResponderResult},
     Request,
 };
+use rocket_contrib::json::Json;
+use serde::Serialize;
 
-use crate::{auth::AuthError, storage::MongoError};
+use crate::{
+    auth::{openid::TokenError, AuthError},
+    storage::MongoError,
+};
 
 /// An enum wrapping sub-error types and mapping them to an HTTP status code,
 /// to simplify returning errors from a route handler.
@@ -26,31 +31,279 @@ use crate::{auth::AuthError, storage::MongoError};
 pub enum ApiError {
     Auth(AuthError),
     Mongo(MongoError),
+    /// The request was well-formed, but asked for something invalid.
+    BadRequest(&'static str),
+    NotFound,
+    /// The request conflicts with something that already exists.
+    Conflict(&'static str),
+    /// The caller has been making a request too often, and should back off.
+    TooManyRequests,
 }
 
 impl<'r, 'o: 'r> Responder<'r, 'o> for ApiError {
     fn respond_to(self, req: &'r Request<'_>) -> ResponderResult<'o> {
         match self {
-            ApiError::Auth(AuthError::MissingAuthHeader) => {
-                eprintln!("Got an auth error: missing auth header");
+            ApiError::Auth(auth_err) => return respond_to_auth_error(auth_err, req),
+            ApiError::Mongo(mongo_err) => {
+                eprintln!("Got a Mongo error: {:?}", mongo_err);
 
-                Status::BadRequest
+                Status::InternalServerError
             }
-            ApiError::Auth(auth_err) => {
-                eprintln!("Got an auth error: {:?}", auth_err);
+            ApiError::BadRequest(reason) => {
+                eprintln!("Got a bad request: {}", reason);
 
-                Status::Unauthorized
+                Status::BadRequest
             }
-            ApiError::Mongo(mongo_err) => {
-                eprintln!("Got a Mongo error: {:?}", mongo_err);
+            ApiError::NotFound => Status::NotFound,
+            ApiError::Conflict(reason) => {
+                eprintln!("Got a conflicting request: {}", reason);
 
-                Status::InternalServerError
+                Status::Conflict
             }
+            ApiError::TooManyRequests => Status::TooManyRequests,
         }
         .respond_to(req)
     }
 }
 
+/// The body of a response to a failure to authenticate.
+#[derive(Serialize)]
+struct AuthErrorBody {
+    /// The RFC 6750 error code, if the request carried a token at all.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'static str>,
+    error_description: String,
+    /// Why exactly authentication failed, e.g. `expired`.
+    reason: &'static str,
+    /// Whether refreshing the token could help, or the user must sign in
+    /// again.
+    refreshable: bool,
+    /// The scope the token lacks, if that's why.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    required_scope: Option<&'static str>,
+    /// The role the user lacks, if that's why.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    required_role: Option<&'static str>,
+}
+
+/// Responds to a failure to authenticate as RFC 6750 describes, with a
+/// `WWW-Authenticate` challenge and a JSON body saying why, so clients know
+/// whether to refresh their token or sign in again.
+fn respond_to_auth_error<'r, 'o: 'r>(
+    auth_err: AuthError,
+    req: &'r Request<'_>,
+) -> ResponderResult<'o> {
+    let (status, challenge, body) = match auth_err {
+        AuthError::FailedToGetJwtValidator => {
+            eprintln!("Got an auth error: no validators are being managed");
+
+            return Status::InternalServerError.respond_to(req);
+        }
+        AuthError::UserLookupFailed(mongo_err) => {
+            eprintln!(
+                "Got a Mongo error looking up a token's user: {:?}",
+                mongo_err
+            );
+
+            return Status::InternalServerError.respond_to(req);
+        }
+        AuthError::MissingAuthHeader => {
+            eprintln!("Got an auth error: missing auth header");
+
+            // With no token to fault, the challenge carries no error code.
+            let body = AuthErrorBody {
+                error: None,
+                error_description: String::from("no bearer token was provided"),
+                reason: "missing_token",
+                refreshable: false,
+                required_scope: None,
+                required_role: None,
+            };
+
+            (Status::Unauthorized, Some(String::from("Bearer")), body)
+        }
+        AuthError::UnsupportedAuthScheme(_) | AuthError::MalformedAuthHeader => {
+            eprintln!("Got an auth error: {:?}", auth_err);
+
+            let description = match &auth_err {
+                AuthError::UnsupportedAuthScheme(scheme) => {
+                    format!("the {} scheme is not supported, use Bearer", scheme)
+                }
+                _ => String::from("the Bearer header must carry exactly one token"),
+            };
+            let challenge = format!(
+                "Bearer error=\"invalid_request\", error_description=\"{}\"",
+                quotable(&description)
+            );
+            let body = AuthErrorBody {
+                error: Some("invalid_request"),
+                error_description: description,
+                reason: "malformed_auth_header",
+                refreshable: false,
+                required_scope: None,
+                required_role: None,
+            };
+
+            (Status::BadRequest, Some(challenge), body)
+        }
+        AuthError::InvalidToken(token_err @ TokenError::KeyFetchFailed) => {
+            eprintln!("Got an auth error: {}", token_err);
+
+            // Not the token's fault, so don't tell the client to replace it.
+            let body = AuthErrorBody {
+                error: None,
+                error_description: token_err.to_string(),
+                reason: token_err.reason(),
+                refreshable: false,
+                required_scope: None,
+                required_role: None,
+            };
+
+            (Status::ServiceUnavailable, None, body)
+        }
+        AuthError::InvalidToken(token_err) => {
+            eprintln!("Got an auth error: {}", token_err);
+
+            let description = token_err.to_string();
+            let challenge = format!(
+                "Bearer error=\"invalid_token\", error_description=\"{}\"",
+                quotable(&description)
+            );
+            let body = AuthErrorBody {
+                error: Some("invalid_token"),
+                error_description: description,
+                reason: token_err.reason(),
+                refreshable: token_err.is_refreshable(),
+                required_scope: None,
+                required_role: None,
+            };
+
+            (Status::Unauthorized, Some(challenge), body)
+        }
+        AuthError::InvalidPersonalAccessToken => {
+            eprintln!("Got an auth error: {:?}", auth_err);
+
+            let description = "the personal access token is invalid, expired or revoked";
+            let challenge = format!(
+                "Bearer error=\"invalid_token\", error_description=\"{}\"",
+                description
+            );
+            let body = AuthErrorBody {
+                error: Some("invalid_token"),
+                error_description: String::from(description),
+                reason: "invalid_personal_access_token",
+                refreshable: false,
+                required_scope: None,
+                required_role: None,
+            };
+
+            (Status::Unauthorized, Some(challenge), body)
+        }
+        AuthError::MissingScope(_) | AuthError::SignInRequired => {
+            eprintln!("Got an auth error: {:?}", auth_err);
+
+            let required_scope = match &auth_err {
+                AuthError::MissingScope(scope) => Some(scope.as_str()),
+                _ => None,
+            };
+            let (description, challenge, reason) = match auth_err {
+                AuthError::MissingScope(scope) => {
+                    let description = format!(
+                        "the personal access token lacks the {} scope",
+                        scope.as_str()
+                    );
+                    let challenge = format!(
+                        "Bearer error=\"insufficient_scope\", scope=\"{}\", error_description=\"{}\"",
+                        scope.as_str(),
+                        description
+                    );
+
+                    (description, challenge, "insufficient_scope")
+                }
+                _ => {
+                    let description =
+                        String::from("personal access tokens can't be used here, sign in instead");
+                    let challenge = format!(
+                        "Bearer error=\"insufficient_scope\", error_description=\"{}\"",
+                        description
+                    );
+
+                    (description, challenge, "sign_in_required")
+                }
+            };
+            let body = AuthErrorBody {
+                error: Some("insufficient_scope"),
+                error_description: description,
+                reason,
+                refreshable: false,
+                required_scope,
+                required_role: None,
+            };
+
+            (Status::Forbidden, Some(challenge), body)
+        }
+        AuthError::MissingRole(role) => {
+            eprintln!("Got an auth error: {:?}", auth_err);
+
+            // The token is fine, and another won't help, so there's no
+            // challenge.
+            let body = AuthErrorBody {
+                error: None,
+                error_description: format!("this requires the {} role", role.as_str()),
+                reason: "insufficient_role",
+                refreshable: false,
+                required_scope: None,
+                required_role: Some(role.as_str()),
+            };
+
+            (Status::Forbidden, None, body)
+        }
+        AuthError::InvalidRefreshToken | AuthError::RefreshTokenReused => {
+            eprintln!("Got an auth error: {:?}", auth_err);
+
+            // The token endpoint's errors are OAuth's, not RFC 6750's, so
+            // there's no challenge.
+            let (description, reason) = match auth_err {
+                AuthError::RefreshTokenReused => (
+                    "the refresh token was already used, so its session was revoked",
+                    "refresh_token_reused",
+                ),
+                _ => (
+                    "the refresh token is invalid or expired",
+                    "invalid_refresh_token",
+                ),
+            };
+            let body = AuthErrorBody {
+                error: Some("invalid_grant"),
+                error_description: String::from(description),
+                reason,
+                refreshable: false,
+                required_scope: None,
+                required_role: None,
+            };
+
+            (Status::BadRequest, None, body)
+        }
+    };
+
+    let mut response = Json(body).respond_to(req)?;
+    response.set_status(status);
+
+    if let Some(challenge) = challenge {
+        response.set_header(Header::new("WWW-Authenticate", challenge));
+    }
+
+    Ok(response)
+}
+
+/// `text` with only the characters RFC 6750 allows in a quoted
+/// `error_description`, since it can contain parts of the token.
+fn quotable(text: &str) -> String {
+    text.chars()
+        .filter(|c| matches!(c, ' '..='~') && *c != '"' && *c != '\\')
+        .collect()
+}
+
 impl From<AuthError> for ApiError {
     fn from(err: AuthError) -> Self {
         ApiError::Auth(err)
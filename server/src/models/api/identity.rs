@@ -0,0 +1,29 @@
+use serde::{Deserialize, Serialize};
+
+use crate::models::storage::Identity;
+
+#[derive(Deserialize)]
+pub struct IdentityLink {
+    /// An ID token from the authority whose account to link, proving it's
+    /// the caller's.
+    pub id_token: String,
+}
+
+#[derive(Serialize)]
+pub struct IdentityInfo {
+    id: String,
+    issuer: String,
+    subject: String,
+    linked_at: i64,
+}
+
+impl From<&Identity> for IdentityInfo {
+    fn from(identity: &Identity) -> Self {
+        Self {
+            id: identity.id().to_hex(),
+            issuer: String::from(identity.issuer()),
+            subject: String::from(identity.subject()),
+            linked_at: identity.linked_at(),
+        }
+    }
+}
@@ -0,0 +1,21 @@
+use std::time::Duration;
+
+use serde::Serialize;
+
+/// How stale an authority's signing keys are, for monitoring.
+#[derive(Serialize)]
+pub struct KeySetAge {
+    issuer: String,
+    /// Seconds since the keys were fetched. Absent if they never have been,
+    /// in which case nobody can sign in with the authority.
+    age_secs: Option<u64>,
+}
+
+impl KeySetAge {
+    pub fn new(issuer: String, age: Option<Duration>) -> Self {
+        Self {
+            issuer,
+            age_secs: age.map(|age| age.as_secs()),
+        }
+    }
+}
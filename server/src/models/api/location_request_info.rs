@@ -0,0 +1,29 @@
+use serde::Serialize;
+
+use crate::models::storage::{LocationRequest, LocationRequestStatus};
+
+#[derive(Serialize)]
+pub struct LocationRequestInfo {
+    id: String,
+    requester_id: String,
+    target_id: String,
+    status: LocationRequestStatus,
+    created_at: i64,
+    expires_at: i64,
+    fulfilled_at: Option<i64>,
+}
+
+impl LocationRequestInfo {
+    /// Views `request` as of `now`, so lapsed requests show as expired.
+    pub fn at(request: &LocationRequest, now: i64) -> Self {
+        Self {
+            id: request.id().to_hex(),
+            requester_id: String::from(request.requester_id()),
+            target_id: String::from(request.target_id()),
+            status: request.status_at(now),
+            created_at: request.created_at(),
+            expires_at: request.expires_at(),
+            fulfilled_at: request.fulfilled_at(),
+        }
+    }
+}
@@ -1,5 +1,27 @@
 mod contact;
+mod contact_changes;
+mod device_registration;
 mod error;
+mod identity;
+mod key_set_age;
+mod location_request_info;
+mod pause_request;
+mod personal_access_token;
+mod role_assignment;
+mod token_cache_info;
+mod token_exchange;
+mod webhook;
 
 pub use contact::Contact;
+pub use contact_changes::ContactChanges;
+pub use device_registration::DeviceRegistration;
 pub use error::ApiError;
+pub use identity::{IdentityInfo, IdentityLink};
+pub use key_set_age::KeySetAge;
+pub use location_request_info::LocationRequestInfo;
+pub use pause_request::PauseRequest;
+pub use personal_access_token::{PersonalAccessTokenInfo, PersonalAccessTokenRequest};
+pub use role_assignment::RoleAssignment;
+pub use token_cache_info::TokenCacheInfo;
+pub use token_exchange::{RevocationRequest, TokenRequest, TokenResponse};
+pub use webhook::{WebhookDeliveryInfo, WebhookInfo, WebhookRegistration};
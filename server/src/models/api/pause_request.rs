@@ -0,0 +1,14 @@
+use serde::Deserialize;
+
+use crate::models::storage::PausedVisibility;
+
+#[derive(Deserialize)]
+pub struct PauseRequest {
+    /// The contact to pause sharing to. Pauses sharing to everyone if absent.
+    pub contact_id: Option<String>,
+    /// What the affected contacts see while paused.
+    pub visibility: PausedVisibility,
+    /// When to automatically resume sharing, in epoch-seconds. Pauses until
+    /// explicitly resumed if absent.
+    pub until: Option<i64>,
+}
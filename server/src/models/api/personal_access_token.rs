@@ -0,0 +1,54 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{auth::Scope, models::storage::PersonalAccessToken};
+
+#[derive(Deserialize)]
+pub struct PersonalAccessTokenRequest {
+    /// What to call the token, e.g. after the device that will use it.
+    pub name: String,
+    /// What the token may be used for. Must not be empty.
+    pub scopes: HashSet<Scope>,
+    /// When the token should stop working, in epoch-seconds. Never, if
+    /// absent.
+    pub expires_at: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct PersonalAccessTokenInfo {
+    id: String,
+    name: String,
+    scopes: HashSet<Scope>,
+    created_at: i64,
+    expires_at: Option<i64>,
+    /// Accurate to within a minute.
+    last_used_at: Option<i64>,
+    /// The token itself. Only revealed when it's first created.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
+}
+
+impl PersonalAccessTokenInfo {
+    /// Includes the token itself, for the response to creating it.
+    pub fn with_token(personal_access_token: &PersonalAccessToken, token: String) -> Self {
+        Self {
+            token: Some(token),
+            ..Self::from(personal_access_token)
+        }
+    }
+}
+
+impl From<&PersonalAccessToken> for PersonalAccessTokenInfo {
+    fn from(personal_access_token: &PersonalAccessToken) -> Self {
+        Self {
+            id: personal_access_token.id().to_hex(),
+            name: String::from(personal_access_token.name()),
+            scopes: personal_access_token.scopes().clone(),
+            created_at: personal_access_token.created_at(),
+            expires_at: personal_access_token.expires_at(),
+            last_used_at: personal_access_token.last_used_at(),
+            token: None,
+        }
+    }
+}
@@ -0,0 +1,8 @@
+use serde::Deserialize;
+
+use crate::auth::Role;
+
+#[derive(Deserialize)]
+pub struct RoleAssignment {
+    pub role: Role,
+}
@@ -0,0 +1,26 @@
+use serde::Serialize;
+
+use crate::auth::openid::TokenCacheStats;
+
+/// How well an authority's verified-token cache is working, for monitoring.
+#[derive(Serialize)]
+pub struct TokenCacheInfo {
+    issuer: String,
+    hits: u64,
+    misses: u64,
+    /// The fraction of lookups that hit. Absent until there have been any.
+    hit_rate: Option<f64>,
+    entries: usize,
+}
+
+impl TokenCacheInfo {
+    pub fn new(issuer: String, stats: TokenCacheStats) -> Self {
+        Self {
+            issuer,
+            hits: stats.hits,
+            misses: stats.misses,
+            hit_rate: stats.hit_rate(),
+            entries: stats.entries,
+        }
+    }
+}
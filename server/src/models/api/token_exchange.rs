@@ -0,0 +1,41 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// A request for an access token, in the style of an OAuth 2.0 token
+/// request: either an authority's ID token after sign-in, or a refresh token
+/// from an earlier exchange.
+#[derive(Deserialize)]
+#[serde(tag = "grant_type", rename_all = "snake_case")]
+pub enum TokenRequest {
+    IdToken { id_token: String },
+    RefreshToken { refresh_token: String },
+}
+
+/// A request to revoke a token, in the style of OAuth 2.0 token revocation:
+/// either one of our access tokens or a refresh token.
+#[derive(Deserialize)]
+pub struct RevocationRequest {
+    pub token: String,
+}
+
+#[derive(Serialize)]
+pub struct TokenResponse {
+    access_token: String,
+    token_type: &'static str,
+    /// Seconds until `access_token` expires.
+    expires_in: u64,
+    /// Exchange this for the next access token. It can only be used once.
+    refresh_token: String,
+}
+
+impl TokenResponse {
+    pub fn new(access_token: String, expires_in: Duration, refresh_token: String) -> Self {
+        Self {
+            access_token,
+            token_type: "Bearer",
+            expires_in: expires_in.as_secs(),
+            refresh_token,
+        }
+    }
+}
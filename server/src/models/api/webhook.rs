@@ -0,0 +1,76 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::{
+    common::{WebhookEvent, WebhookEventKind},
+    storage::{DeliveryStatus, Webhook, WebhookDelivery},
+};
+
+#[derive(Deserialize)]
+pub struct WebhookRegistration {
+    /// Where to POST events. Must be `http` or `https`.
+    pub url: String,
+    /// The kinds of events to deliver.
+    pub events: HashSet<WebhookEventKind>,
+}
+
+#[derive(Serialize)]
+pub struct WebhookInfo {
+    id: String,
+    url: String,
+    events: HashSet<WebhookEventKind>,
+    disabled: bool,
+    consecutive_failures: i32,
+    /// The signing secret. Only revealed when the webhook is first created.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    secret: Option<String>,
+}
+
+impl WebhookInfo {
+    /// Includes the webhook's signing secret, for the response to creating it.
+    pub fn with_secret(webhook: &Webhook) -> Self {
+        Self {
+            secret: Some(String::from(webhook.secret())),
+            ..Self::from(webhook)
+        }
+    }
+}
+
+impl From<&Webhook> for WebhookInfo {
+    fn from(webhook: &Webhook) -> Self {
+        Self {
+            id: webhook.id().to_hex(),
+            url: String::from(webhook.url()),
+            events: webhook.events().clone(),
+            disabled: webhook.is_disabled(),
+            consecutive_failures: webhook.consecutive_failures(),
+            secret: None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct WebhookDeliveryInfo {
+    id: String,
+    event: WebhookEvent,
+    status: DeliveryStatus,
+    attempts: i32,
+    last_status_code: Option<i32>,
+    last_error: Option<String>,
+    created_at: i64,
+}
+
+impl From<WebhookDelivery> for WebhookDeliveryInfo {
+    fn from(delivery: WebhookDelivery) -> Self {
+        Self {
+            id: delivery.id().to_hex(),
+            event: delivery.event().clone(),
+            status: delivery.status(),
+            attempts: delivery.attempts(),
+            last_status_code: delivery.last_status_code(),
+            last_error: delivery.last_error().map(String::from),
+            created_at: delivery.created_at(),
+        }
+    }
+}
@@ -0,0 +1,162 @@
+//! Great-circle geometry on a spherical Earth. Accurate to within ~0.5% of
+//! the ellipsoidal answer, which is plenty for showing people how far away
+//! their friends are.
+
+use super::Location;
+
+/// Mean Earth radius, in meters (IUGG).
+pub const EARTH_RADIUS_METERS: f64 = 6_371_008.8;
+
+/// Great-circle distance between `from` and `to`, in meters, via the
+/// haversine formula.
+pub fn haversine_distance(from: Location, to: Location) -> f64 {
+    let (lat_from, lat_to) = (from.latitude().to_radians(), to.latitude().to_radians());
+    let delta_lat = lat_to - lat_from;
+    let delta_lon = (to.longitude() - from.longitude()).to_radians();
+
+    let a = (delta_lat / 2.0).sin().powi(2)
+        + lat_from.cos() * lat_to.cos() * (delta_lon / 2.0).sin().powi(2);
+
+    // Rounding can push `a` just outside [0, 1] for (near-)antipodal points.
+    let a = a.clamp(0.0, 1.0);
+
+    2.0 * EARTH_RADIUS_METERS * a.sqrt().atan2((1.0 - a).sqrt())
+}
+
+/// Initial bearing of the great-circle path from `from` to `to`, in degrees
+/// clockwise from true north, normalized to `[0, 360)`.
+pub fn initial_bearing(from: Location, to: Location) -> f64 {
+    let (lat_from, lat_to) = (from.latitude().to_radians(), to.latitude().to_radians());
+    let delta_lon = (to.longitude() - from.longitude()).to_radians();
+
+    let y = delta_lon.sin() * lat_to.cos();
+    let x = lat_from.cos() * lat_to.sin() - lat_from.sin() * lat_to.cos() * delta_lon.cos();
+
+    normalize_degrees(y.atan2(x).to_degrees(), 0.0)
+}
+
+/// The point reached by travelling `distance_meters` along a great circle
+/// from `origin`, starting at `bearing_degrees` clockwise from true north.
+pub fn destination_point(origin: Location, bearing_degrees: f64, distance_meters: f64) -> Location {
+    let lat = origin.latitude().to_radians();
+    let lon = origin.longitude().to_radians();
+    let bearing = bearing_degrees.to_radians();
+    let angular_distance = distance_meters / EARTH_RADIUS_METERS;
+
+    let dest_lat = (lat.sin() * angular_distance.cos()
+        + lat.cos() * angular_distance.sin() * bearing.cos())
+    .clamp(-1.0, 1.0)
+    .asin();
+
+    let dest_lon = lon
+        + (bearing.sin() * angular_distance.sin() * lat.cos())
+            .atan2(angular_distance.cos() - lat.sin() * dest_lat.sin());
+
+    Location::new(
+        dest_lat.to_degrees(),
+        normalize_degrees(dest_lon.to_degrees(), -180.0),
+    )
+}
+
+/// Wraps `degrees` into the half-open range `[lower, lower + 360)`.
+fn normalize_degrees(degrees: f64, lower: f64) -> f64 {
+    let wrapped = (degrees - lower).rem_euclid(360.0) + lower;
+
+    // `rem_euclid` can round up to exactly 360 for tiny negative inputs.
+    if wrapped >= lower + 360.0 {
+        lower
+    } else {
+        wrapped
+    }
+}
+
+impl Location {
+    /// Great-circle distance to `other`, in meters.
+    pub fn distance_to(&self, other: &Location) -> f64 {
+        haversine_distance(*self, *other)
+    }
+
+    /// Initial bearing towards `other`, in degrees clockwise from true north.
+    pub fn bearing_to(&self, other: &Location) -> f64 {
+        initial_bearing(*self, *other)
+    }
+
+    /// The point `distance_meters` away from here along `bearing_degrees`.
+    pub fn destination(&self, bearing_degrees: f64, distance_meters: f64) -> Location {
+        destination_point(*self, bearing_degrees, distance_meters)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use proptest::prelude::*;
+
+    /// Keep clear of the poles, where bearings are degenerate.
+    fn location() -> impl Strategy<Value = Location> {
+        (-85.0..85.0f64, -180.0..180.0f64).prop_map(|(lat, lon)| Location::new(lat, lon))
+    }
+
+    /// Smallest absolute difference between two bearings, in degrees.
+    fn bearing_difference(a: f64, b: f64) -> f64 {
+        let diff = (a - b).rem_euclid(360.0);
+        diff.min(360.0 - diff)
+    }
+
+    #[test]
+    /// Sanity-checks against a well-known distance: London to Paris.
+    fn test_known_distance_and_bearing() {
+        let london = Location::new(51.5074, -0.1278);
+        let paris = Location::new(48.8566, 2.3522);
+
+        assert!((london.distance_to(&paris) - 343_500.0).abs() < 1_000.0);
+        assert!((london.bearing_to(&paris) - 148.1).abs() < 0.5);
+    }
+
+    proptest! {
+        #[test]
+        /// Distance doesn't depend on direction of travel.
+        fn prop_distance_is_symmetric(a in location(), b in location()) {
+            prop_assert!((a.distance_to(&b) - b.distance_to(&a)).abs() < 1e-6);
+        }
+
+        #[test]
+        /// Distance is never negative nor longer than half the circumference.
+        fn prop_distance_is_bounded(a in location(), b in location()) {
+            let distance = a.distance_to(&b);
+
+            prop_assert!(distance >= 0.0);
+            prop_assert!(distance <= std::f64::consts::PI * EARTH_RADIUS_METERS + 1e-6);
+        }
+
+        #[test]
+        /// A location is zero meters from itself.
+        fn prop_distance_to_self_is_zero(a in location()) {
+            prop_assert!(a.distance_to(&a) < 1e-6);
+        }
+
+        #[test]
+        /// Bearings always land in [0, 360).
+        fn prop_bearing_is_normalized(a in location(), b in location()) {
+            let bearing = a.bearing_to(&b);
+
+            prop_assert!((0.0..360.0).contains(&bearing));
+        }
+
+        #[test]
+        /// Travelling `d` meters from a point puts us `d` meters away from it,
+        /// and the bearing towards where we arrived is the one we set off on.
+        fn prop_destination_round_trips(
+            origin in location(),
+            bearing in 0.0..360.0f64,
+            distance in 10.0..1_000_000.0f64,
+        ) {
+            let destination = origin.destination(bearing, distance);
+
+            prop_assert!((-180.0..180.0).contains(&destination.longitude()));
+            prop_assert!((origin.distance_to(&destination) - distance).abs() < 1e-3);
+            prop_assert!(bearing_difference(origin.bearing_to(&destination), bearing) < 1e-6);
+        }
+    }
+}
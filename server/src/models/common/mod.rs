@@ -1,3 +1,10 @@
+pub mod geo;
+mod notification;
 mod ping;
+mod time;
+mod webhook_event;
 
+pub use notification::Notification;
 pub use ping::{Location, Ping};
+pub use time::now_epoch_secs;
+pub use webhook_event::{WebhookEvent, WebhookEventKind};
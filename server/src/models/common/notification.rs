@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+
+/// Something that happened which a user should hear about, even if their app
+/// isn't running.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Notification {
+    /// A contact the recipient shares with asked for a fresh location.
+    LocationRequested {
+        from_user_id: String,
+        from_display_name: String,
+        request_id: String,
+    },
+}
+
+impl Notification {
+    /// A short, user-facing headline.
+    pub fn title(&self) -> String {
+        match self {
+            Notification::LocationRequested { .. } => String::from("Where are you?"),
+        }
+    }
+
+    /// User-facing detail, displayed beneath the title.
+    pub fn body(&self) -> String {
+        match self {
+            Notification::LocationRequested {
+                from_display_name, ..
+            } => format!("{} would like to know where you are", from_display_name),
+        }
+    }
+}
@@ -1,11 +1,8 @@
-use std::{
-    convert::TryFrom,
-    time::{SystemTime, UNIX_EPOCH},
-};
-
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+use super::time::now_epoch_secs;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Ping {
     /// Location of the ping.
     location: Location,
@@ -17,23 +14,56 @@ pub struct Ping {
 
 impl Ping {
     pub fn new_at_now(location: Location) -> Self {
-        let now_unsigned: u64 = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("SystemTime::now() is prior to the UNIX_EPOCH")
-            .as_secs();
-
-        let now_signed: i64 =
-            i64::try_from(now_unsigned).expect("Could not convert unsigned timestamp to signed");
+        Self {
+            location,
+            timestamp: now_epoch_secs(),
+        }
+    }
 
+    #[cfg(test)]
+    pub fn new(location: Location, timestamp: i64) -> Self {
         Self {
             location,
-            timestamp: now_signed,
+            timestamp,
         }
     }
+
+    pub fn location(&self) -> Location {
+        self.location
+    }
+
+    pub fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+
+    /// Seconds elapsed since this ping was taken. Clamped to zero in case
+    /// the ping's timestamp is (slightly) in the future.
+    pub fn age_secs(&self) -> i64 {
+        (now_epoch_secs() - self.timestamp).max(0)
+    }
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Location {
     latitude: f64,
     longitude: f64,
 }
+
+impl Location {
+    pub fn new(latitude: f64, longitude: f64) -> Self {
+        Self {
+            latitude,
+            longitude,
+        }
+    }
+
+    /// Latitude in degrees, positive north of the equator.
+    pub fn latitude(&self) -> f64 {
+        self.latitude
+    }
+
+    /// Longitude in degrees, positive east of the prime meridian.
+    pub fn longitude(&self) -> f64 {
+        self.longitude
+    }
+}
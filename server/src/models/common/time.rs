@@ -0,0 +1,15 @@
+use std::{
+    convert::TryFrom,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The current time, in epoch-seconds. Signed because Mongo's BSON does not
+/// support `u64`.
+pub fn now_epoch_secs() -> i64 {
+    let now_unsigned: u64 = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("SystemTime::now() is prior to the UNIX_EPOCH")
+        .as_secs();
+
+    i64::try_from(now_unsigned).expect("Could not convert unsigned timestamp to signed")
+}
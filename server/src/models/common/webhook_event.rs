@@ -0,0 +1,49 @@
+use serde::{Deserialize, Serialize};
+
+use crate::models::common::Ping;
+
+/// Something that happened to a user's account, which their webhooks can
+/// subscribe to. Serialized as the body of a webhook delivery.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WebhookEvent {
+    /// The user uploaded a new location.
+    PingUploaded { user_id: String, ping: Ping },
+    /// The user started or stopped sharing their location with a contact.
+    ShareChanged {
+        user_id: String,
+        contact_id: String,
+        shared: bool,
+    },
+}
+
+/// The kinds of `WebhookEvent`, which a webhook filters on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEventKind {
+    PingUploaded,
+    /// Reserved for when users can define places. Nothing emits it yet, but
+    /// webhooks may subscribe to it ahead of time.
+    PlaceEntered,
+    ShareChanged,
+}
+
+impl WebhookEvent {
+    pub fn kind(&self) -> WebhookEventKind {
+        match self {
+            WebhookEvent::PingUploaded { .. } => WebhookEventKind::PingUploaded,
+            WebhookEvent::ShareChanged { .. } => WebhookEventKind::ShareChanged,
+        }
+    }
+}
+
+impl WebhookEventKind {
+    /// The kind's name, as it appears in the `Sonar-Event` delivery header.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEventKind::PingUploaded => "ping_uploaded",
+            WebhookEventKind::PlaceEntered => "place_entered",
+            WebhookEventKind::ShareChanged => "share_changed",
+        }
+    }
+}
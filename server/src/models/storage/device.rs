@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DevicePlatform {
+    /// Notified via Apple Push Notification service.
+    Ios,
+    /// Notified via Firebase Cloud Messaging.
+    Android,
+}
+
+/// A device that a user has registered to receive push notifications.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Device {
+    platform: DevicePlatform,
+    /// The platform's push token for this device.
+    token: String,
+    /// When the device was (last) registered, in epoch-seconds.
+    registered_at: i64,
+}
+
+impl Device {
+    pub fn new(platform: DevicePlatform, token: String, registered_at: i64) -> Self {
+        Self {
+            platform,
+            token,
+            registered_at,
+        }
+    }
+
+    pub fn platform(&self) -> DevicePlatform {
+        self.platform
+    }
+
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+}
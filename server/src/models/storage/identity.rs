@@ -0,0 +1,93 @@
+use mongodb::bson::{doc, oid::ObjectId, Document};
+use serde::{Deserialize, Serialize};
+
+/// Links an authority's ID for someone to their Sonar user ID. Users can sign
+/// in with any authority they've linked an identity from.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Identity {
+    #[serde(rename = "_id")]
+    id: ObjectId,
+    /// The authority's issuer, as configured.
+    issuer: String,
+    /// The authority's ID for the user.
+    subject: String,
+    user_id: String,
+    /// When the identity was linked, in epoch-seconds.
+    linked_at: i64,
+}
+
+impl Identity {
+    pub fn id(&self) -> &ObjectId {
+        &self.id
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    pub fn subject(&self) -> &str {
+        &self.subject
+    }
+
+    pub fn user_id(&self) -> &str {
+        &self.user_id
+    }
+
+    pub fn linked_at(&self) -> i64 {
+        self.linked_at
+    }
+}
+
+impl Identity {
+    pub fn find_by_provider(issuer: &str, subject: &str) -> Document {
+        doc! {
+            "issuer": issuer,
+            "subject": subject
+        }
+    }
+
+    pub fn find_by_user(user_id: &str) -> Document {
+        doc! {
+            "user_id": user_id
+        }
+    }
+
+    pub fn find_by_user_and_id(user_id: &str, id: &ObjectId) -> Document {
+        doc! {
+            "_id": id.clone(),
+            "user_id": user_id
+        }
+    }
+
+    /// Upserted on `find_by_provider`, links the identity to `user_id`
+    /// unless it's already linked to someone.
+    pub fn link(user_id: &str, linked_at: i64) -> Document {
+        doc! {
+            "$setOnInsert": {
+                "user_id": user_id,
+                "linked_at": linked_at
+            }
+        }
+    }
+
+    /// Moves an identity to `user_id`, as if it had been linked to them at
+    /// `linked_at`.
+    pub fn relink(user_id: &str, linked_at: i64) -> Document {
+        doc! {
+            "$set": {
+                "user_id": user_id,
+                "linked_at": linked_at
+            }
+        }
+    }
+
+    /// The index that makes each authority's ID for someone link to at most
+    /// one user, even when they sign in twice at once.
+    pub fn unique_provider_index() -> Document {
+        doc! {
+            "key": { "issuer": 1, "subject": 1 },
+            "name": "issuer_subject",
+            "unique": true
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use mongodb::bson::{doc, oid::ObjectId, Document};
+use serde::{Deserialize, Serialize};
+
+use crate::models::storage::User;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LocationRequestStatus {
+    /// Waiting for the target to upload a location.
+    Pending,
+    /// The target uploaded a location after the request was made.
+    Fulfilled,
+    /// The target didn't upload a location in time.
+    Expired,
+}
+
+/// One user asking another, who shares their location with them, for a fresh
+/// location.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LocationRequest {
+    #[serde(rename = "_id")]
+    id: ObjectId,
+    requester_id: String,
+    /// The user being asked for their location.
+    target_id: String,
+    /// Only ever `Pending` or `Fulfilled` in storage. Whether a pending
+    /// request has expired is worked out from `expires_at` on read.
+    status: LocationRequestStatus,
+    /// When the request was made, in epoch-seconds.
+    created_at: i64,
+    /// When a still-pending request lapses, in epoch-seconds.
+    expires_at: i64,
+    /// When the target fulfilled the request, in epoch-seconds.
+    fulfilled_at: Option<i64>,
+}
+
+impl LocationRequest {
+    /// How long the target has to respond to a request.
+    pub const EXPIRY_SECS: i64 = 15 * 60;
+    /// How long a requester must wait before asking the same target again.
+    pub const COOLDOWN_SECS: i64 = 5 * 60;
+
+    pub fn new(requester_id: &str, target_id: &str, now: i64) -> Self {
+        Self {
+            id: ObjectId::new(),
+            requester_id: String::from(requester_id),
+            target_id: String::from(target_id),
+            status: LocationRequestStatus::Pending,
+            created_at: now,
+            expires_at: now + LocationRequest::EXPIRY_SECS,
+            fulfilled_at: None,
+        }
+    }
+
+    pub fn id(&self) -> &ObjectId {
+        &self.id
+    }
+
+    pub fn requester_id(&self) -> &str {
+        &self.requester_id
+    }
+
+    pub fn target_id(&self) -> &str {
+        &self.target_id
+    }
+
+    pub fn created_at(&self) -> i64 {
+        self.created_at
+    }
+
+    pub fn expires_at(&self) -> i64 {
+        self.expires_at
+    }
+
+    pub fn fulfilled_at(&self) -> Option<i64> {
+        self.fulfilled_at
+    }
+
+    /// Whether `target`'s latest ping answers the request: it must be new
+    /// since the request, and the requester must be able to see it. While
+    /// the target has paused sharing with the requester, the request waits.
+    pub fn is_fulfilled_by(&self, target: &User) -> bool {
+        match target.visible_ping_for(&self.requester_id) {
+            Some(ping) => Some(ping) == target.last_ping() && ping.timestamp() >= self.created_at,
+            None => false,
+        }
+    }
+
+    /// The request's status as of `now`.
+    pub fn status_at(&self, now: i64) -> LocationRequestStatus {
+        match self.status {
+            LocationRequestStatus::Pending if now >= self.expires_at => {
+                LocationRequestStatus::Expired
+            }
+            status => status,
+        }
+    }
+}
+
+impl LocationRequest {
+    pub fn find_by_id(id: &ObjectId) -> Document {
+        doc! {
+            "_id": id.clone()
+        }
+    }
+
+    /// Matches requests to `target_id` that are still pending at `now`.
+    pub fn find_pending_for_target(target_id: &str, now: i64) -> Document {
+        doc! {
+            "target_id": target_id,
+            "status": "pending",
+            "expires_at": { "$gt": now }
+        }
+    }
+
+    /// Matches those of the requests with the given `ids` that are still
+    /// pending at `now`.
+    pub fn find_pending_by_ids(ids: Vec<ObjectId>, now: i64) -> Document {
+        doc! {
+            "_id": { "$in": ids },
+            "status": "pending",
+            "expires_at": { "$gt": now }
+        }
+    }
+
+    pub fn fulfill(now: i64) -> Document {
+        doc! {
+            "$set": {
+                "status": "fulfilled",
+                "fulfilled_at": now
+            }
+        }
+    }
+
+    /// Matches the cooldown between `requester_id` asking `target_id`, if it
+    /// has lapsed by `now`. Cooldowns are kept apart from requests, one per
+    /// pair, so they can be started with a single conditional upsert.
+    pub fn find_lapsed_cooldown(requester_id: &str, target_id: &str, now: i64) -> Document {
+        doc! {
+            "_id": {
+                "requester_id": requester_id,
+                "target_id": target_id
+            },
+            "requested_at": { "$lte": now - LocationRequest::COOLDOWN_SECS }
+        }
+    }
+
+    /// Upserted on `find_lapsed_cooldown`, starts a new cooldown at `now`.
+    pub fn start_cooldown(now: i64) -> Document {
+        doc! {
+            "$set": {
+                "requested_at": now
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{
+        models::storage::{PausedVisibility, SharingPause, Storable},
+        storage::MongoResult,
+    };
+
+    #[test]
+    /// Tests that a pending request reports itself expired once it lapses,
+    /// but a fulfilled one stays fulfilled.
+    fn test_status_expires() {
+        let mut request = LocationRequest::new("requester", "target", 100);
+
+        assert_eq!(request.status_at(100), LocationRequestStatus::Pending);
+        assert_eq!(
+            request.status_at(100 + LocationRequest::EXPIRY_SECS),
+            LocationRequestStatus::Expired
+        );
+
+        request.status = LocationRequestStatus::Fulfilled;
+
+        assert_eq!(
+            request.status_at(100 + LocationRequest::EXPIRY_SECS),
+            LocationRequestStatus::Fulfilled
+        );
+    }
+
+    #[test]
+    /// Tests that an upload only fulfills a request if it's new and the
+    /// requester can see it, so a paused target's uploads leave it pending.
+    fn test_fulfilled_only_by_visible_ping() {
+        let request = LocationRequest::new("requester", "target", 150);
+        let target = utils::target_with_ping(100);
+
+        assert!(!request.is_fulfilled_by(&target));
+
+        let pause = target.new_pause(Some("requester"), PausedVisibility::Frozen, None);
+        let paused_target = utils::with_ping(utils::paused(target, "requester", &pause), 200);
+
+        assert!(!request.is_fulfilled_by(&paused_target));
+        assert!(request.is_fulfilled_by(&utils::target_with_ping(200)));
+    }
+
+    #[test]
+    /// Tests that the cooldown only lasts `COOLDOWN_SECS`.
+    fn test_cooldown() {
+        let now = 100 + LocationRequest::COOLDOWN_SECS;
+        let lapsed_before = LocationRequest::find_lapsed_cooldown("requester", "target", now)
+            .get_document("requested_at")
+            .and_then(|requested_at| requested_at.get_i64("$lte"))
+            .expect("No bound on requested_at");
+
+        assert_eq!(lapsed_before, 100);
+    }
+
+    mod utils {
+        use super::*;
+
+        use crate::models::common::{Location, Ping};
+
+        pub fn target_with_ping(timestamp: i64) -> User {
+            with_ping(User::new(String::from("target")), timestamp)
+        }
+
+        pub fn with_ping(user: User, timestamp: i64) -> User {
+            let ping = Ping::new(Location::new(0.0, 0.0), timestamp);
+
+            with_field(user, "last_ping", ping.to_document())
+        }
+
+        /// `user` with `pause` on sharing to `contact_id`.
+        pub fn paused(user: User, contact_id: &str, pause: &SharingPause) -> User {
+            let pause = pause.to_document().map(|pause| doc! { contact_id: pause });
+
+            with_field(user, "paused_grants", pause)
+        }
+
+        fn with_field(user: User, field: &str, value: MongoResult<Document>) -> User {
+            let mut document = user.to_document().expect("Failed to serialize");
+            document.insert(field, value.expect("Failed to serialize"));
+
+            User::from_document(document).expect("Failed to deserialize")
+        }
+    }
+}
@@ -1,5 +1,25 @@
+mod device;
+mod identity;
+mod location_request;
+mod outbox_entry;
+mod personal_access_token;
+mod revocation;
+mod session;
+mod sharing_pause;
 mod storable;
 mod user;
+mod webhook;
+mod webhook_delivery;
 
+pub use device::{Device, DevicePlatform};
+pub use identity::Identity;
+pub use location_request::{LocationRequest, LocationRequestStatus};
+pub use outbox_entry::OutboxEntry;
+pub use personal_access_token::PersonalAccessToken;
+pub use revocation::Revocation;
+pub use session::{RefreshTokenRotation, Session};
+pub use sharing_pause::{PausedVisibility, SharingPause};
 pub use storable::Storable;
 pub use user::User;
+pub use webhook::Webhook;
+pub use webhook_delivery::{DeliveryStatus, WebhookDelivery};
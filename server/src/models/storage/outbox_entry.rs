@@ -0,0 +1,101 @@
+use mongodb::bson::{doc, oid::ObjectId, Document};
+use serde::{Deserialize, Serialize};
+
+use crate::models::{
+    common::Notification,
+    storage::{Device, DevicePlatform},
+};
+
+/// A notification waiting to be delivered to a single device. Entries live in
+/// the outbox until they're delivered or we give up on them, so they survive
+/// restarts.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OutboxEntry {
+    #[serde(rename = "_id")]
+    id: ObjectId,
+    /// The user the notification is for.
+    user_id: String,
+    platform: DevicePlatform,
+    device_token: String,
+    notification: Notification,
+    /// How many delivery attempts have been made so far.
+    attempts: i32,
+    /// When the entry is next due for delivery, in epoch-seconds. Pushed back
+    /// while an attempt is in flight, so no two workers attempt it at once.
+    next_attempt_at: i64,
+    /// Why the last attempt failed, if it did.
+    last_error: Option<String>,
+}
+
+impl OutboxEntry {
+    pub fn new(user_id: &str, device: &Device, notification: Notification, now: i64) -> Self {
+        Self {
+            id: ObjectId::new(),
+            user_id: String::from(user_id),
+            platform: device.platform(),
+            device_token: String::from(device.token()),
+            notification,
+            attempts: 0,
+            next_attempt_at: now,
+            last_error: None,
+        }
+    }
+
+    pub fn id(&self) -> &ObjectId {
+        &self.id
+    }
+
+    pub fn user_id(&self) -> &str {
+        &self.user_id
+    }
+
+    pub fn platform(&self) -> DevicePlatform {
+        self.platform
+    }
+
+    pub fn device_token(&self) -> &str {
+        &self.device_token
+    }
+
+    pub fn notification(&self) -> &Notification {
+        &self.notification
+    }
+
+    pub fn attempts(&self) -> i32 {
+        self.attempts
+    }
+}
+
+impl OutboxEntry {
+    pub fn find_by_id(id: &ObjectId) -> Document {
+        doc! {
+            "_id": id.clone()
+        }
+    }
+
+    /// Matches entries due for delivery at `now`.
+    pub fn find_due(now: i64) -> Document {
+        doc! {
+            "next_attempt_at": { "$lte": now }
+        }
+    }
+
+    /// Leases an entry until `lease_until`, so no one else picks it up while
+    /// we're attempting it.
+    pub fn lease(lease_until: i64) -> Document {
+        doc! {
+            "$set": { "next_attempt_at": lease_until }
+        }
+    }
+
+    /// Records a failed attempt and schedules the next one for `retry_at`.
+    pub fn reschedule(attempts: i32, retry_at: i64, error: &str) -> Document {
+        doc! {
+            "$set": {
+                "attempts": attempts,
+                "next_attempt_at": retry_at,
+                "last_error": error
+            }
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::HashSet;
+
+use mongodb::bson::{doc, oid::ObjectId, Document};
+use serde::{Deserialize, Serialize};
+
+use crate::auth::Scope;
+
+/// A long-lived token a user made for a script or device to act as them,
+/// within the token's scopes. Only the token's hash is stored.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PersonalAccessToken {
+    #[serde(rename = "_id")]
+    id: ObjectId,
+    user_id: String,
+    /// What the user called the token, to tell their tokens apart.
+    name: String,
+    token_hash: String,
+    scopes: HashSet<Scope>,
+    /// When the token was made, in epoch-seconds.
+    created_at: i64,
+    /// When the token stops working, in epoch-seconds. Never, if absent.
+    expires_at: Option<i64>,
+    /// When the token was last used, in epoch-seconds. Only updated every
+    /// `LAST_USED_GRANULARITY_SECS`, so that using a token isn't always a
+    /// write.
+    last_used_at: Option<i64>,
+}
+
+impl PersonalAccessToken {
+    pub const LAST_USED_GRANULARITY_SECS: i64 = 60;
+
+    pub fn new(
+        user_id: &str,
+        name: String,
+        token_hash: String,
+        scopes: HashSet<Scope>,
+        created_at: i64,
+        expires_at: Option<i64>,
+    ) -> Self {
+        Self {
+            id: ObjectId::new(),
+            user_id: String::from(user_id),
+            name,
+            token_hash,
+            scopes,
+            created_at,
+            expires_at,
+            last_used_at: None,
+        }
+    }
+
+    pub fn id(&self) -> &ObjectId {
+        &self.id
+    }
+
+    pub fn user_id(&self) -> &str {
+        &self.user_id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn scopes(&self) -> &HashSet<Scope> {
+        &self.scopes
+    }
+
+    pub fn created_at(&self) -> i64 {
+        self.created_at
+    }
+
+    pub fn expires_at(&self) -> Option<i64> {
+        self.expires_at
+    }
+
+    pub fn last_used_at(&self) -> Option<i64> {
+        self.last_used_at
+    }
+
+    /// Whether `last_used_at` is stale enough to update for a use at `now`.
+    pub fn needs_last_used_update(&self, now: i64) -> bool {
+        match self.last_used_at {
+            Some(last_used_at) => {
+                now - last_used_at >= PersonalAccessToken::LAST_USED_GRANULARITY_SECS
+            }
+            None => true,
+        }
+    }
+}
+
+impl PersonalAccessToken {
+    pub fn find_by_user(user_id: &str) -> Document {
+        doc! {
+            "user_id": user_id
+        }
+    }
+
+    pub fn find_by_user_and_id(user_id: &str, id: &ObjectId) -> Document {
+        doc! {
+            "_id": id.clone(),
+            "user_id": user_id
+        }
+    }
+
+    /// Matches the token hashing to `token_hash`, if it hasn't expired by
+    /// `now`.
+    pub fn find_usable(token_hash: &str, now: i64) -> Document {
+        doc! {
+            "token_hash": token_hash,
+            "$or": [
+                { "expires_at": null },
+                { "expires_at": { "$gt": now } }
+            ]
+        }
+    }
+
+    pub fn record_use(now: i64) -> Document {
+        doc! {
+            "$set": { "last_used_at": now }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    /// Tests that `last_used_at` is only updated once it's a minute stale.
+    fn test_last_used_granularity() {
+        let mut token = PersonalAccessToken::new(
+            "user",
+            String::from("tracker"),
+            String::from("hash"),
+            HashSet::new(),
+            1000,
+            None,
+        );
+        assert!(token.needs_last_used_update(1000));
+
+        token.last_used_at = Some(1000);
+        assert!(!token.needs_last_used_update(1059));
+        assert!(token.needs_last_used_update(1060));
+    }
+}
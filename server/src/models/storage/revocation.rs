@@ -0,0 +1,121 @@
+use mongodb::bson::{doc, Document};
+use serde::{Deserialize, Serialize};
+
+/// Makes tokens stop working before they expire: one access token by its
+/// `jti`, every access token of a session, or every token a user was issued
+/// before some time. Kept until every token it applies to has expired anyway.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Revocation {
+    /// What's revoked, as made by `Revocation::token_key`, `session_key` or
+    /// `user_key`.
+    #[serde(rename = "_id")]
+    key: String,
+    /// Only tokens issued before this are revoked, in epoch-seconds. If
+    /// unset, every token the key matches is.
+    issued_before: Option<i64>,
+    /// When the revocation can be forgotten, in epoch-seconds.
+    expires_at: i64,
+}
+
+impl Revocation {
+    /// Revokes the access token with `jti`, which expires at `expires_at`.
+    pub fn token(jti: &str, expires_at: i64) -> Self {
+        Self {
+            key: Revocation::token_key(jti),
+            issued_before: None,
+            expires_at,
+        }
+    }
+
+    /// Revokes every access token of the session with `session_id`. None are
+    /// issued after `expires_at`.
+    pub fn session(session_id: &str, expires_at: i64) -> Self {
+        Self {
+            key: Revocation::session_key(session_id),
+            issued_before: None,
+            expires_at,
+        }
+    }
+
+    /// Revokes every token issued to the user with `user_id` before
+    /// `issued_before`, all of which expire by `expires_at`.
+    pub fn user(user_id: &str, issued_before: i64, expires_at: i64) -> Self {
+        Self {
+            key: Revocation::user_key(user_id),
+            issued_before: Some(issued_before),
+            expires_at,
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn token_key(jti: &str) -> String {
+        format!("jti:{}", jti)
+    }
+
+    pub fn session_key(session_id: &str) -> String {
+        format!("session:{}", session_id)
+    }
+
+    pub fn user_key(user_id: &str) -> String {
+        format!("user:{}", user_id)
+    }
+
+    /// Whether this revokes a token issued at `issued_at`, as of `now`. A
+    /// token that doesn't say when it was issued can't show it's newer than
+    /// a user's revocation, so is revoked too.
+    pub fn applies_to(&self, issued_at: Option<i64>, now: i64) -> bool {
+        if self.expires_at <= now {
+            return false;
+        }
+
+        match (self.issued_before, issued_at) {
+            (Some(issued_before), Some(issued_at)) => issued_at < issued_before,
+            _ => true,
+        }
+    }
+}
+
+impl Revocation {
+    pub fn find_by_key(key: &str) -> Document {
+        doc! {
+            "_id": key
+        }
+    }
+
+    pub fn find_by_keys(keys: &[String]) -> Document {
+        doc! {
+            "_id": { "$in": keys }
+        }
+    }
+
+    pub fn find_expired(now: i64) -> Document {
+        doc! {
+            "expires_at": { "$lte": now }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    /// Tests that a user's revocation only applies to tokens issued before
+    /// it, and that revocations lapse at their expiry.
+    fn test_applies_to() {
+        let user = Revocation::user("user", 1_000, 2_000);
+
+        assert!(user.applies_to(Some(999), 1_500));
+        assert!(!user.applies_to(Some(1_000), 1_500));
+        assert!(user.applies_to(None, 1_500));
+        assert!(!user.applies_to(Some(999), 2_000));
+
+        let token = Revocation::token("jti", 2_000);
+
+        assert!(token.applies_to(Some(1_500), 1_500));
+        assert!(!token.applies_to(Some(1_500), 2_000));
+    }
+}
@@ -0,0 +1,120 @@
+use mongodb::bson::{doc, oid::ObjectId, Document};
+use serde::{Deserialize, Serialize};
+
+/// A signed-in client's session, started by exchanging an authority's ID
+/// token. Each refresh token it's given can be exchanged once, for an access
+/// token and the next refresh token. Only hashes of refresh tokens are
+/// stored.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Session {
+    #[serde(rename = "_id")]
+    id: ObjectId,
+    user_id: String,
+    /// The hash of the refresh token that can be exchanged next.
+    refresh_token_hash: String,
+    /// The hashes of refresh tokens already exchanged, oldest first. One
+    /// being presented again means it leaked, so the session is revoked.
+    used_refresh_token_hashes: Vec<String>,
+    /// When the session started, in epoch-seconds.
+    created_at: i64,
+    /// When a refresh token was last exchanged, in epoch-seconds.
+    refreshed_at: i64,
+    /// When the current refresh token lapses, in epoch-seconds. Pushed back
+    /// each time one is exchanged.
+    expires_at: i64,
+    /// When the session was revoked, in epoch-seconds.
+    revoked_at: Option<i64>,
+}
+
+/// What came of trying to exchange a refresh token.
+pub enum RefreshTokenRotation {
+    /// The token was current, and has been replaced.
+    Rotated(Session),
+    /// The token was already exchanged, so its session has been revoked.
+    Reused(Session),
+    /// The token is unknown, expired, or its session was revoked.
+    Invalid,
+}
+
+impl Session {
+    /// How many used refresh tokens to remember for reuse detection.
+    pub const MAX_USED_REFRESH_TOKENS: i32 = 100;
+
+    pub fn new(user_id: &str, refresh_token_hash: String, now: i64, expires_at: i64) -> Self {
+        Self {
+            id: ObjectId::new(),
+            user_id: String::from(user_id),
+            refresh_token_hash,
+            used_refresh_token_hashes: vec![],
+            created_at: now,
+            refreshed_at: now,
+            expires_at,
+            revoked_at: None,
+        }
+    }
+
+    pub fn id(&self) -> &ObjectId {
+        &self.id
+    }
+
+    pub fn user_id(&self) -> &str {
+        &self.user_id
+    }
+}
+
+impl Session {
+    /// Matches the unrevoked session whose current refresh token hashes to
+    /// `refresh_token_hash`, if that token hasn't lapsed by `now`.
+    pub fn find_refreshable(refresh_token_hash: &str, now: i64) -> Document {
+        doc! {
+            "refresh_token_hash": refresh_token_hash,
+            "revoked_at": null,
+            "expires_at": { "$gt": now }
+        }
+    }
+
+    /// Matches the user with `user_id`'s sessions that haven't been revoked.
+    pub fn find_unrevoked_by_user(user_id: &str) -> Document {
+        doc! {
+            "user_id": user_id,
+            "revoked_at": null
+        }
+    }
+
+    /// Matches the session that already exchanged the refresh token hashing
+    /// to `refresh_token_hash`.
+    pub fn find_by_used_refresh_token(refresh_token_hash: &str) -> Document {
+        doc! {
+            "used_refresh_token_hashes": refresh_token_hash
+        }
+    }
+
+    /// Replaces the current refresh token's hash with `new_hash`, remembering
+    /// `old_hash` as used, and pushes the session's expiry back.
+    pub fn rotate_refresh_token(
+        old_hash: &str,
+        new_hash: &str,
+        now: i64,
+        expires_at: i64,
+    ) -> Document {
+        doc! {
+            "$set": {
+                "refresh_token_hash": new_hash,
+                "refreshed_at": now,
+                "expires_at": expires_at
+            },
+            "$push": {
+                "used_refresh_token_hashes": {
+                    "$each": [old_hash],
+                    "$slice": -Session::MAX_USED_REFRESH_TOKENS
+                }
+            }
+        }
+    }
+
+    pub fn revoke(now: i64) -> Document {
+        doc! {
+            "$set": { "revoked_at": now }
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+
+use crate::models::common::{now_epoch_secs, Ping};
+
+/// What contacts see while a user's sharing is paused.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PausedVisibility {
+    /// Contacts keep seeing the last ping from before the pause began.
+    Frozen,
+    /// Contacts see no location at all.
+    Hidden,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SharingPause {
+    /// What contacts see while the pause is active.
+    visibility: PausedVisibility,
+    /// When the pause lapses, in epoch-seconds. `None` pauses until the
+    /// user explicitly resumes.
+    until: Option<i64>,
+    /// The ping contacts could see when the pause began. Only surfaced to
+    /// contacts for `PausedVisibility::Frozen`.
+    frozen_ping: Option<Ping>,
+}
+
+impl SharingPause {
+    pub fn new(
+        visibility: PausedVisibility,
+        until: Option<i64>,
+        frozen_ping: Option<Ping>,
+    ) -> Self {
+        Self {
+            visibility,
+            until,
+            frozen_ping,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.until.map_or(true, |until| now_epoch_secs() < until)
+    }
+
+    pub fn visibility(&self) -> PausedVisibility {
+        self.visibility
+    }
+
+    pub fn frozen_ping(&self) -> Option<Ping> {
+        self.frozen_ping
+    }
+}
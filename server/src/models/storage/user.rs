@@ -1,9 +1,16 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
-use mongodb::bson::{doc, Document};
-use serde::{Deserialize, Serialize};
+use mongodb::bson::{doc, oid::ObjectId, Bson, Document, Timestamp};
+use serde::{de::Error, Deserialize, Deserializer, Serialize};
 
-use crate::models::common::{Location, Ping};
+use crate::{
+    auth::Role,
+    models::{
+        common::Ping,
+        storage::{Device, PausedVisibility, SharingPause, Storable},
+    },
+    storage::MongoResult,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct User {
@@ -19,6 +26,34 @@ pub struct User {
     /// list is solely intended as a hint for lookups - this user's ID must be
     // in each other user's `shared_to` list for the access to succeed.
     shared_with_me_hint: HashSet<String>,
+    /// A pause on sharing to everyone in `shared_to`, if any.
+    sharing_pause: Option<SharingPause>,
+    /// Pauses on sharing to individual user IDs in `shared_to`.
+    #[serde(default)]
+    paused_grants: HashMap<String, SharingPause>,
+    /// Position in the users collection's change sequence of this user's
+    /// latest location upload. Like the other sequence numbers, stamped by
+    /// Mongo in the same write as the update, so it only ever increases, and
+    /// never written from here.
+    #[serde(default, skip_serializing, deserialize_with = "deserialize_change_seq")]
+    ping_seq: i64,
+    /// Position in the change sequence of the latest global pause or resume.
+    #[serde(default, skip_serializing, deserialize_with = "deserialize_change_seq")]
+    sharing_seq: i64,
+    /// Position in the change sequence of the latest share, pause or resume
+    /// for each user ID in `shared_to`.
+    #[serde(
+        default,
+        skip_serializing,
+        deserialize_with = "deserialize_change_seqs"
+    )]
+    grant_seqs: HashMap<String, i64>,
+    /// Devices registered to receive this user's push notifications.
+    #[serde(default)]
+    devices: Vec<Device>,
+    /// What the user may do beyond managing their own account. Unset for
+    /// most users, who have `Role::User`.
+    role: Option<Role>,
 }
 
 impl User {
@@ -29,9 +64,22 @@ impl User {
             last_ping: None,
             shared_to: HashSet::new(),
             shared_with_me_hint: HashSet::new(),
+            sharing_pause: None,
+            paused_grants: HashMap::new(),
+            ping_seq: 0,
+            sharing_seq: 0,
+            grant_seqs: HashMap::new(),
+            devices: Vec::new(),
+            role: None,
         }
     }
 
+    /// A fresh ID for a new user. Users from before identities existed have
+    /// their MSA `oid` as their ID instead.
+    pub fn generate_id() -> String {
+        ObjectId::new().to_hex()
+    }
+
     pub fn id(&self) -> &str {
         &self.id
     }
@@ -43,6 +91,102 @@ impl User {
     pub fn last_ping(&self) -> Option<Ping> {
         self.last_ping
     }
+
+    pub fn devices(&self) -> &[Device] {
+        &self.devices
+    }
+
+    pub fn role(&self) -> Role {
+        self.role.unwrap_or(Role::User)
+    }
+
+    /// Whether the user has done nothing yet that would be lost without them:
+    /// no location, contacts, pauses, devices or role.
+    pub fn is_empty(&self) -> bool {
+        self.last_ping.is_none()
+            && self.shared_to.is_empty()
+            && self.shared_with_me_hint.is_empty()
+            && self.sharing_pause.is_none()
+            && self.paused_grants.is_empty()
+            && self.devices.is_empty()
+            && self.role.is_none()
+    }
+
+    /// Whether the user with ID `viewer_id` is allowed to see this user.
+    pub fn is_shared_to(&self, viewer_id: &str) -> bool {
+        self.shared_to.contains(viewer_id)
+    }
+
+    /// The ping that the user with ID `viewer_id` is allowed to see. This is
+    /// `last_ping` unless sharing is paused, either globally or for the viewer.
+    /// If both pauses are active, the more restrictive one wins.
+    pub fn visible_ping_for(&self, viewer_id: &str) -> Option<Ping> {
+        let hidden = self
+            .pauses_for(Some(viewer_id))
+            .any(|pause| pause.is_active() && pause.visibility() == PausedVisibility::Hidden);
+
+        if hidden {
+            None
+        } else {
+            self.ping_before(self.pauses_for(Some(viewer_id)))
+        }
+    }
+
+    /// Position in the change sequence of the latest change to what the user
+    /// with ID `viewer_id` can see of this user. Uploads they can't see, and
+    /// pauses for other viewers, don't count, so they can't tell this user is
+    /// active. A pause lapsing isn't a change until the next upload.
+    pub fn visible_change_seq(&self, viewer_id: &str) -> i64 {
+        let ping_seq = if self.visible_ping_for(viewer_id) == self.last_ping {
+            self.ping_seq
+        } else {
+            0
+        };
+
+        ping_seq
+            .max(self.sharing_seq)
+            .max(self.grant_seqs.get(viewer_id).copied().unwrap_or(0))
+    }
+
+    /// A pause on sharing to `contact_id`, or to everyone if `None`, as of
+    /// now.
+    pub fn new_pause(
+        &self,
+        contact_id: Option<&str>,
+        visibility: PausedVisibility,
+        until: Option<i64>,
+    ) -> SharingPause {
+        // Freeze on the ping from before any pause already in effect for this
+        // scope, so that re-pausing doesn't leak pings uploaded in the meantime.
+        let frozen_ping = self.ping_before(self.pauses_for(contact_id));
+
+        SharingPause::new(visibility, until, frozen_ping)
+    }
+
+    /// The global pause, followed by the pause for `viewer_id` if there is one.
+    fn pauses_for<'a>(
+        &'a self,
+        viewer_id: Option<&'a str>,
+    ) -> impl Iterator<Item = &'a SharingPause> + 'a {
+        self.sharing_pause
+            .iter()
+            .chain(viewer_id.and_then(|viewer_id| self.paused_grants.get(viewer_id)))
+    }
+
+    /// The latest ping taken before any of the active `pauses` began.
+    fn ping_before<'a>(&self, pauses: impl Iterator<Item = &'a SharingPause>) -> Option<Ping> {
+        pauses
+            .filter(|pause| pause.is_active())
+            .fold(self.last_ping, |latest, pause| {
+                match (latest, pause.frozen_ping()) {
+                    (Some(latest), Some(frozen)) if frozen.timestamp() < latest.timestamp() => {
+                        Some(frozen)
+                    }
+                    (Some(latest), Some(_)) => Some(latest),
+                    _ => None,
+                }
+            })
+    }
 }
 
 impl User {
@@ -52,9 +196,466 @@ impl User {
         }
     }
 
-    pub fn update_location(mut self, location: Location) -> Self {
-        self.last_ping = Some(Ping::new_at_now(location));
+    pub fn set_role(role: Role) -> Document {
+        doc! {
+            "$set": { "role": role.as_str() }
+        }
+    }
+
+    /// Matches users whose location is shared to the user with the given `id`.
+    pub fn find_shared_to(id: &str) -> Document {
+        doc! {
+            "shared_to": id
+        }
+    }
+
+    /// Matches users whose location is shared to the user with the given `id`,
+    /// and who have been updated since `change_seq`. Not every update changes
+    /// what that user can see; see `visible_change_seq`.
+    pub fn find_shared_to_changed_since(id: &str, change_seq: i64) -> Document {
+        doc! {
+            "shared_to": id,
+            "change_seq": { "$gt": Bson::Timestamp(timestamp_of(change_seq)) }
+        }
+    }
+
+    /// Matches the user with the given `id` if their location isn't shared to
+    /// the user with the given `contact_id` yet.
+    pub fn find_by_id_not_shared_to(id: &str, contact_id: &str) -> Document {
+        doc! {
+            "id": id,
+            "shared_to": { "$ne": contact_id }
+        }
+    }
 
-        self
+    /// Matches the user with the given `id` if their location is shared to
+    /// the user with the given `contact_id`.
+    pub fn find_by_id_shared_to(id: &str, contact_id: &str) -> Document {
+        doc! {
+            "id": id,
+            "shared_to": contact_id
+        }
+    }
+
+    /// Records a new ping, creating the user if they don't exist yet. This is
+    /// accepted even while sharing is paused, so the user's own view stays
+    /// current; pauses only change what contacts see.
+    pub fn update_location(id: &str, ping: &Ping) -> MongoResult<Document> {
+        let mut new_user = User::new(String::from(id)).to_document()?;
+        // Set either way, or taken from the filter.
+        new_user.remove("last_ping");
+        new_user.remove("id");
+
+        Ok(with_change_seq(
+            doc! {
+                "$set": { "last_ping": ping.to_document()? },
+                "$setOnInsert": new_user
+            },
+            "ping_seq",
+        ))
+    }
+
+    /// Starts sharing this user's location to `contact_id`.
+    pub fn share_to(contact_id: &str) -> Document {
+        with_change_seq(
+            doc! {
+                "$addToSet": { "shared_to": contact_id }
+            },
+            &seq_field(Some(contact_id)),
+        )
+    }
+
+    /// Stops sharing this user's location to `contact_id`, along with any
+    /// pause on that sharing. They can't see the change, so it isn't stamped.
+    pub fn unshare_to(contact_id: &str) -> Document {
+        doc! {
+            "$pull": { "shared_to": contact_id },
+            "$unset": {
+                pause_field(Some(contact_id)): "",
+                seq_field(Some(contact_id)): ""
+            }
+        }
+    }
+
+    /// Notes that `contact_id` has (`true`) or no longer has (`false`) shared
+    /// their location with this user.
+    pub fn set_shared_with_me_hint(contact_id: &str, shared: bool) -> Document {
+        if shared {
+            doc! { "$addToSet": { "shared_with_me_hint": contact_id } }
+        } else {
+            doc! { "$pull": { "shared_with_me_hint": contact_id } }
+        }
+    }
+
+    /// Matches users who have registered the device with the given `token`.
+    pub fn find_by_device(token: &str) -> Document {
+        doc! {
+            "devices.token": token
+        }
+    }
+
+    /// Registers a device for push notifications. Any existing registration
+    /// of the same token must be removed first.
+    pub fn register_device(device: &Device) -> MongoResult<Document> {
+        Ok(doc! {
+            "$push": { "devices": device.to_document()? }
+        })
+    }
+
+    pub fn unregister_device(token: &str) -> Document {
+        doc! {
+            "$pull": { "devices": { "token": token } }
+        }
+    }
+
+    /// Pauses sharing to `contact_id`, or to everyone if `None`, replacing any
+    /// existing pause for the same scope.
+    pub fn pause_sharing(contact_id: Option<&str>, pause: &SharingPause) -> MongoResult<Document> {
+        Ok(with_change_seq(
+            doc! {
+                "$set": { pause_field(contact_id): pause.to_document()? }
+            },
+            &seq_field(contact_id),
+        ))
+    }
+
+    /// Lifts the pause on sharing to `contact_id`, or the global pause if `None`.
+    pub fn resume_sharing(contact_id: Option<&str>) -> Document {
+        with_change_seq(
+            doc! {
+                "$unset": { pause_field(contact_id): "" }
+            },
+            &seq_field(contact_id),
+        )
+    }
+}
+
+/// Where the pause on sharing to `contact_id` is kept, or the global pause if
+/// `None`. Only IDs of users who exist are shared to, and so paused, and
+/// those never contain a `.`.
+fn pause_field(contact_id: Option<&str>) -> String {
+    match contact_id {
+        Some(contact_id) => format!("paused_grants.{}", contact_id),
+        None => String::from("sharing_pause"),
+    }
+}
+
+/// Where the sequence number of the latest change to sharing to `contact_id`
+/// is kept, or of the latest global change if `None`.
+fn seq_field(contact_id: Option<&str>) -> String {
+    match contact_id {
+        Some(contact_id) => format!("grant_seqs.{}", contact_id),
+        None => String::from("sharing_seq"),
+    }
+}
+
+/// Adds to `update` stamping the user, and `seq_field`, with the next change
+/// sequence number, in the same write. `change_seq` is the latest of all of a
+/// user's sequence numbers, so queries only need to check the one.
+fn with_change_seq(mut update: Document, seq_field: &str) -> Document {
+    update.insert(
+        "$currentDate",
+        doc! {
+            "change_seq": { "$type": "timestamp" },
+            seq_field: { "$type": "timestamp" }
+        },
+    );
+
+    update
+}
+
+/// Reads a sequence number stamped by Mongo as its timestamp's seconds and
+/// increment, so it orders the same way.
+fn deserialize_change_seq<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i64, D::Error> {
+    change_seq_of(Bson::deserialize(deserializer)?)
+}
+
+fn deserialize_change_seqs<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<String, i64>, D::Error> {
+    HashMap::<String, Bson>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(id, change_seq)| Ok((id, change_seq_of(change_seq)?)))
+        .collect()
+}
+
+fn change_seq_of<E: Error>(change_seq: Bson) -> Result<i64, E> {
+    match change_seq {
+        Bson::Timestamp(timestamp) => {
+            Ok(i64::from(timestamp.time) << 32 | i64::from(timestamp.increment))
+        }
+        other => Err(E::custom(format!("invalid change_seq: {}", other))),
+    }
+}
+
+/// The inverse of `change_seq_of`, for cursors clients pass back.
+fn timestamp_of(change_seq: i64) -> Timestamp {
+    let change_seq = change_seq.max(0);
+
+    Timestamp {
+        time: (change_seq >> 32) as u32,
+        increment: change_seq as u32,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::models::common::{now_epoch_secs, Location};
+
+    #[test]
+    /// Tests that a frozen global pause shows contacts the pre-pause ping,
+    /// while the user's own `last_ping` keeps moving.
+    fn test_frozen_pause_shows_pre_pause_ping() {
+        let user = utils::user_with_ping(100);
+        let user = utils::paused(user, None, PausedVisibility::Frozen, None);
+        let user = utils::with_ping(user, 200);
+
+        assert_eq!(user.visible_ping_for("viewer").unwrap().timestamp(), 100);
+        assert_eq!(user.last_ping().unwrap().timestamp(), 200);
+    }
+
+    #[test]
+    /// Tests that a hidden pause shows contacts nothing.
+    fn test_hidden_pause_shows_nothing() {
+        let user = utils::paused(
+            utils::user_with_ping(100),
+            None,
+            PausedVisibility::Hidden,
+            None,
+        );
+
+        assert!(user.visible_ping_for("viewer").is_none());
+    }
+
+    #[test]
+    /// Tests that a per-contact pause only affects that contact.
+    fn test_grant_pause_only_affects_that_contact() {
+        let user = utils::user_with_ping(100);
+        let user = utils::paused(user, Some("paused"), PausedVisibility::Hidden, None);
+        let user = utils::with_ping(user, 200);
+
+        assert!(user.visible_ping_for("paused").is_none());
+        assert_eq!(user.visible_ping_for("other").unwrap().timestamp(), 200);
+    }
+
+    #[test]
+    /// Tests that a pause stops applying once its `until` has passed.
+    fn test_lapsed_pause_is_ignored() {
+        let user = utils::paused(
+            utils::user_with_ping(100),
+            None,
+            PausedVisibility::Hidden,
+            Some(now_epoch_secs() - 1),
+        );
+
+        assert_eq!(user.visible_ping_for("viewer").unwrap().timestamp(), 100);
+    }
+
+    #[test]
+    /// Tests that pausing a contact during a global pause freezes on the
+    /// globally-frozen ping, rather than one uploaded since.
+    fn test_repause_does_not_leak_newer_pings() {
+        let user = utils::user_with_ping(100);
+        let user = utils::paused(user, None, PausedVisibility::Hidden, None);
+        let user = utils::with_ping(user, 200);
+        let mut user = utils::paused(user, Some("viewer"), PausedVisibility::Frozen, None);
+        user.sharing_pause = None;
+
+        assert_eq!(user.visible_ping_for("viewer").unwrap().timestamp(), 100);
+    }
+
+    #[test]
+    /// Tests that an upload written after a pause that started from the same
+    /// read of the user doesn't undo the pause.
+    fn test_upload_racing_pause_keeps_pause() {
+        let user = utils::user_with_ping(100);
+        let pause = user.new_pause(None, PausedVisibility::Hidden, None);
+        let ping = Ping::new(Location::new(1.0, 1.0), 200);
+
+        let mut document = user.to_document().expect("Failed to serialize");
+        utils::apply(
+            &mut document,
+            User::pause_sharing(None, &pause).expect("Failed to serialize"),
+        );
+        utils::apply(
+            &mut document,
+            User::update_location("user_id", &ping).expect("Failed to serialize"),
+        );
+        let user = User::from_document(document).expect("Failed to deserialize");
+
+        assert!(user.visible_ping_for("viewer").is_none());
+        assert_eq!(user.last_ping().unwrap().timestamp(), 200);
+    }
+
+    #[test]
+    /// Tests that pausing and resuming a single contact only touches that
+    /// contact's pause.
+    fn test_grant_pause_updates() {
+        let user = utils::user_with_ping(100);
+        let pause = user.new_pause(Some("viewer"), PausedVisibility::Hidden, None);
+
+        let mut document = user.to_document().expect("Failed to serialize");
+        utils::apply(
+            &mut document,
+            User::pause_sharing(Some("viewer"), &pause).expect("Failed to serialize"),
+        );
+        let paused = User::from_document(document.clone()).expect("Failed to deserialize");
+
+        assert!(paused.visible_ping_for("viewer").is_none());
+        assert!(paused.visible_ping_for("other").is_some());
+
+        utils::apply(&mut document, User::resume_sharing(Some("viewer")));
+        let resumed = User::from_document(document).expect("Failed to deserialize");
+
+        assert!(resumed.visible_ping_for("viewer").is_some());
+    }
+
+    #[test]
+    /// Tests that unsharing with a contact drops any pause on them, so a
+    /// later share doesn't start out paused.
+    fn test_unshare_drops_grant_pause() {
+        let user = utils::paused(
+            utils::user_with_ping(100),
+            Some("viewer"),
+            PausedVisibility::Hidden,
+            None,
+        );
+
+        let mut document = user.to_document().expect("Failed to serialize");
+        utils::apply(&mut document, User::unshare_to("viewer"));
+        let user = User::from_document(document).expect("Failed to deserialize");
+
+        assert!(user.visible_ping_for("viewer").is_some());
+    }
+
+    #[test]
+    /// Tests that a user is only empty until they've done something worth
+    /// keeping.
+    fn test_is_empty() {
+        let mut sharing = User::new(String::from("user_id"));
+        sharing.shared_to.insert(String::from("contact"));
+
+        assert!(User::new(String::from("user_id")).is_empty());
+        assert!(!utils::user_with_ping(100).is_empty());
+        assert!(!sharing.is_empty());
+    }
+
+    #[test]
+    /// Tests that sequence numbers are read from Mongo's timestamps in the
+    /// order they were stamped, and that they're never written.
+    fn test_change_seq() {
+        let earlier = utils::user_with_seq("ping_seq", utils::timestamp(7));
+        let later = utils::user_with_seq("ping_seq", utils::timestamp(8));
+
+        assert!(later.ping_seq > earlier.ping_seq);
+        assert_eq!(
+            Bson::Timestamp(timestamp_of(earlier.ping_seq)),
+            utils::timestamp(7)
+        );
+
+        let document = earlier.to_document().expect("Failed to serialize");
+        assert!(!document.contains_key("ping_seq"));
+        assert!(!document.contains_key("grant_seqs"));
+    }
+
+    #[test]
+    /// Tests that uploads only count as a change for viewers who can see
+    /// them, and pauses only for the viewers they apply to.
+    fn test_visible_change_seq() {
+        let user = utils::user_with_seq("ping_seq", utils::timestamp(7));
+        let ping_seq = user.ping_seq;
+
+        assert_eq!(user.visible_change_seq("viewer"), ping_seq);
+
+        let user = utils::paused(user, Some("paused"), PausedVisibility::Frozen, None);
+        let mut user = utils::with_ping(user, 200);
+        user.ping_seq = ping_seq + 2;
+        user.grant_seqs.insert(String::from("paused"), ping_seq + 1);
+
+        assert_eq!(user.visible_change_seq("viewer"), ping_seq + 2);
+        assert_eq!(user.visible_change_seq("paused"), ping_seq + 1);
+    }
+
+    mod utils {
+        use super::*;
+
+        pub fn user_with_ping(timestamp: i64) -> User {
+            with_ping(User::new(String::from("user_id")), timestamp)
+        }
+
+        pub fn with_ping(mut user: User, timestamp: i64) -> User {
+            user.last_ping = Some(Ping::new(Location::new(0.0, 0.0), timestamp));
+
+            user
+        }
+
+        /// `user` with the pause that `pause_sharing` would write.
+        pub fn paused(
+            mut user: User,
+            contact_id: Option<&str>,
+            visibility: PausedVisibility,
+            until: Option<i64>,
+        ) -> User {
+            let pause = user.new_pause(contact_id, visibility, until);
+
+            match contact_id {
+                Some(contact_id) => {
+                    user.paused_grants.insert(String::from(contact_id), pause);
+                }
+                None => user.sharing_pause = Some(pause),
+            }
+
+            user
+        }
+
+        pub fn timestamp(increment: u32) -> Bson {
+            Bson::Timestamp(Timestamp {
+                time: 1_600_000_000,
+                increment,
+            })
+        }
+
+        pub fn user_with_seq(field: &str, change_seq: Bson) -> User {
+            let mut document = user_with_ping(100)
+                .to_document()
+                .expect("Failed to serialize");
+            document.insert(field, change_seq);
+
+            User::from_document(document).expect("Failed to deserialize")
+        }
+
+        /// Applies the `$set` and `$unset` parts of `update` to `document`,
+        /// as Mongo would to a user that already exists. Sequence numbers,
+        /// which only Mongo can stamp, are left alone.
+        pub fn apply(document: &mut Document, update: Document) {
+            for (operator, fields) in update {
+                let fields = match (operator.as_str(), fields) {
+                    ("$set", Bson::Document(fields)) | ("$unset", Bson::Document(fields)) => fields,
+                    _ => continue,
+                };
+
+                for (path, value) in fields {
+                    let mut path = path.splitn(2, '.');
+                    let (field, subfield) = (path.next().unwrap(), path.next());
+                    let target = match subfield {
+                        // Nothing to unset under a field that isn't there.
+                        Some(_) if operator == "$unset" && !document.contains_key(field) => {
+                            continue
+                        }
+                        Some(_) => document.get_document_mut(field).expect("Not a document"),
+                        None => &mut *document,
+                    };
+                    let key = subfield.unwrap_or(field);
+
+                    if operator == "$set" {
+                        target.insert(key, value);
+                    } else {
+                        target.remove(key);
+                    }
+                }
+            }
+        }
     }
 }
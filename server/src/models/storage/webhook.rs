@@ -0,0 +1,143 @@
+use std::collections::HashSet;
+
+use mongodb::bson::{doc, oid::ObjectId, to_bson, Bson, Document};
+use serde::{Deserialize, Serialize};
+
+use crate::models::common::WebhookEventKind;
+
+/// A user-registered URL that we POST their account's events to.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Webhook {
+    #[serde(rename = "_id")]
+    id: ObjectId,
+    /// The user whose events this webhook receives.
+    owner_id: String,
+    url: String,
+    /// Shared with the owner, and used to sign each delivery so the receiver
+    /// can check it came from us.
+    secret: String,
+    /// The kinds of events to deliver.
+    events: HashSet<WebhookEventKind>,
+    /// How many delivery attempts in a row have failed.
+    consecutive_failures: i32,
+    /// Set once `consecutive_failures` gets too high. Disabled webhooks
+    /// receive no deliveries until the owner re-enables them.
+    disabled: bool,
+    /// When the webhook was registered, in epoch-seconds.
+    created_at: i64,
+}
+
+impl Webhook {
+    pub fn new(
+        owner_id: &str,
+        url: String,
+        secret: String,
+        events: HashSet<WebhookEventKind>,
+        created_at: i64,
+    ) -> Self {
+        Self {
+            id: ObjectId::new(),
+            owner_id: String::from(owner_id),
+            url,
+            secret,
+            events,
+            consecutive_failures: 0,
+            disabled: false,
+            created_at,
+        }
+    }
+
+    pub fn id(&self) -> &ObjectId {
+        &self.id
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn secret(&self) -> &str {
+        &self.secret
+    }
+
+    pub fn events(&self) -> &HashSet<WebhookEventKind> {
+        &self.events
+    }
+
+    pub fn consecutive_failures(&self) -> i32 {
+        self.consecutive_failures
+    }
+
+    pub fn is_disabled(&self) -> bool {
+        self.disabled
+    }
+}
+
+impl Webhook {
+    /// Webhooks are disabled after this many delivery attempts in a row fail.
+    pub const MAX_CONSECUTIVE_FAILURES: i32 = 10;
+
+    pub fn find_by_id(id: &ObjectId) -> Document {
+        doc! {
+            "_id": id.clone()
+        }
+    }
+
+    pub fn find_by_owner(owner_id: &str) -> Document {
+        doc! {
+            "owner_id": owner_id
+        }
+    }
+
+    pub fn find_by_owner_and_id(owner_id: &str, id: &ObjectId) -> Document {
+        doc! {
+            "_id": id.clone(),
+            "owner_id": owner_id
+        }
+    }
+
+    /// Matches enabled webhooks belonging to `owner_id` that want `kind`.
+    pub fn find_subscribed(owner_id: &str, kind: WebhookEventKind) -> Document {
+        doc! {
+            "owner_id": owner_id,
+            "disabled": false,
+            "events": kind_to_bson(kind)
+        }
+    }
+
+    /// Matches the webhook with `id` if it has failed too many times in a row.
+    pub fn find_failing(id: &ObjectId) -> Document {
+        doc! {
+            "_id": id.clone(),
+            "consecutive_failures": { "$gte": Webhook::MAX_CONSECUTIVE_FAILURES }
+        }
+    }
+
+    pub fn record_success() -> Document {
+        doc! {
+            "$set": { "consecutive_failures": 0 }
+        }
+    }
+
+    pub fn record_failure() -> Document {
+        doc! {
+            "$inc": { "consecutive_failures": 1 }
+        }
+    }
+
+    pub fn disable() -> Document {
+        doc! {
+            "$set": { "disabled": true }
+        }
+    }
+
+    pub fn enable() -> Document {
+        doc! {
+            "$set": { "disabled": false, "consecutive_failures": 0 }
+        }
+    }
+}
+
+/// The BSON representation of `kind`, as stored in `Webhook::events`.
+fn kind_to_bson(kind: WebhookEventKind) -> Bson {
+    to_bson(&kind).expect("WebhookEventKind always serializes")
+}
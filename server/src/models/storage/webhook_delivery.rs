@@ -0,0 +1,171 @@
+use mongodb::bson::{doc, oid::ObjectId, Bson, Document};
+use serde::{Deserialize, Serialize};
+
+use crate::models::common::WebhookEvent;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    /// Not yet delivered, but will be (re)attempted.
+    Pending,
+    Succeeded,
+    /// Gave up, either after too many attempts or because the webhook was
+    /// disabled or removed.
+    Failed,
+}
+
+/// A single event's delivery to a single webhook. Serves both as the queue of
+/// deliveries to attempt and, once finished, as the delivery log.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    #[serde(rename = "_id")]
+    id: ObjectId,
+    webhook_id: ObjectId,
+    /// The owner of the webhook.
+    owner_id: String,
+    event: WebhookEvent,
+    status: DeliveryStatus,
+    /// How many delivery attempts have been made so far.
+    attempts: i32,
+    /// When a pending delivery is next due, in epoch-seconds. Pushed back
+    /// while an attempt is in flight, so no two workers attempt it at once.
+    next_attempt_at: i64,
+    /// The HTTP status of the last attempt, if the receiver responded.
+    last_status_code: Option<i32>,
+    /// Why the last attempt failed, if it did.
+    last_error: Option<String>,
+    /// When the event happened, in epoch-seconds.
+    created_at: i64,
+}
+
+impl WebhookDelivery {
+    pub fn new(webhook_id: &ObjectId, owner_id: &str, event: WebhookEvent, now: i64) -> Self {
+        Self {
+            id: ObjectId::new(),
+            webhook_id: webhook_id.clone(),
+            owner_id: String::from(owner_id),
+            event,
+            status: DeliveryStatus::Pending,
+            attempts: 0,
+            next_attempt_at: now,
+            last_status_code: None,
+            last_error: None,
+            created_at: now,
+        }
+    }
+
+    pub fn id(&self) -> &ObjectId {
+        &self.id
+    }
+
+    pub fn webhook_id(&self) -> &ObjectId {
+        &self.webhook_id
+    }
+
+    pub fn event(&self) -> &WebhookEvent {
+        &self.event
+    }
+
+    pub fn status(&self) -> DeliveryStatus {
+        self.status
+    }
+
+    pub fn attempts(&self) -> i32 {
+        self.attempts
+    }
+
+    pub fn last_status_code(&self) -> Option<i32> {
+        self.last_status_code
+    }
+
+    pub fn last_error(&self) -> Option<&str> {
+        self.last_error.as_deref()
+    }
+
+    pub fn created_at(&self) -> i64 {
+        self.created_at
+    }
+}
+
+impl WebhookDelivery {
+    pub fn find_by_id(id: &ObjectId) -> Document {
+        doc! {
+            "_id": id.clone()
+        }
+    }
+
+    pub fn find_by_webhook(webhook_id: &ObjectId) -> Document {
+        doc! {
+            "webhook_id": webhook_id.clone()
+        }
+    }
+
+    pub fn find_by_owner_and_webhook(owner_id: &str, webhook_id: &ObjectId) -> Document {
+        doc! {
+            "webhook_id": webhook_id.clone(),
+            "owner_id": owner_id
+        }
+    }
+
+    /// Matches deliveries that have succeeded or been given up on, for events
+    /// that happened before `created_before`.
+    pub fn find_finished_before(created_before: i64) -> Document {
+        doc! {
+            "status": { "$in": ["succeeded", "failed"] },
+            "created_at": { "$lt": created_before }
+        }
+    }
+
+    /// Matches pending deliveries due at `now`.
+    pub fn find_due(now: i64) -> Document {
+        doc! {
+            "status": "pending",
+            "next_attempt_at": { "$lte": now }
+        }
+    }
+
+    /// Leases a delivery until `lease_until`, so no one else picks it up
+    /// while we're attempting it.
+    pub fn lease(lease_until: i64) -> Document {
+        doc! {
+            "$set": { "next_attempt_at": lease_until }
+        }
+    }
+
+    /// Gives up on a delivery without attempting it, e.g. because its webhook
+    /// has since been disabled.
+    pub fn abandon(reason: &str) -> Document {
+        doc! {
+            "$set": {
+                "status": "failed",
+                "last_error": reason
+            }
+        }
+    }
+
+    /// Records the `attempts`th attempt, which got `status_code` back (if the
+    /// receiver responded at all). If it failed with `error`, the delivery is
+    /// retried at `retry_at`, or abandoned if that's `None`.
+    pub fn record_attempt(
+        attempts: i32,
+        status_code: Option<i32>,
+        error: Option<&str>,
+        retry_at: Option<i64>,
+    ) -> Document {
+        let status = match (error, retry_at) {
+            (None, _) => "succeeded",
+            (Some(_), Some(_)) => "pending",
+            (Some(_), None) => "failed",
+        };
+
+        doc! {
+            "$set": {
+                "status": status,
+                "attempts": attempts,
+                "next_attempt_at": retry_at.unwrap_or(0),
+                "last_status_code": status_code.map_or(Bson::Null, Bson::from),
+                "last_error": error.map_or(Bson::Null, Bson::from)
+            }
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use std::time::{Duration, Instant};
+
+use jsonwebtoken::{encode, errors::Result as JwtResult, Algorithm, EncodingKey, Header};
+use reqwest::{Client, StatusCode};
+use rocket::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::sync::Mutex;
+use tokio_compat_02::FutureExt;
+
+use super::{NotificationProvider, ProviderError};
+use crate::models::common::{now_epoch_secs, Notification};
+
+/// Delivers notifications to iOS devices via the Apple Push Notification
+/// service, authenticating with a token signed by our APNs key.
+///
+/// See: https://developer.apple.com/documentation/usernotifications/setting_up_a_remote_notification_server/sending_notification_requests_to_apns
+pub struct ApnsProvider {
+    client: Client,
+    endpoint: &'static str,
+    /// Our app's bundle ID.
+    topic: String,
+    team_id: String,
+    key_id: String,
+    signing_key: EncodingKey,
+    /// The current provider token, and when it was minted.
+    token: Mutex<Option<(String, Instant)>>,
+}
+
+pub struct ApnsConfig {
+    pub team_id: String,
+    pub key_id: String,
+    /// The contents of the `.p8` key file from the Apple developer portal.
+    pub signing_key_pem: Vec<u8>,
+    pub topic: String,
+    /// Whether to use the sandbox environment, for development builds.
+    pub sandbox: bool,
+}
+
+impl ApnsProvider {
+    const PRODUCTION_ENDPOINT: &'static str = "https://api.push.apple.com";
+    const SANDBOX_ENDPOINT: &'static str = "https://api.sandbox.push.apple.com";
+
+    /// APNs rejects provider tokens older than an hour, and throttles us if
+    /// we mint new ones more often than every 20 minutes.
+    const TOKEN_LIFETIME: Duration = Duration::from_secs(50 * 60);
+
+    pub fn new(config: ApnsConfig) -> JwtResult<Self> {
+        Ok(Self {
+            // APNs only speaks HTTP/2.
+            client: Client::builder()
+                .http2_prior_knowledge()
+                .build()
+                .expect("Failed to build APNs HTTP client"),
+            endpoint: if config.sandbox {
+                ApnsProvider::SANDBOX_ENDPOINT
+            } else {
+                ApnsProvider::PRODUCTION_ENDPOINT
+            },
+            topic: config.topic,
+            team_id: config.team_id,
+            key_id: config.key_id,
+            signing_key: EncodingKey::from_ec_pem(&config.signing_key_pem)?,
+            token: Mutex::new(None),
+        })
+    }
+
+    /// Returns the cached provider token, minting a new one if it's too old.
+    async fn provider_token(&self) -> Result<String, ProviderError> {
+        let mut cached = self.token.lock().await;
+
+        if let Some((token, minted_at)) = cached.as_ref() {
+            if minted_at.elapsed() < ApnsProvider::TOKEN_LIFETIME {
+                return Ok(token.clone());
+            }
+        }
+
+        #[derive(Serialize)]
+        struct ProviderClaims<'a> {
+            iss: &'a str,
+            iat: i64,
+        }
+
+        let mut header = Header::new(Algorithm::ES256);
+        header.kid = Some(self.key_id.clone());
+
+        let claims = ProviderClaims {
+            iss: &self.team_id,
+            iat: now_epoch_secs(),
+        };
+
+        let token = encode(&header, &claims, &self.signing_key)
+            .map_err(|err| ProviderError::Permanent(format!("Failed to sign token: {}", err)))?;
+
+        *cached = Some((token.clone(), Instant::now()));
+
+        Ok(token)
+    }
+
+    /// Forgets the cached provider token, e.g. because APNs rejected it.
+    async fn discard_provider_token(&self) {
+        *self.token.lock().await = None;
+    }
+}
+
+#[async_trait]
+impl NotificationProvider for ApnsProvider {
+    async fn send(
+        &self,
+        device_token: &str,
+        notification: &Notification,
+    ) -> Result<(), ProviderError> {
+        // Need the `.compat()` wrappers around futures from `reqwest`, since
+        // it uses Tokio 0.2 and we will be running on Tokio 0.3.
+
+        #[derive(Deserialize)]
+        struct ErrorResponse {
+            reason: String,
+        }
+
+        let payload = json!({
+            "aps": {
+                "alert": {
+                    "title": notification.title(),
+                    "body": notification.body(),
+                },
+                "sound": "default",
+            },
+            "sonar": notification,
+        });
+
+        let response = self
+            .client
+            .post(&format!("{}/3/device/{}", self.endpoint, device_token))
+            .header(
+                "authorization",
+                format!("bearer {}", self.provider_token().await?),
+            )
+            .header("apns-topic", &self.topic)
+            .header("apns-push-type", "alert")
+            .json(&payload)
+            .send()
+            .compat() // shim
+            .await?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+
+        let reason = response
+            .json::<ErrorResponse>()
+            .compat() // shim
+            .await
+            .map(|err| err.reason)
+            .unwrap_or_else(|_| String::from("Unknown"));
+
+        match (status, reason.as_str()) {
+            (StatusCode::GONE, _) | (_, "BadDeviceToken") | (_, "DeviceTokenNotForTopic") => {
+                Err(ProviderError::InvalidToken)
+            }
+            (StatusCode::FORBIDDEN, "ExpiredProviderToken") => {
+                self.discard_provider_token().await;
+
+                Err(ProviderError::Transient(reason))
+            }
+            (StatusCode::TOO_MANY_REQUESTS, _) => Err(ProviderError::Transient(reason)),
+            (status, _) if status.is_server_error() => Err(ProviderError::Transient(reason)),
+            _ => Err(ProviderError::Permanent(reason)),
+        }
+    }
+}
@@ -0,0 +1,255 @@
+use std::{env, fs, path::PathBuf, time::Duration};
+
+use super::{
+    ApnsConfig, ApnsProvider, FcmProvider, NotificationProvider, ProviderError, StubProvider,
+};
+use crate::{
+    models::{
+        common::now_epoch_secs,
+        storage::{DevicePlatform, OutboxEntry},
+    },
+    retry::Backoff,
+    storage::{MongoManager, MongoResult},
+};
+
+/// Drains the notification outbox in the background, handing each entry to
+/// the provider for its device's platform and retrying failures with backoff.
+pub struct NotificationDispatcher {
+    mongo: MongoManager,
+    ios: Box<dyn NotificationProvider>,
+    android: Box<dyn NotificationProvider>,
+}
+
+impl NotificationDispatcher {
+    /// How often to check the outbox for due entries.
+    const POLL_INTERVAL: Duration = Duration::from_secs(5);
+    /// How long an entry is leased for while we attempt it. If we crash
+    /// mid-attempt, it becomes due again after this long.
+    const LEASE_SECS: i64 = 60;
+    /// The most entries to attempt per poll.
+    const BATCH_SIZE: usize = 100;
+    const BACKOFF: Backoff = Backoff::new(Duration::from_secs(30), Duration::from_secs(60 * 60), 8);
+
+    pub fn new(
+        mongo: MongoManager,
+        ios: Box<dyn NotificationProvider>,
+        android: Box<dyn NotificationProvider>,
+    ) -> Self {
+        Self {
+            mongo,
+            ios,
+            android,
+        }
+    }
+
+    /// Builds a dispatcher whose providers are configured from the environment.
+    /// Platforms without complete configuration fall back to a `StubProvider`.
+    /// Panics if configuration is present but unusable, e.g. an unreadable key.
+    ///
+    /// - `SONAR_APNS_TEAM_ID`, `SONAR_APNS_KEY_ID`, `SONAR_APNS_KEY_PATH`, and
+    ///   `SONAR_APNS_TOPIC` configure APNs. Set `SONAR_APNS_SANDBOX` to use
+    ///   the sandbox environment.
+    /// - `SONAR_FCM_SERVER_KEY` configures FCM.
+    /// - `SONAR_PUSH_STUB_LOG` is a file the stub provider appends to.
+    pub fn from_env(mongo: MongoManager) -> Self {
+        let stub = || -> Box<dyn NotificationProvider> {
+            Box::new(StubProvider::new(
+                env::var_os("SONAR_PUSH_STUB_LOG").map(PathBuf::from),
+            ))
+        };
+
+        let apns_vars = (
+            env::var("SONAR_APNS_TEAM_ID"),
+            env::var("SONAR_APNS_KEY_ID"),
+            env::var("SONAR_APNS_KEY_PATH"),
+            env::var("SONAR_APNS_TOPIC"),
+        );
+
+        let ios: Box<dyn NotificationProvider> = match apns_vars {
+            (Ok(team_id), Ok(key_id), Ok(key_path), Ok(topic)) => Box::new(
+                ApnsProvider::new(ApnsConfig {
+                    team_id,
+                    key_id,
+                    signing_key_pem: fs::read(key_path).expect("Failed to read APNs key"),
+                    topic,
+                    sandbox: env::var_os("SONAR_APNS_SANDBOX").is_some(),
+                })
+                .expect("Failed to load APNs key"),
+            ),
+            _ => stub(),
+        };
+
+        let android: Box<dyn NotificationProvider> = match env::var("SONAR_FCM_SERVER_KEY") {
+            Ok(server_key) => Box::new(FcmProvider::new(server_key)),
+            Err(_) => stub(),
+        };
+
+        NotificationDispatcher::new(mongo, ios, android)
+    }
+
+    /// Dispatches due notifications forever.
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(NotificationDispatcher::POLL_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            if let Err(mongo_err) = self.dispatch_due().await {
+                eprintln!("Failed to dispatch notifications: {:?}", mongo_err);
+            }
+        }
+    }
+
+    async fn dispatch_due(&self) -> MongoResult<()> {
+        for _ in 0..NotificationDispatcher::BATCH_SIZE {
+            let now = now_epoch_secs();
+
+            match self
+                .mongo
+                .claim_due_notification(now, now + NotificationDispatcher::LEASE_SECS)
+                .await?
+            {
+                Some(entry) => self.deliver(entry).await?,
+                None => break,
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn deliver(&self, entry: OutboxEntry) -> MongoResult<()> {
+        let provider = match entry.platform() {
+            DevicePlatform::Ios => &self.ios,
+            DevicePlatform::Android => &self.android,
+        };
+
+        let result = provider
+            .send(entry.device_token(), entry.notification())
+            .await;
+
+        match Disposition::of(result, entry.attempts() + 1) {
+            Disposition::Delivered => self.mongo.complete_notification(entry.id()).await,
+            Disposition::DropDevice => {
+                self.mongo
+                    .unregister_user_device(entry.user_id(), entry.device_token())
+                    .await?;
+
+                self.mongo.complete_notification(entry.id()).await
+            }
+            Disposition::Retry {
+                attempts,
+                delay,
+                error,
+            } => {
+                let retry_at = now_epoch_secs() + delay.as_secs() as i64;
+
+                self.mongo
+                    .reschedule_notification(entry.id(), attempts, retry_at, &error)
+                    .await
+            }
+            Disposition::GiveUp { error } => {
+                eprintln!(
+                    "Giving up on notification {} after {} attempts: {}",
+                    entry.id(),
+                    entry.attempts() + 1,
+                    error
+                );
+
+                self.mongo.complete_notification(entry.id()).await
+            }
+        }
+    }
+}
+
+/// What to do with an outbox entry after an attempt to deliver it.
+#[derive(Debug, PartialEq)]
+enum Disposition {
+    Delivered,
+    /// The device is gone, so stop sending to it.
+    DropDevice,
+    Retry {
+        attempts: i32,
+        delay: Duration,
+        error: String,
+    },
+    GiveUp {
+        error: String,
+    },
+}
+
+impl Disposition {
+    /// Decides what to do after the `attempts`th attempt produced `result`.
+    fn of(result: Result<(), ProviderError>, attempts: i32) -> Self {
+        match result {
+            Ok(()) => Disposition::Delivered,
+            Err(ProviderError::InvalidToken) => Disposition::DropDevice,
+            Err(ProviderError::Transient(error)) => {
+                match NotificationDispatcher::BACKOFF.delay_after(attempts) {
+                    Some(delay) => Disposition::Retry {
+                        attempts,
+                        delay,
+                        error,
+                    },
+                    None => Disposition::GiveUp { error },
+                }
+            }
+            Err(ProviderError::Permanent(error)) => Disposition::GiveUp { error },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    /// Tests that transient failures are retried with increasing delays.
+    fn test_transient_failures_back_off() {
+        let first = Disposition::of(Err(ProviderError::Transient(String::from("503"))), 1);
+        let second = Disposition::of(Err(ProviderError::Transient(String::from("503"))), 2);
+
+        assert_eq!(
+            first,
+            Disposition::Retry {
+                attempts: 1,
+                delay: Duration::from_secs(30),
+                error: String::from("503"),
+            }
+        );
+        assert_eq!(
+            second,
+            Disposition::Retry {
+                attempts: 2,
+                delay: Duration::from_secs(60),
+                error: String::from("503"),
+            }
+        );
+    }
+
+    #[test]
+    /// Tests that we give up once transient failures exhaust our attempts,
+    /// and immediately on permanent failures.
+    fn test_gives_up() {
+        assert_eq!(
+            Disposition::of(Err(ProviderError::Transient(String::from("503"))), 8),
+            Disposition::GiveUp {
+                error: String::from("503")
+            }
+        );
+        assert_eq!(
+            Disposition::of(Err(ProviderError::Permanent(String::from("400"))), 1),
+            Disposition::GiveUp {
+                error: String::from("400")
+            }
+        );
+    }
+
+    #[test]
+    /// Tests that invalid tokens drop the device rather than retrying.
+    fn test_invalid_token_drops_device() {
+        assert_eq!(
+            Disposition::of(Err(ProviderError::InvalidToken), 1),
+            Disposition::DropDevice
+        );
+    }
+}
@@ -0,0 +1,98 @@
+use reqwest::Client;
+use rocket::async_trait;
+use serde::Deserialize;
+use serde_json::json;
+use tokio_compat_02::FutureExt;
+
+use super::{NotificationProvider, ProviderError};
+use crate::models::common::Notification;
+
+/// Delivers notifications to Android devices via Firebase Cloud Messaging's
+/// HTTP API, authenticating with our project's server key.
+///
+/// See: https://firebase.google.com/docs/cloud-messaging/http-server-ref
+pub struct FcmProvider {
+    client: Client,
+    server_key: String,
+}
+
+impl FcmProvider {
+    const ENDPOINT: &'static str = "https://fcm.googleapis.com/fcm/send";
+
+    pub fn new(server_key: String) -> Self {
+        Self {
+            client: Client::new(),
+            server_key,
+        }
+    }
+}
+
+#[async_trait]
+impl NotificationProvider for FcmProvider {
+    async fn send(
+        &self,
+        device_token: &str,
+        notification: &Notification,
+    ) -> Result<(), ProviderError> {
+        // Need the `.compat()` wrappers around futures from `reqwest`, since
+        // it uses Tokio 0.2 and we will be running on Tokio 0.3.
+
+        #[derive(Deserialize)]
+        struct SendResponse {
+            results: Vec<SendResult>,
+        }
+
+        #[derive(Deserialize)]
+        struct SendResult {
+            error: Option<String>,
+        }
+
+        let payload = json!({
+            "to": device_token,
+            "notification": {
+                "title": notification.title(),
+                "body": notification.body(),
+            },
+            "data": notification,
+        });
+
+        let response = self
+            .client
+            .post(FcmProvider::ENDPOINT)
+            .header("authorization", format!("key={}", self.server_key))
+            .json(&payload)
+            .send()
+            .compat() // shim
+            .await?;
+
+        let status = response.status();
+        if status.is_server_error() {
+            return Err(ProviderError::Transient(status.to_string()));
+        } else if !status.is_success() {
+            return Err(ProviderError::Permanent(status.to_string()));
+        }
+
+        // FCM reports per-device failures in the body of a 200.
+        let error = response
+            .json::<SendResponse>()
+            .compat() // shim
+            .await?
+            .results
+            .into_iter()
+            .next()
+            .and_then(|result| result.error);
+
+        match error.as_deref() {
+            None => Ok(()),
+            Some("NotRegistered") | Some("InvalidRegistration") | Some("MismatchSenderId") => {
+                Err(ProviderError::InvalidToken)
+            }
+            Some(reason @ "Unavailable")
+            | Some(reason @ "InternalServerError")
+            | Some(reason @ "DeviceMessageRateExceeded") => {
+                Err(ProviderError::Transient(String::from(reason)))
+            }
+            Some(other) => Err(ProviderError::Permanent(String::from(other))),
+        }
+    }
+}
@@ -0,0 +1,11 @@
+mod apns;
+mod dispatcher;
+mod fcm;
+mod provider;
+mod stub;
+
+pub use apns::{ApnsConfig, ApnsProvider};
+pub use dispatcher::NotificationDispatcher;
+pub use fcm::FcmProvider;
+pub use provider::{NotificationProvider, ProviderError};
+pub use stub::StubProvider;
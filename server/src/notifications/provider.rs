@@ -0,0 +1,30 @@
+use rocket::async_trait;
+
+use crate::models::common::Notification;
+
+/// A service that can deliver a notification to a device, e.g. APNs.
+#[async_trait]
+pub trait NotificationProvider: Send + Sync {
+    async fn send(
+        &self,
+        device_token: &str,
+        notification: &Notification,
+    ) -> Result<(), ProviderError>;
+}
+
+#[derive(Debug)]
+pub enum ProviderError {
+    /// The device token is no longer valid (e.g., the app was uninstalled),
+    /// so we should stop sending to it.
+    InvalidToken,
+    /// Delivery failed, but might succeed if retried later.
+    Transient(String),
+    /// Delivery failed, and will keep failing no matter how often it's retried.
+    Permanent(String),
+}
+
+impl From<reqwest::Error> for ProviderError {
+    fn from(err: reqwest::Error) -> Self {
+        ProviderError::Transient(err.to_string())
+    }
+}
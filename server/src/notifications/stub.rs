@@ -0,0 +1,125 @@
+use std::path::PathBuf;
+
+use rocket::async_trait;
+use serde_json::json;
+use tokio::{fs::OpenOptions, io::AsyncWriteExt};
+
+use super::{NotificationProvider, ProviderError};
+use crate::models::common::Notification;
+
+/// Logs notifications instead of delivering them, for local development and
+/// tests. Each notification is printed, and also appended as a JSON line to
+/// `log_path` if one is given.
+///
+/// Device tokens starting with `StubProvider::INVALID_TOKEN_PREFIX` are
+/// rejected as invalid, to exercise clean-up of stale devices.
+pub struct StubProvider {
+    log_path: Option<PathBuf>,
+}
+
+impl StubProvider {
+    pub const INVALID_TOKEN_PREFIX: &'static str = "invalid-";
+
+    pub fn new(log_path: Option<PathBuf>) -> Self {
+        Self { log_path }
+    }
+}
+
+#[async_trait]
+impl NotificationProvider for StubProvider {
+    async fn send(
+        &self,
+        device_token: &str,
+        notification: &Notification,
+    ) -> Result<(), ProviderError> {
+        if device_token.starts_with(StubProvider::INVALID_TOKEN_PREFIX) {
+            return Err(ProviderError::InvalidToken);
+        }
+
+        let line = json!({
+            "device_token": device_token,
+            "title": notification.title(),
+            "body": notification.body(),
+            "notification": notification,
+        })
+        .to_string();
+
+        eprintln!("Stub push notification: {}", line);
+
+        if let Some(log_path) = &self.log_path {
+            let mut log = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(log_path)
+                .await
+                .map_err(|err| ProviderError::Transient(err.to_string()))?;
+
+            log.write_all(format!("{}\n", line).as_bytes())
+                .await
+                .map_err(|err| ProviderError::Transient(err.to_string()))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use serde_json::Value;
+
+    #[tokio::test]
+    /// Tests that sent notifications are appended to the log file, one JSON
+    /// object per line.
+    async fn test_appends_notifications_to_log() {
+        let log_path =
+            std::env::temp_dir().join(format!("sonar-stub-push-{}.log", std::process::id()));
+        let provider = StubProvider::new(Some(log_path.clone()));
+
+        for token in &["token-a", "token-b"] {
+            provider
+                .send(token, &utils::notification())
+                .await
+                .expect("Stub failed to send");
+        }
+
+        let log = tokio::fs::read_to_string(&log_path)
+            .await
+            .expect("Failed to read log");
+        let lines: Vec<Value> = log
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("Line was not JSON"))
+            .collect();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["device_token"], "token-a");
+        assert_eq!(lines[1]["device_token"], "token-b");
+        assert_eq!(lines[1]["notification"]["type"], "location_requested");
+
+        let _ = tokio::fs::remove_file(&log_path).await;
+    }
+
+    #[tokio::test]
+    /// Tests that tokens with the invalid prefix are rejected.
+    async fn test_rejects_invalid_tokens() {
+        let provider = StubProvider::new(None);
+
+        assert!(matches!(
+            provider.send("invalid-token", &utils::notification()).await,
+            Err(ProviderError::InvalidToken)
+        ));
+    }
+
+    mod utils {
+        use super::*;
+
+        pub fn notification() -> Notification {
+            Notification::LocationRequested {
+                from_user_id: String::from("user_id"),
+                from_display_name: String::from("Agent 007"),
+                request_id: String::from("request_id"),
+            }
+        }
+    }
+}
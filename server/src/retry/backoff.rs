@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+/// An exponential backoff schedule for retrying deliveries: the `n`th retry
+/// waits `base * 2^(n - 1)`, capped at `max_delay`, and no more than
+/// `max_attempts` attempts are made in total.
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+    base: Duration,
+    max_delay: Duration,
+    max_attempts: i32,
+}
+
+impl Backoff {
+    pub const fn new(base: Duration, max_delay: Duration, max_attempts: i32) -> Self {
+        Self {
+            base,
+            max_delay,
+            max_attempts,
+        }
+    }
+
+    /// How long to wait before the next attempt, given that `attempts` have
+    /// been made so far. `None` if we should give up.
+    pub fn delay_after(&self, attempts: i32) -> Option<Duration> {
+        if attempts < 1 || attempts >= self.max_attempts {
+            return None;
+        }
+
+        // Past 2^16 we're certainly over any sane `max_delay` anyway.
+        let factor = 1u32 << (attempts - 1).min(16);
+
+        Some(
+            self.base
+                .checked_mul(factor)
+                .map_or(self.max_delay, |delay| delay.min(self.max_delay)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const BACKOFF: Backoff = Backoff::new(Duration::from_secs(10), Duration::from_secs(60), 6);
+
+    #[test]
+    /// Tests that delays double after each attempt.
+    fn test_delay_doubles() {
+        assert_eq!(BACKOFF.delay_after(1), Some(Duration::from_secs(10)));
+        assert_eq!(BACKOFF.delay_after(2), Some(Duration::from_secs(20)));
+        assert_eq!(BACKOFF.delay_after(3), Some(Duration::from_secs(40)));
+    }
+
+    #[test]
+    /// Tests that delays are capped at the max delay.
+    fn test_delay_is_capped() {
+        assert_eq!(BACKOFF.delay_after(4), Some(Duration::from_secs(60)));
+        assert_eq!(BACKOFF.delay_after(5), Some(Duration::from_secs(60)));
+    }
+
+    #[test]
+    /// Tests that we give up after the max number of attempts.
+    fn test_gives_up_after_max_attempts() {
+        assert_eq!(BACKOFF.delay_after(6), None);
+        assert_eq!(BACKOFF.delay_after(100), None);
+    }
+}
@@ -0,0 +1,55 @@
+use rocket::{Rocket, State};
+use rocket_contrib::json::Json;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{
+    auth::openid::{AuthConfig, MintRequest, MockProvider},
+    models::common::now_epoch_secs,
+};
+
+#[derive(Serialize)]
+struct MintResponse {
+    id_token: String,
+}
+
+/// Serves a `MockProvider` from the server itself, beneath its own address,
+/// and adds it to `auth_config`'s authorities so its tokens are accepted.
+pub fn mount(rocket: Rocket, mut auth_config: AuthConfig) -> (Rocket, AuthConfig) {
+    let port = rocket
+        .figment()
+        .extract_inner::<u16>("port")
+        .unwrap_or(8000);
+    let provider = MockProvider::new(&format!("http://localhost:{}", port));
+
+    eprintln!(
+        "Serving a mock OpenID provider at {}, which signs tokens for anyone",
+        provider.issuer()
+    );
+    auth_config.authorities.push(provider.authority_config());
+
+    let rocket = rocket.manage(provider).mount(
+        MockProvider::MOUNT_PATH,
+        routes![get_discovery_document, get_key_set, mint_token],
+    );
+
+    (rocket, auth_config)
+}
+
+#[get("/.well-known/openid-configuration")]
+fn get_discovery_document(provider: State<'_, MockProvider>) -> Json<Value> {
+    Json(provider.discovery_document())
+}
+
+#[get("/jwks")]
+fn get_key_set(provider: State<'_, MockProvider>) -> Json<Value> {
+    Json(provider.key_set())
+}
+
+/// Mints an ID token for any user, with whatever claims are asked for.
+#[post("/token", data = "<request>")]
+fn mint_token(provider: State<'_, MockProvider>, request: Json<MintRequest>) -> Json<MintResponse> {
+    Json(MintResponse {
+        id_token: provider.mint(request.into_inner(), now_epoch_secs()),
+    })
+}
@@ -1,3 +1,5 @@
+#[cfg(feature = "mock-oidc")]
+pub mod mock_oidc;
 mod route_result;
 
 pub use route_result::{RouteResult, ToRouteResult};
@@ -0,0 +1,219 @@
+use std::collections::HashSet;
+
+use futures::stream::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId},
+    error::ErrorKind,
+    options::{FindOneAndUpdateOptions, ReturnDocument},
+    Collection,
+};
+
+use super::{MongoError, MongoManager, MongoResult};
+use crate::{
+    auth::openid::ProviderIdentity,
+    models::{
+        common::now_epoch_secs,
+        storage::{Identity, Storable, User},
+    },
+};
+
+impl MongoManager {
+    const IDENTITIES_COLLECTION_NAME: &'static str = "identities";
+    const MIGRATIONS_COLLECTION_NAME: &'static str = "migrations";
+    /// The `_id` of the record that `migrate_legacy_user_ids` has run.
+    const LEGACY_USER_IDS_MIGRATION: &'static str = "link_legacy_user_ids";
+
+    /// Creates the identities collection's indexes, if they don't exist yet.
+    pub async fn create_identity_indexes(&self) -> MongoResult<()> {
+        self.database()
+            .run_command(
+                doc! {
+                    "createIndexes": MongoManager::IDENTITIES_COLLECTION_NAME,
+                    "indexes": [Identity::unique_provider_index()]
+                },
+                None,
+            )
+            .await
+            .map(|_| {})
+    }
+
+    /// Get the ID of the user `identity` is linked to. If it isn't linked to
+    /// anyone yet, it's linked to a new user, so signing in for the first
+    /// time signs up.
+    pub async fn get_or_create_user_id(
+        &self,
+        identity: &ProviderIdentity,
+        now: i64,
+    ) -> MongoResult<String> {
+        self.link_identity(identity, &User::generate_id(), now)
+            .await
+            .map(|linked| String::from(linked.user_id()))
+    }
+
+    /// Links `identity` to the user with the given `user_id`, unless it's
+    /// already linked to someone. Returns the identity as it's now linked,
+    /// which may be to someone else.
+    pub async fn link_identity(
+        &self,
+        identity: &ProviderIdentity,
+        user_id: &str,
+        now: i64,
+    ) -> MongoResult<Identity> {
+        let options = FindOneAndUpdateOptions::builder()
+            .upsert(true)
+            .return_document(ReturnDocument::After)
+            .build();
+
+        let linked = self
+            .identities_collection()
+            .find_one_and_update(
+                Identity::find_by_provider(&identity.issuer, &identity.subject),
+                Identity::link(user_id, now),
+                options,
+            )
+            .await?
+            .ok_or_else(|| {
+                MongoError::from(ErrorKind::ResponseError {
+                    message: String::from("Upserted identity was not returned"),
+                })
+            })?;
+
+        Identity::from_document(linked)
+    }
+
+    /// Whether the user with the given `user_id` can be merged into another
+    /// by moving their identity over: they have only the one identity, and
+    /// nothing else that would be lost without them.
+    pub async fn is_user_mergeable(&self, user_id: &str) -> MongoResult<bool> {
+        if self.get_identities(user_id).await?.len() > 1 {
+            return Ok(false);
+        }
+
+        if let Some(user) = self.find_user_by_id(user_id).await? {
+            if !user.is_empty() {
+                return Ok(false);
+            }
+        }
+
+        Ok(self.get_personal_access_tokens(user_id).await?.is_empty()
+            && self.get_webhooks_for_owner(user_id).await?.is_empty())
+    }
+
+    /// Moves `identity` from the user it's linked to, to the user with the
+    /// given `user_id`. Returns the identity as it's now linked, or `None`
+    /// if it was unlinked or moved in the meantime.
+    pub async fn relink_identity(
+        &self,
+        identity: &Identity,
+        user_id: &str,
+        now: i64,
+    ) -> MongoResult<Option<Identity>> {
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+
+        self.identities_collection()
+            .find_one_and_update(
+                Identity::find_by_user_and_id(identity.user_id(), identity.id()),
+                Identity::relink(user_id, now),
+                options,
+            )
+            .await?
+            .map(Identity::from_document)
+            .transpose()
+    }
+
+    /// Get all identities linked to the user with the given `user_id`.
+    pub async fn get_identities(&self, user_id: &str) -> MongoResult<Vec<Identity>> {
+        self.identities_collection()
+            .find(Identity::find_by_user(user_id), None)
+            .await?
+            .and_then(|document| async move { Identity::from_document(document) })
+            .try_collect()
+            .await
+    }
+
+    /// Unlinks an identity. Returns `false` if the user with the given
+    /// `user_id` has no such identity.
+    pub async fn unlink_identity(&self, user_id: &str, id: &ObjectId) -> MongoResult<bool> {
+        self.identities_collection()
+            .delete_one(Identity::find_by_user_and_id(user_id, id), None)
+            .await
+            .map(|result| result.deleted_count > 0)
+    }
+
+    /// Links every user from before identities existed, whose ID is their
+    /// `oid` from the authority with `legacy_issuer`, to that identity, so
+    /// they keep their ID when they next sign in. Only runs once.
+    ///
+    /// Users already linked to an identity signed up since, so their ID is
+    /// no one's `oid`, and linking it would let whoever has that `oid` sign
+    /// in as them. They're left alone.
+    pub async fn migrate_legacy_user_ids(&self, legacy_issuer: &str) -> MongoResult<()> {
+        if self
+            .migrations_collection()
+            .find_one(
+                doc! { "_id": MongoManager::LEGACY_USER_IDS_MIGRATION },
+                None,
+            )
+            .await?
+            .is_some()
+        {
+            return Ok(());
+        }
+
+        let now = now_epoch_secs();
+        let already_linked = self
+            .identities_collection()
+            .distinct("user_id", None, None)
+            .await?
+            .into_iter()
+            .filter_map(|user_id| user_id.as_str().map(String::from))
+            .collect::<HashSet<_>>();
+        let mut users = self.users_collection().find(None, None).await?;
+        let mut linked = 0;
+        let mut skipped = 0;
+
+        // Linking is idempotent, so a migration that's interrupted can just
+        // run again.
+        while let Some(document) = users.try_next().await? {
+            let user = User::from_document(document)?;
+
+            if already_linked.contains(user.id()) {
+                skipped += 1;
+                continue;
+            }
+
+            let identity = ProviderIdentity {
+                issuer: String::from(legacy_issuer),
+                subject: String::from(user.id()),
+            };
+
+            self.link_identity(&identity, user.id(), now).await?;
+            linked += 1;
+        }
+
+        eprintln!(
+            "Linked {} existing users to {}, skipping {} who signed up since",
+            linked, legacy_issuer, skipped
+        );
+
+        self.migrations_collection()
+            .insert_one(
+                doc! { "_id": MongoManager::LEGACY_USER_IDS_MIGRATION, "completed_at": now },
+                None,
+            )
+            .await
+            .map(|_| {})
+    }
+
+    fn identities_collection(&self) -> Collection {
+        self.database()
+            .collection(MongoManager::IDENTITIES_COLLECTION_NAME)
+    }
+
+    fn migrations_collection(&self) -> Collection {
+        self.database()
+            .collection(MongoManager::MIGRATIONS_COLLECTION_NAME)
+    }
+}
@@ -0,0 +1,127 @@
+use futures::stream::TryStreamExt;
+use mongodb::{
+    bson::oid::ObjectId,
+    error::{ErrorKind, WriteFailure},
+    options::UpdateOptions,
+    Collection,
+};
+
+use super::{MongoError, MongoManager, MongoResult};
+use crate::models::storage::{LocationRequest, Storable, User};
+
+impl MongoManager {
+    const LOCATION_REQUESTS_COLLECTION_NAME: &'static str = "location_requests";
+    const LOCATION_REQUEST_COOLDOWNS_COLLECTION_NAME: &'static str = "location_request_cooldowns";
+    /// The code of the write error for a duplicate key.
+    const DUPLICATE_KEY_CODE: i32 = 11000;
+
+    pub async fn create_location_request(&self, request: &LocationRequest) -> MongoResult<()> {
+        self.location_requests_collection()
+            .insert_one(request.to_document()?, None)
+            .await
+            .map(|_| {})
+    }
+
+    pub async fn get_location_request(
+        &self,
+        id: &ObjectId,
+    ) -> MongoResult<Option<LocationRequest>> {
+        self.location_requests_collection()
+            .find_one(LocationRequest::find_by_id(id), None)
+            .await?
+            .map(LocationRequest::from_document)
+            .transpose()
+    }
+
+    /// Starts the cooldown on the user with the given `requester_id` asking
+    /// the user with the given `target_id` for their location, unless one is
+    /// still running at `now`. Returns whether it started. Checked and
+    /// started in one write, so concurrent requests can't both get through.
+    pub async fn start_location_request_cooldown(
+        &self,
+        requester_id: &str,
+        target_id: &str,
+        now: i64,
+    ) -> MongoResult<bool> {
+        let options = UpdateOptions::builder().upsert(true).build();
+
+        match self
+            .location_request_cooldowns_collection()
+            .update_one(
+                LocationRequest::find_lapsed_cooldown(requester_id, target_id, now),
+                LocationRequest::start_cooldown(now),
+                options,
+            )
+            .await
+        {
+            Ok(_) => Ok(true),
+            // A running cooldown doesn't match, so the upsert tries to insert
+            // another with the same `_id`.
+            Err(mongo_err) if MongoManager::is_duplicate_key(&mongo_err) => Ok(false),
+            Err(mongo_err) => Err(mongo_err),
+        }
+    }
+
+    /// Get the requests to the user with the given `target_id` that are still
+    /// pending at `now`.
+    pub async fn get_pending_location_requests(
+        &self,
+        target_id: &str,
+        now: i64,
+    ) -> MongoResult<Vec<LocationRequest>> {
+        self.location_requests_collection()
+            .find(
+                LocationRequest::find_pending_for_target(target_id, now),
+                None,
+            )
+            .await?
+            .and_then(|document| async move { LocationRequest::from_document(document) })
+            .try_collect()
+            .await
+    }
+
+    /// Fulfills the requests to `target` that are still pending at `now` and
+    /// that their latest ping answers (see `LocationRequest::is_fulfilled_by`).
+    /// The rest stay pending, e.g. until the target resumes sharing.
+    pub async fn fulfill_location_requests(&self, target: &User, now: i64) -> MongoResult<()> {
+        let fulfilled_ids: Vec<ObjectId> = self
+            .get_pending_location_requests(target.id(), now)
+            .await?
+            .into_iter()
+            .filter(|request| request.is_fulfilled_by(target))
+            .map(|request| request.id().clone())
+            .collect();
+
+        if fulfilled_ids.is_empty() {
+            return Ok(());
+        }
+
+        self.location_requests_collection()
+            .update_many(
+                LocationRequest::find_pending_by_ids(fulfilled_ids, now),
+                LocationRequest::fulfill(now),
+                None,
+            )
+            .await
+            .map(|_| {})
+    }
+
+    fn is_duplicate_key(mongo_err: &MongoError) -> bool {
+        match mongo_err.kind.as_ref() {
+            ErrorKind::WriteError(WriteFailure::WriteError(write_err)) => {
+                write_err.code == MongoManager::DUPLICATE_KEY_CODE
+            }
+            _ => false,
+        }
+    }
+
+    fn location_requests_collection(&self) -> Collection {
+        self.database()
+            .collection(MongoManager::LOCATION_REQUESTS_COLLECTION_NAME)
+    }
+
+    fn location_request_cooldowns_collection(&self) -> Collection {
+        self.database()
+            .collection(MongoManager::LOCATION_REQUEST_COOLDOWNS_COLLECTION_NAME)
+    }
+}
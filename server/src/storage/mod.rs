@@ -1,3 +1,10 @@
+mod identities;
+mod location_requests;
 mod mongo_manager;
+mod notification_outbox;
+mod personal_access_tokens;
+mod revocations;
+mod sessions;
+mod webhooks;
 
 pub use mongo_manager::{MongoError, MongoManager, MongoResult};
@@ -1,15 +1,28 @@
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
+use futures::stream::TryStreamExt;
 pub use mongodb::error::{Error as MongoError, Result as MongoResult};
-use mongodb::{options::ClientOptions, Client, Collection, Database};
+use mongodb::{
+    bson::Document,
+    options::{ClientOptions, FindOneAndUpdateOptions, ReturnDocument},
+    Client, Collection, Database,
+};
 
-use crate::models::{
-    common::Location,
-    storage::{Storable, User},
+use crate::{
+    auth::Role,
+    events::LocationHub,
+    models::{
+        common::{now_epoch_secs, Ping},
+        storage::{Device, DevicePlatform, PausedVisibility, Storable, User},
+    },
 };
 
+/// Cheap to clone: clones share the same connection pool and location hub.
+#[derive(Clone)]
 pub struct MongoManager {
     client: Client,
+    /// Published to whenever a user is updated.
+    location_hub: LocationHub,
 }
 
 impl MongoManager {
@@ -26,17 +39,28 @@ impl MongoManager {
 
         Ok(Self {
             client: Client::with_options(options)?,
+            location_hub: LocationHub::new(),
         })
     }
 
-    /// Get the `User` with the given `id`. If none exists, one is created.
-    pub async fn get_user_by_id(&self, id: &str) -> MongoResult<User> {
-        if let Some(existing_user) = self
-            .users_collection()
+    /// The hub that updated users are published to.
+    pub fn location_hub(&self) -> &LocationHub {
+        &self.location_hub
+    }
+
+    /// Get the `User` with the given `id`, if one exists.
+    pub async fn find_user_by_id(&self, id: &str) -> MongoResult<Option<User>> {
+        self.users_collection()
             .find_one(User::find_by_id(id), None)
             .await?
-        {
-            Ok(User::from_document(existing_user)?)
+            .map(User::from_document)
+            .transpose()
+    }
+
+    /// Get the `User` with the given `id`. If none exists, one is created.
+    pub async fn get_user_by_id(&self, id: &str) -> MongoResult<User> {
+        if let Some(existing_user) = self.find_user_by_id(id).await? {
+            Ok(existing_user)
         } else {
             let new_user = User::new(String::from(id));
 
@@ -48,24 +72,234 @@ impl MongoManager {
         }
     }
 
-    /// Updates the location of the user with the given `id`. If no user exists,
-    /// one is created.
-    pub async fn update_user_location(&self, id: &str, location: Location) -> MongoResult<()> {
-        let user = self.get_user_by_id(id).await?;
-        let updated_user = user.update_location(location);
+    /// Deletes the user with the given `id`, if they exist.
+    pub async fn delete_user(&self, id: &str) -> MongoResult<()> {
+        self.users_collection()
+            .delete_one(User::find_by_id(id), None)
+            .await
+            .map(|_| {})
+    }
+
+    /// Get the role of the user with the given `id`. Users that don't exist
+    /// yet have `Role::User`.
+    pub async fn get_user_role(&self, id: &str) -> MongoResult<Role> {
+        match self
+            .users_collection()
+            .find_one(User::find_by_id(id), None)
+            .await?
+        {
+            Some(document) => Ok(User::from_document(document)?.role()),
+            None => Ok(Role::User),
+        }
+    }
+
+    /// Sets the role of the user with the given `id`. Returns `false` if no
+    /// such user exists. Not a change contacts can see, so this doesn't publish
+    /// it or advance the change sequence.
+    pub async fn set_user_role(&self, id: &str, role: Role) -> MongoResult<bool> {
+        self.users_collection()
+            .update_one(User::find_by_id(id), User::set_role(role), None)
+            .await
+            .map(|result| result.matched_count > 0)
+    }
+
+    /// Get all users who share their location to the user with the given `id`.
+    pub async fn get_users_sharing_to(&self, id: &str) -> MongoResult<Vec<User>> {
+        self.users_collection()
+            .find(User::find_shared_to(id), None)
+            .await?
+            .and_then(|document| async move { User::from_document(document) })
+            .try_collect()
+            .await
+    }
+
+    /// Get all users who share their location to the user with the given `id`
+    /// and have been updated since `change_seq`.
+    pub async fn get_users_sharing_to_changed_since(
+        &self,
+        id: &str,
+        change_seq: i64,
+    ) -> MongoResult<Vec<User>> {
+        self.users_collection()
+            .find(User::find_shared_to_changed_since(id, change_seq), None)
+            .await?
+            .and_then(|document| async move { User::from_document(document) })
+            .try_collect()
+            .await
+    }
+
+    /// Sets the last ping of the user with the given `id`, returning the
+    /// updated user. If no user exists, one is created.
+    pub async fn update_user_location(&self, id: &str, ping: &Ping) -> MongoResult<Arc<User>> {
+        let updated_user = self
+            .update_user_matching(User::find_by_id(id), User::update_location(id, ping)?, true)
+            .await?;
+
+        // Upserted, so there's always a user.
+        Ok(updated_user.expect("Upserted user was not returned"))
+    }
+
+    /// Starts sharing the location of the user with the given `id` to the
+    /// user with the given `contact_id`.
+    pub async fn share_user_location(&self, id: &str, contact_id: &str) -> MongoResult<()> {
+        self.update_user_matching(
+            User::find_by_id_not_shared_to(id, contact_id),
+            User::share_to(contact_id),
+            false,
+        )
+        .await?;
+
+        self.users_collection()
+            .update_one(
+                User::find_by_id(contact_id),
+                User::set_shared_with_me_hint(id, true),
+                None,
+            )
+            .await
+            .map(|_| {})
+    }
+
+    /// Stops sharing the location of the user with the given `id` to the
+    /// user with the given `contact_id`.
+    pub async fn unshare_user_location(&self, id: &str, contact_id: &str) -> MongoResult<()> {
+        self.update_user_matching(
+            User::find_by_id_shared_to(id, contact_id),
+            User::unshare_to(contact_id),
+            false,
+        )
+        .await?;
 
         self.users_collection()
-            .replace_one(User::find_by_id(id), updated_user.to_document()?, None)
+            .update_one(
+                User::find_by_id(contact_id),
+                User::set_shared_with_me_hint(id, false),
+                None,
+            )
             .await
             .map(|_| {})
     }
 
-    fn users_collection(&self) -> Collection {
+    /// Registers a device to receive push notifications for the user with the
+    /// given `id`, and only them: a phone handed down to someone else stops
+    /// getting its previous owner's notifications. Returns `false` if no such
+    /// user exists.
+    pub async fn register_user_device(
+        &self,
+        id: &str,
+        platform: DevicePlatform,
+        token: &str,
+    ) -> MongoResult<bool> {
+        let device = Device::new(platform, String::from(token), now_epoch_secs());
+
+        // Replaces any existing registration of the same token, ours or
+        // anyone else's. Mongo can't pull from and push to the same array in
+        // one write.
+        self.users_collection()
+            .update_many(
+                User::find_by_device(token),
+                User::unregister_device(token),
+                None,
+            )
+            .await?;
+
+        self.users_collection()
+            .update_one(User::find_by_id(id), User::register_device(&device)?, None)
+            .await
+            .map(|result| result.matched_count > 0)
+    }
+
+    /// Stops sending push notifications for the user with the given `id` to
+    /// the device with the given `token`. Returns `false` if no such user
+    /// exists.
+    pub async fn unregister_user_device(&self, id: &str, token: &str) -> MongoResult<bool> {
+        self.users_collection()
+            .update_one(User::find_by_id(id), User::unregister_device(token), None)
+            .await
+            .map(|result| result.matched_count > 0)
+    }
+
+    /// Pauses sharing from the user with the given `id`, either to a single
+    /// contact they share to or to everyone. Returns `false` if no such user
+    /// exists, or they don't share to the contact.
+    pub async fn pause_user_sharing(
+        &self,
+        id: &str,
+        contact_id: Option<&str>,
+        visibility: PausedVisibility,
+        until: Option<i64>,
+    ) -> MongoResult<bool> {
+        let filter = match contact_id {
+            Some(contact_id) => User::find_by_id_shared_to(id, contact_id),
+            None => User::find_by_id(id),
+        };
+
+        let user = match self
+            .users_collection()
+            .find_one(filter.clone(), None)
+            .await?
+        {
+            Some(document) => User::from_document(document)?,
+            None => return Ok(false),
+        };
+        let pause = user.new_pause(contact_id, visibility, until);
+
+        self.update_user_matching(filter, User::pause_sharing(contact_id, &pause)?, false)
+            .await
+            .map(|updated_user| updated_user.is_some())
+    }
+
+    /// Resumes sharing from the user with the given `id`, either to a single
+    /// contact or to everyone. Returns `false` if no such user exists.
+    pub async fn resume_user_sharing(
+        &self,
+        id: &str,
+        contact_id: Option<&str>,
+    ) -> MongoResult<bool> {
+        self.update_user_matching(
+            User::find_by_id(id),
+            User::resume_sharing(contact_id),
+            false,
+        )
+        .await
+        .map(|updated_user| updated_user.is_some())
+    }
+
+    /// Applies `update`, which stamps the change sequence, to the user
+    /// matching `filter` (or inserts one, if `upsert`), and publishes the
+    /// result to the location hub. Updates only touch the fields they change,
+    /// so concurrent updates can't undo each other. Returns the updated user,
+    /// if one matched.
+    async fn update_user_matching(
+        &self,
+        filter: Document,
+        update: Document,
+        upsert: bool,
+    ) -> MongoResult<Option<Arc<User>>> {
+        let options = FindOneAndUpdateOptions::builder()
+            .upsert(upsert)
+            .return_document(ReturnDocument::After)
+            .build();
+
+        let updated_user = match self
+            .users_collection()
+            .find_one_and_update(filter, update, options)
+            .await?
+        {
+            Some(updated) => Arc::new(User::from_document(updated)?),
+            None => return Ok(None),
+        };
+
+        self.location_hub.publish(Arc::clone(&updated_user));
+
+        Ok(Some(updated_user))
+    }
+
+    pub(super) fn users_collection(&self) -> Collection {
         self.database()
             .collection(MongoManager::USERS_COLLECTION_NAME)
     }
 
-    fn database(&self) -> Database {
+    pub(super) fn database(&self) -> Database {
         self.client.database(MongoManager::DATABASE_NAME)
     }
 }
@@ -0,0 +1,100 @@
+use mongodb::{
+    bson::{doc, oid::ObjectId},
+    options::FindOneAndUpdateOptions,
+    Collection,
+};
+
+use super::{MongoManager, MongoResult};
+use crate::models::{
+    common::{now_epoch_secs, Notification},
+    storage::{OutboxEntry, Storable},
+};
+
+impl MongoManager {
+    const NOTIFICATION_OUTBOX_COLLECTION_NAME: &'static str = "notification_outbox";
+
+    /// Queues `notification` for delivery to each of the devices registered
+    /// by the user with the given `user_id`, if they exist.
+    pub async fn enqueue_notification(
+        &self,
+        user_id: &str,
+        notification: Notification,
+    ) -> MongoResult<()> {
+        let user = match self.find_user_by_id(user_id).await? {
+            Some(user) => user,
+            None => return Ok(()),
+        };
+        let now = now_epoch_secs();
+
+        let entries = user
+            .devices()
+            .iter()
+            .map(|device| {
+                OutboxEntry::new(user_id, device, notification.clone(), now).to_document()
+            })
+            .collect::<MongoResult<Vec<_>>>()?;
+
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        self.notification_outbox_collection()
+            .insert_many(entries, None)
+            .await
+            .map(|_| {})
+    }
+
+    /// Claims the longest-overdue outbox entry that is due at `now`, leasing
+    /// it until `lease_until` so that no one else attempts it meanwhile.
+    pub async fn claim_due_notification(
+        &self,
+        now: i64,
+        lease_until: i64,
+    ) -> MongoResult<Option<OutboxEntry>> {
+        let options = FindOneAndUpdateOptions::builder()
+            .sort(doc! { "next_attempt_at": 1 })
+            .build();
+
+        self.notification_outbox_collection()
+            .find_one_and_update(
+                OutboxEntry::find_due(now),
+                OutboxEntry::lease(lease_until),
+                options,
+            )
+            .await?
+            .map(OutboxEntry::from_document)
+            .transpose()
+    }
+
+    /// Removes a delivered (or abandoned) entry from the outbox.
+    pub async fn complete_notification(&self, id: &ObjectId) -> MongoResult<()> {
+        self.notification_outbox_collection()
+            .delete_one(OutboxEntry::find_by_id(id), None)
+            .await
+            .map(|_| {})
+    }
+
+    /// Records a failed attempt at an entry, and schedules it to be retried
+    /// at `retry_at`.
+    pub async fn reschedule_notification(
+        &self,
+        id: &ObjectId,
+        attempts: i32,
+        retry_at: i64,
+        error: &str,
+    ) -> MongoResult<()> {
+        self.notification_outbox_collection()
+            .update_one(
+                OutboxEntry::find_by_id(id),
+                OutboxEntry::reschedule(attempts, retry_at, error),
+                None,
+            )
+            .await
+            .map(|_| {})
+    }
+
+    fn notification_outbox_collection(&self) -> Collection {
+        self.database()
+            .collection(MongoManager::NOTIFICATION_OUTBOX_COLLECTION_NAME)
+    }
+}
@@ -0,0 +1,80 @@
+use futures::stream::TryStreamExt;
+use mongodb::{bson::oid::ObjectId, Collection};
+
+use super::{MongoManager, MongoResult};
+use crate::models::storage::{PersonalAccessToken, Storable};
+
+impl MongoManager {
+    const PERSONAL_ACCESS_TOKENS_COLLECTION_NAME: &'static str = "personal_access_tokens";
+
+    pub async fn create_personal_access_token(
+        &self,
+        token: &PersonalAccessToken,
+    ) -> MongoResult<()> {
+        self.personal_access_tokens_collection()
+            .insert_one(token.to_document()?, None)
+            .await
+            .map(|_| {})
+    }
+
+    /// Get all personal access tokens made by the user with the given
+    /// `user_id`, including expired ones.
+    pub async fn get_personal_access_tokens(
+        &self,
+        user_id: &str,
+    ) -> MongoResult<Vec<PersonalAccessToken>> {
+        self.personal_access_tokens_collection()
+            .find(PersonalAccessToken::find_by_user(user_id), None)
+            .await?
+            .and_then(|document| async move { PersonalAccessToken::from_document(document) })
+            .try_collect()
+            .await
+    }
+
+    /// Looks up the unexpired token hashing to `token_hash`, recording that
+    /// it was used at `now`.
+    pub async fn use_personal_access_token(
+        &self,
+        token_hash: &str,
+        now: i64,
+    ) -> MongoResult<Option<PersonalAccessToken>> {
+        let token = match self
+            .personal_access_tokens_collection()
+            .find_one(PersonalAccessToken::find_usable(token_hash, now), None)
+            .await?
+        {
+            Some(document) => PersonalAccessToken::from_document(document)?,
+            None => return Ok(None),
+        };
+
+        if token.needs_last_used_update(now) {
+            self.personal_access_tokens_collection()
+                .update_one(
+                    PersonalAccessToken::find_by_user_and_id(token.user_id(), token.id()),
+                    PersonalAccessToken::record_use(now),
+                    None,
+                )
+                .await?;
+        }
+
+        Ok(Some(token))
+    }
+
+    /// Revokes a personal access token. Returns `false` if the user with the
+    /// given `user_id` has no such token.
+    pub async fn delete_personal_access_token(
+        &self,
+        user_id: &str,
+        id: &ObjectId,
+    ) -> MongoResult<bool> {
+        self.personal_access_tokens_collection()
+            .delete_one(PersonalAccessToken::find_by_user_and_id(user_id, id), None)
+            .await
+            .map(|result| result.deleted_count > 0)
+    }
+
+    fn personal_access_tokens_collection(&self) -> Collection {
+        self.database()
+            .collection(MongoManager::PERSONAL_ACCESS_TOKENS_COLLECTION_NAME)
+    }
+}
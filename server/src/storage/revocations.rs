@@ -0,0 +1,64 @@
+use futures::stream::TryStreamExt;
+use mongodb::{options::ReplaceOptions, Collection};
+
+use super::{MongoManager, MongoResult};
+use crate::models::storage::{Revocation, Storable};
+
+impl MongoManager {
+    const REVOCATIONS_COLLECTION_NAME: &'static str = "revocations";
+
+    /// Stores `revocation`, replacing any earlier one of the same thing.
+    pub async fn revoke(&self, revocation: &Revocation) -> MongoResult<()> {
+        let options = ReplaceOptions::builder().upsert(true).build();
+
+        self.revocations_collection()
+            .replace_one(
+                Revocation::find_by_key(revocation.key()),
+                revocation.to_document()?,
+                options,
+            )
+            .await
+            .map(|_| {})
+    }
+
+    /// Whether a token issued to the user with `user_id` at `issued_at` has
+    /// been revoked, by its `jti`, its session or the user's revocation of
+    /// everything they were issued before some time.
+    pub async fn is_token_revoked(
+        &self,
+        user_id: &str,
+        session_id: Option<&str>,
+        jti: Option<&str>,
+        issued_at: Option<i64>,
+        now: i64,
+    ) -> MongoResult<bool> {
+        let mut keys = vec![Revocation::user_key(user_id)];
+        keys.extend(session_id.map(Revocation::session_key));
+        keys.extend(jti.map(Revocation::token_key));
+
+        let revocations: Vec<Revocation> = self
+            .revocations_collection()
+            .find(Revocation::find_by_keys(&keys), None)
+            .await?
+            .and_then(|document| async move { Revocation::from_document(document) })
+            .try_collect()
+            .await?;
+
+        Ok(revocations
+            .iter()
+            .any(|revocation| revocation.applies_to(issued_at, now)))
+    }
+
+    /// Deletes revocations that have expired by `now`, returning how many.
+    pub async fn delete_expired_revocations(&self, now: i64) -> MongoResult<i64> {
+        self.revocations_collection()
+            .delete_many(Revocation::find_expired(now), None)
+            .await
+            .map(|result| result.deleted_count)
+    }
+
+    fn revocations_collection(&self) -> Collection {
+        self.database()
+            .collection(MongoManager::REVOCATIONS_COLLECTION_NAME)
+    }
+}
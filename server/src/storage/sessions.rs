@@ -0,0 +1,98 @@
+use mongodb::{
+    options::{FindOneAndUpdateOptions, ReturnDocument},
+    Collection,
+};
+
+use super::{MongoManager, MongoResult};
+use crate::models::storage::{RefreshTokenRotation, Session, Storable};
+
+impl MongoManager {
+    const SESSIONS_COLLECTION_NAME: &'static str = "sessions";
+
+    pub async fn create_session(&self, session: &Session) -> MongoResult<()> {
+        self.sessions_collection()
+            .insert_one(session.to_document()?, None)
+            .await
+            .map(|_| {})
+    }
+
+    /// Exchanges the refresh token hashing to `old_hash` for the one hashing
+    /// to `new_hash`, which lapses at `expires_at`. Only one of two racing
+    /// exchanges of the same token can win; the other revokes the session.
+    pub async fn rotate_refresh_token(
+        &self,
+        old_hash: &str,
+        new_hash: &str,
+        now: i64,
+        expires_at: i64,
+    ) -> MongoResult<RefreshTokenRotation> {
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+
+        if let Some(rotated) = self
+            .sessions_collection()
+            .find_one_and_update(
+                Session::find_refreshable(old_hash, now),
+                Session::rotate_refresh_token(old_hash, new_hash, now, expires_at),
+                options,
+            )
+            .await?
+        {
+            return Ok(RefreshTokenRotation::Rotated(Session::from_document(
+                rotated,
+            )?));
+        }
+
+        match self
+            .sessions_collection()
+            .find_one_and_update(
+                Session::find_by_used_refresh_token(old_hash),
+                Session::revoke(now),
+                None,
+            )
+            .await?
+        {
+            Some(reused) => Ok(RefreshTokenRotation::Reused(Session::from_document(
+                reused,
+            )?)),
+            None => Ok(RefreshTokenRotation::Invalid),
+        }
+    }
+
+    /// Revokes the session whose current refresh token hashes to
+    /// `refresh_token_hash`, returning it, if there is one.
+    pub async fn revoke_session_by_refresh_token(
+        &self,
+        refresh_token_hash: &str,
+        now: i64,
+    ) -> MongoResult<Option<Session>> {
+        self.sessions_collection()
+            .find_one_and_update(
+                Session::find_refreshable(refresh_token_hash, now),
+                Session::revoke(now),
+                None,
+            )
+            .await?
+            .map(Session::from_document)
+            .transpose()
+    }
+
+    /// Revokes all the sessions of the user with `user_id`, so none of their
+    /// refresh tokens work anymore. Returns how many were revoked.
+    pub async fn revoke_user_sessions(&self, user_id: &str, now: i64) -> MongoResult<i64> {
+        self.sessions_collection()
+            .update_many(
+                Session::find_unrevoked_by_user(user_id),
+                Session::revoke(now),
+                None,
+            )
+            .await
+            .map(|result| result.modified_count)
+    }
+
+    fn sessions_collection(&self) -> Collection {
+        self.database()
+            .collection(MongoManager::SESSIONS_COLLECTION_NAME)
+    }
+}
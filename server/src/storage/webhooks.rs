@@ -0,0 +1,230 @@
+use futures::stream::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId},
+    options::{FindOneAndUpdateOptions, FindOptions},
+    Collection,
+};
+
+use super::{MongoManager, MongoResult};
+use crate::models::{
+    common::{now_epoch_secs, WebhookEvent},
+    storage::{Storable, Webhook, WebhookDelivery},
+};
+
+impl MongoManager {
+    const WEBHOOKS_COLLECTION_NAME: &'static str = "webhooks";
+    const WEBHOOK_DELIVERIES_COLLECTION_NAME: &'static str = "webhook_deliveries";
+
+    pub async fn create_webhook(&self, webhook: &Webhook) -> MongoResult<()> {
+        self.webhooks_collection()
+            .insert_one(webhook.to_document()?, None)
+            .await
+            .map(|_| {})
+    }
+
+    pub async fn get_webhook(&self, id: &ObjectId) -> MongoResult<Option<Webhook>> {
+        self.webhooks_collection()
+            .find_one(Webhook::find_by_id(id), None)
+            .await?
+            .map(Webhook::from_document)
+            .transpose()
+    }
+
+    /// Get all webhooks registered by the user with the given `owner_id`.
+    pub async fn get_webhooks_for_owner(&self, owner_id: &str) -> MongoResult<Vec<Webhook>> {
+        self.webhooks_collection()
+            .find(Webhook::find_by_owner(owner_id), None)
+            .await?
+            .and_then(|document| async move { Webhook::from_document(document) })
+            .try_collect()
+            .await
+    }
+
+    /// Removes a webhook and its delivery log. Returns `false` if the user
+    /// with the given `owner_id` has no such webhook.
+    pub async fn delete_webhook(&self, owner_id: &str, id: &ObjectId) -> MongoResult<bool> {
+        let deleted = self
+            .webhooks_collection()
+            .delete_one(Webhook::find_by_owner_and_id(owner_id, id), None)
+            .await?
+            .deleted_count;
+
+        if deleted == 0 {
+            return Ok(false);
+        }
+
+        self.webhook_deliveries_collection()
+            .delete_many(WebhookDelivery::find_by_webhook(id), None)
+            .await?;
+
+        Ok(true)
+    }
+
+    /// Re-enables a webhook and resets its failure count. Returns `false` if
+    /// the user with the given `owner_id` has no such webhook.
+    pub async fn enable_webhook(&self, owner_id: &str, id: &ObjectId) -> MongoResult<bool> {
+        self.webhooks_collection()
+            .update_one(
+                Webhook::find_by_owner_and_id(owner_id, id),
+                Webhook::enable(),
+                None,
+            )
+            .await
+            .map(|result| result.matched_count > 0)
+    }
+
+    /// Get the `limit` most recent deliveries to a webhook belonging to the
+    /// user with the given `owner_id`, newest first.
+    pub async fn get_webhook_deliveries(
+        &self,
+        owner_id: &str,
+        webhook_id: &ObjectId,
+        limit: i64,
+    ) -> MongoResult<Vec<WebhookDelivery>> {
+        let options = FindOptions::builder()
+            .sort(doc! { "created_at": -1 })
+            .limit(limit)
+            .build();
+
+        self.webhook_deliveries_collection()
+            .find(
+                WebhookDelivery::find_by_owner_and_webhook(owner_id, webhook_id),
+                options,
+            )
+            .await?
+            .and_then(|document| async move { WebhookDelivery::from_document(document) })
+            .try_collect()
+            .await
+    }
+
+    /// Queues `event` for delivery to each of the enabled webhooks of the user
+    /// with the given `owner_id` that subscribe to it.
+    pub async fn enqueue_webhook_event(
+        &self,
+        owner_id: &str,
+        event: WebhookEvent,
+    ) -> MongoResult<()> {
+        let webhooks: Vec<Webhook> = self
+            .webhooks_collection()
+            .find(Webhook::find_subscribed(owner_id, event.kind()), None)
+            .await?
+            .and_then(|document| async move { Webhook::from_document(document) })
+            .try_collect()
+            .await?;
+
+        if webhooks.is_empty() {
+            return Ok(());
+        }
+
+        let now = now_epoch_secs();
+        let deliveries = webhooks
+            .iter()
+            .map(|webhook| {
+                WebhookDelivery::new(webhook.id(), owner_id, event.clone(), now).to_document()
+            })
+            .collect::<MongoResult<Vec<_>>>()?;
+
+        self.webhook_deliveries_collection()
+            .insert_many(deliveries, None)
+            .await
+            .map(|_| {})
+    }
+
+    /// Claims the longest-overdue pending delivery that is due at `now`,
+    /// leasing it until `lease_until` so that no one else attempts it meanwhile.
+    pub async fn claim_due_webhook_delivery(
+        &self,
+        now: i64,
+        lease_until: i64,
+    ) -> MongoResult<Option<WebhookDelivery>> {
+        let options = FindOneAndUpdateOptions::builder()
+            .sort(doc! { "next_attempt_at": 1 })
+            .build();
+
+        self.webhook_deliveries_collection()
+            .find_one_and_update(
+                WebhookDelivery::find_due(now),
+                WebhookDelivery::lease(lease_until),
+                options,
+            )
+            .await?
+            .map(WebhookDelivery::from_document)
+            .transpose()
+    }
+
+    /// Records an attempt at `delivery` (see `WebhookDelivery::record_attempt`)
+    /// and updates its webhook's failure count, disabling the webhook if it
+    /// has now failed too many times in a row.
+    pub async fn record_webhook_attempt(
+        &self,
+        delivery: &WebhookDelivery,
+        attempts: i32,
+        status_code: Option<i32>,
+        error: Option<&str>,
+        retry_at: Option<i64>,
+    ) -> MongoResult<()> {
+        self.webhook_deliveries_collection()
+            .update_one(
+                WebhookDelivery::find_by_id(delivery.id()),
+                WebhookDelivery::record_attempt(attempts, status_code, error, retry_at),
+                None,
+            )
+            .await?;
+
+        let webhook_update = match error {
+            None => Webhook::record_success(),
+            Some(_) => Webhook::record_failure(),
+        };
+
+        self.webhooks_collection()
+            .update_one(
+                Webhook::find_by_id(delivery.webhook_id()),
+                webhook_update,
+                None,
+            )
+            .await?;
+
+        self.webhooks_collection()
+            .update_one(
+                Webhook::find_failing(delivery.webhook_id()),
+                Webhook::disable(),
+                None,
+            )
+            .await
+            .map(|_| {})
+    }
+
+    /// Marks a delivery as failed without attempting it.
+    pub async fn abandon_webhook_delivery(&self, id: &ObjectId, reason: &str) -> MongoResult<()> {
+        self.webhook_deliveries_collection()
+            .update_one(
+                WebhookDelivery::find_by_id(id),
+                WebhookDelivery::abandon(reason),
+                None,
+            )
+            .await
+            .map(|_| {})
+    }
+
+    /// Deletes finished deliveries for events that happened before
+    /// `created_before`, returning how many.
+    pub async fn delete_finished_webhook_deliveries(
+        &self,
+        created_before: i64,
+    ) -> MongoResult<i64> {
+        self.webhook_deliveries_collection()
+            .delete_many(WebhookDelivery::find_finished_before(created_before), None)
+            .await
+            .map(|result| result.deleted_count)
+    }
+
+    fn webhooks_collection(&self) -> Collection {
+        self.database()
+            .collection(MongoManager::WEBHOOKS_COLLECTION_NAME)
+    }
+
+    fn webhook_deliveries_collection(&self) -> Collection {
+        self.database()
+            .collection(MongoManager::WEBHOOK_DELIVERIES_COLLECTION_NAME)
+    }
+}
@@ -0,0 +1,183 @@
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+
+use reqwest::Url;
+
+/// Why webhooks can't be sent to a URL.
+#[derive(Debug, PartialEq)]
+pub enum DestinationError {
+    /// Not an http(s) URL with a host.
+    InvalidUrl,
+    /// The host didn't resolve to any address.
+    Unresolvable(String),
+    /// The host resolves to an address off the public internet, e.g.
+    /// loopback, a private network, or a cloud metadata service.
+    NotPublic(IpAddr),
+}
+
+impl fmt::Display for DestinationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DestinationError::InvalidUrl => write!(f, "Webhook URL must be http(s)"),
+            DestinationError::Unresolvable(error) => {
+                write!(f, "Webhook host didn't resolve: {}", error)
+            }
+            DestinationError::NotPublic(ip) => {
+                write!(f, "Webhook host resolves to non-public address {}", ip)
+            }
+        }
+    }
+}
+
+/// Checks that `url` is http(s), and that every address its host resolves
+/// to is public, so webhooks can't be used to reach into our own network.
+pub async fn check_destination(url: &str) -> Result<(), DestinationError> {
+    let url = Url::parse(url).map_err(|_| DestinationError::InvalidUrl)?;
+
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(DestinationError::InvalidUrl);
+    }
+
+    let host = url.host_str().ok_or(DestinationError::InvalidUrl)?;
+    let port = url
+        .port_or_known_default()
+        .ok_or(DestinationError::InvalidUrl)?;
+
+    // IPv6 hosts are bracketed in URLs.
+    let ips = match host.trim_start_matches('[').trim_end_matches(']').parse() {
+        Ok(ip) => vec![ip],
+        Err(_) => tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|err| DestinationError::Unresolvable(err.to_string()))?
+            .map(|addr| addr.ip())
+            .collect(),
+    };
+
+    if ips.is_empty() {
+        return Err(DestinationError::Unresolvable(String::from(host)));
+    }
+
+    match ips.into_iter().find(|ip| !is_public(*ip)) {
+        Some(ip) => Err(DestinationError::NotPublic(ip)),
+        None => Ok(()),
+    }
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => is_public_v6(ip),
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        // "This network".
+        || a == 0
+        // Carrier-grade NAT, where some clouds put their metadata services.
+        || (a == 100 && (64..128).contains(&b))
+        // IETF protocol assignments.
+        || (a == 192 && b == 0 && c == 0)
+        // Reserved.
+        || a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let segments = ip.segments();
+
+    // IPv4 addresses mapped into IPv6, or translated by NAT64, are only as
+    // public as the IPv4 address.
+    match segments {
+        [0, 0, 0, 0, 0, 0xffff, high, low] | [0x64, 0xff9b, 0, 0, 0, 0, high, low] => {
+            let [a, b] = high.to_be_bytes();
+            let [c, d] = low.to_be_bytes();
+
+            return is_public_v4(Ipv4Addr::new(a, b, c, d));
+        }
+        _ => {}
+    }
+
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // Unique local, where AWS puts its metadata service.
+        || (segments[0] & 0xfe00) == 0xfc00
+        // Link-local.
+        || (segments[0] & 0xffc0) == 0xfe80
+        // Site-local, since deprecated.
+        || (segments[0] & 0xffc0) == 0xfec0
+        // Documentation.
+        || (segments[0] == 0x2001 && segments[1] == 0x0db8))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    /// Tests that loopback, private, link-local and metadata addresses are
+    /// rejected, including when mapped into IPv6, and public ones aren't.
+    fn test_is_public() {
+        let not_public = [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.100.100.200",
+            "0.0.0.0",
+            "::1",
+            "::",
+            "fd00:ec2::254",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "64:ff9b::a9fe:a9fe",
+        ];
+
+        for ip in &not_public {
+            assert!(!is_public(ip.parse().unwrap()), "{} is public", ip);
+        }
+
+        for ip in &["93.184.216.34", "2606:2800:220:1:248:1893:25c8:1946"] {
+            assert!(is_public(ip.parse().unwrap()), "{} isn't public", ip);
+        }
+    }
+
+    #[tokio::test]
+    /// Tests that URLs are rejected by what their host resolves to, and for
+    /// not being http(s).
+    async fn test_check_destination() {
+        assert_eq!(
+            check_destination("http://169.254.169.254/latest/meta-data").await,
+            Err(DestinationError::NotPublic(
+                "169.254.169.254".parse().unwrap()
+            ))
+        );
+        assert_eq!(
+            check_destination("https://[::1]:8443/hook").await,
+            Err(DestinationError::NotPublic("::1".parse().unwrap()))
+        );
+        assert!(matches!(
+            check_destination("http://localhost:8000/hook").await,
+            Err(DestinationError::NotPublic(_))
+        ));
+        assert_eq!(
+            check_destination("ftp://example.com/hook").await,
+            Err(DestinationError::InvalidUrl)
+        );
+        assert_eq!(
+            check_destination("https://93.184.216.34/hook").await,
+            Ok(())
+        );
+    }
+}
@@ -0,0 +1,129 @@
+use std::time::Duration;
+
+use super::{SendError, WebhookSender};
+use crate::{
+    models::{common::now_epoch_secs, storage::WebhookDelivery},
+    retry::Backoff,
+    storage::{MongoManager, MongoResult},
+};
+
+/// Drains pending webhook deliveries in the background, retrying failures with
+/// backoff. Each attempt is recorded on the delivery, which doubles as the
+/// delivery log.
+pub struct WebhookDispatcher {
+    mongo: MongoManager,
+    sender: WebhookSender,
+}
+
+impl WebhookDispatcher {
+    /// How often to check for due deliveries.
+    const POLL_INTERVAL: Duration = Duration::from_secs(5);
+    /// How long a delivery is leased for while we attempt it. If we crash
+    /// mid-attempt, it becomes due again after this long.
+    const LEASE_SECS: i64 = 60;
+    /// The most deliveries to attempt per poll.
+    const BATCH_SIZE: usize = 100;
+    const BACKOFF: Backoff = Backoff::new(Duration::from_secs(10), Duration::from_secs(60 * 60), 8);
+
+    pub fn new(mongo: MongoManager) -> Self {
+        Self {
+            mongo,
+            sender: WebhookSender::new(),
+        }
+    }
+
+    /// Dispatches due deliveries forever.
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(WebhookDispatcher::POLL_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            if let Err(mongo_err) = self.dispatch_due().await {
+                eprintln!("Failed to dispatch webhooks: {:?}", mongo_err);
+            }
+        }
+    }
+
+    async fn dispatch_due(&self) -> MongoResult<()> {
+        for _ in 0..WebhookDispatcher::BATCH_SIZE {
+            let now = now_epoch_secs();
+
+            match self
+                .mongo
+                .claim_due_webhook_delivery(now, now + WebhookDispatcher::LEASE_SECS)
+                .await?
+            {
+                Some(delivery) => self.deliver(delivery).await?,
+                None => break,
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn deliver(&self, delivery: WebhookDelivery) -> MongoResult<()> {
+        let webhook = match self.mongo.get_webhook(delivery.webhook_id()).await? {
+            Some(webhook) if !webhook.is_disabled() => webhook,
+            _ => {
+                return self
+                    .mongo
+                    .abandon_webhook_delivery(delivery.id(), "Webhook was disabled or removed")
+                    .await
+            }
+        };
+
+        let body =
+            serde_json::to_string(delivery.event()).expect("Webhook events always serialize");
+        let attempts = delivery.attempts() + 1;
+
+        let result = self
+            .sender
+            .send(
+                webhook.url(),
+                webhook.secret(),
+                &delivery.id().to_hex(),
+                delivery.event().kind().as_str(),
+                body,
+            )
+            .await;
+
+        match result {
+            Ok(status_code) => {
+                self.mongo
+                    .record_webhook_attempt(
+                        &delivery,
+                        attempts,
+                        Some(i32::from(status_code)),
+                        None,
+                        None,
+                    )
+                    .await
+            }
+            Err(send_err) => {
+                let (status_code, error) = match send_err {
+                    SendError::Status(status_code) => (
+                        Some(i32::from(status_code)),
+                        format!("Receiver responded with {}", status_code),
+                    ),
+                    SendError::Network(error) => (None, error),
+                    SendError::Destination(destination_err) => (None, destination_err.to_string()),
+                };
+
+                let retry_at = WebhookDispatcher::BACKOFF
+                    .delay_after(attempts)
+                    .map(|delay| now_epoch_secs() + delay.as_secs() as i64);
+
+                self.mongo
+                    .record_webhook_attempt(
+                        &delivery,
+                        attempts,
+                        status_code,
+                        Some(&error),
+                        retry_at,
+                    )
+                    .await
+            }
+        }
+    }
+}
@@ -0,0 +1,11 @@
+mod destination;
+mod dispatcher;
+mod sender;
+mod signature;
+mod sweeper;
+
+pub use destination::{check_destination, DestinationError};
+pub use dispatcher::WebhookDispatcher;
+pub use sender::{SendError, WebhookSender};
+pub use signature::generate_secret;
+pub use sweeper::WebhookDeliverySweeper;
@@ -0,0 +1,313 @@
+use std::time::Duration;
+
+use reqwest::{redirect, Client};
+use tokio_compat_02::FutureExt;
+
+use super::{destination, signature, DestinationError};
+use crate::models::common::now_epoch_secs;
+
+/// POSTs signed webhook deliveries.
+pub struct WebhookSender {
+    client: Client,
+    /// Whether to refuse destinations off the public internet.
+    check_destinations: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SendError {
+    /// The receiver responded, but not with a 2xx.
+    Status(u16),
+    /// We couldn't get a response at all, e.g. the connection was refused.
+    Network(String),
+    /// The URL's host resolves somewhere we won't send to, e.g. to our own
+    /// network since the webhook was registered.
+    Destination(DestinationError),
+}
+
+impl WebhookSender {
+    /// How long to wait for a receiver to respond.
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    pub fn new() -> Self {
+        Self {
+            client: Client::builder()
+                .timeout(WebhookSender::TIMEOUT)
+                // A redirect could lead anywhere, including places
+                // `check_destination` would refuse.
+                .redirect(redirect::Policy::none())
+                .build()
+                .expect("Failed to build webhook HTTP client"),
+            check_destinations: true,
+        }
+    }
+
+    /// Lets tests send to stand-in receivers on loopback.
+    #[cfg(test)]
+    pub fn allowing_any_destination(mut self) -> Self {
+        self.check_destinations = false;
+
+        self
+    }
+
+    /// POSTs `body` to `url`, signed with `secret`. Returns the receiver's
+    /// HTTP status on success.
+    pub async fn send(
+        &self,
+        url: &str,
+        secret: &str,
+        delivery_id: &str,
+        event_kind: &str,
+        body: String,
+    ) -> Result<u16, SendError> {
+        // Need the `.compat()` wrappers around futures from `reqwest`, since
+        // it uses Tokio 0.2 and we will be running on Tokio 0.3. The whole
+        // send is wrapped, since `reqwest` starts the request timeout's timer
+        // as soon as `send` is called.
+
+        // The host is checked again now, since where it resolves to may have
+        // changed since the webhook was registered.
+        if self.check_destinations {
+            destination::check_destination(url)
+                .await
+                .map_err(SendError::Destination)?;
+        }
+
+        let signature = signature::sign(secret, now_epoch_secs(), &body);
+
+        let request = self
+            .client
+            .post(url)
+            .header("content-type", "application/json")
+            .header("sonar-delivery", delivery_id)
+            .header("sonar-event", event_kind)
+            .header("sonar-signature", signature)
+            .body(body);
+
+        let response = async { request.send().await }
+            .compat() // shim
+            .await
+            .map_err(|err| SendError::Network(err.to_string()))?;
+
+        let status = response.status();
+        if status.is_success() {
+            Ok(status.as_u16())
+        } else {
+            Err(SendError::Status(status.as_u16()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    /// Tests that deliveries arrive with a valid signature and our headers.
+    async fn test_sends_signed_delivery() {
+        let mut stand_in = utils::StandIn::start(200).await;
+
+        let status = WebhookSender::new()
+            .allowing_any_destination()
+            .send(
+                &stand_in.url(),
+                "secret",
+                "delivery_id",
+                "ping_uploaded",
+                String::from("{\"hello\":\"world\"}"),
+            )
+            .await;
+
+        assert_eq!(status, Ok(200));
+
+        let request = stand_in.request().await;
+        assert!(request.head.starts_with("POST / HTTP/1.1"));
+        assert_eq!(request.header("sonar-delivery"), Some("delivery_id"));
+        assert_eq!(request.header("sonar-event"), Some("ping_uploaded"));
+        assert_eq!(request.body, "{\"hello\":\"world\"}");
+        assert!(signature::verify(
+            "secret",
+            request.header("sonar-signature").expect("No signature"),
+            &request.body,
+            now_epoch_secs(),
+            60,
+        ));
+    }
+
+    #[tokio::test]
+    /// Tests that a non-2xx response is reported as a failure.
+    async fn test_reports_error_status() {
+        let stand_in = utils::StandIn::start(503).await;
+
+        let status = WebhookSender::new()
+            .allowing_any_destination()
+            .send(
+                &stand_in.url(),
+                "secret",
+                "id",
+                "ping_uploaded",
+                String::from("{}"),
+            )
+            .await;
+
+        assert_eq!(status, Err(SendError::Status(503)));
+    }
+
+    #[tokio::test]
+    /// Tests that an unreachable receiver is reported as a network failure.
+    async fn test_reports_unreachable_receiver() {
+        // Grab a free port, then close it so nothing is listening.
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .expect("Failed to find a free port")
+            .port();
+        let url = format!("http://127.0.0.1:{}/", port);
+
+        let status = WebhookSender::new()
+            .allowing_any_destination()
+            .send(&url, "secret", "id", "ping_uploaded", String::from("{}"))
+            .await;
+
+        assert!(matches!(status, Err(SendError::Network(_))));
+    }
+
+    #[tokio::test]
+    /// Tests that nothing is sent to hosts off the public internet.
+    async fn test_refuses_non_public_destination() {
+        let status = WebhookSender::new()
+            .send(
+                "http://127.0.0.1:9/",
+                "secret",
+                "id",
+                "ping_uploaded",
+                String::from("{}"),
+            )
+            .await;
+
+        assert_eq!(
+            status,
+            Err(SendError::Destination(DestinationError::NotPublic(
+                "127.0.0.1".parse().unwrap()
+            )))
+        );
+    }
+
+    mod utils {
+        use tokio::{
+            io::{AsyncReadExt, AsyncWriteExt},
+            net::TcpListener,
+            sync::oneshot,
+            task::JoinHandle,
+        };
+
+        /// A minimal local HTTP server standing in for a webhook receiver. It
+        /// accepts a single request, records it, and responds with a fixed
+        /// status.
+        pub struct StandIn {
+            port: u16,
+            request: oneshot::Receiver<ReceivedRequest>,
+            server: JoinHandle<()>,
+        }
+
+        pub struct ReceivedRequest {
+            /// The request line and headers.
+            pub head: String,
+            pub body: String,
+        }
+
+        impl ReceivedRequest {
+            pub fn header(&self, name: &str) -> Option<&str> {
+                self.head.lines().find_map(|line| {
+                    let mut name_value = line.splitn(2, ':');
+
+                    match (name_value.next(), name_value.next()) {
+                        (Some(line_name), Some(value)) if line_name.eq_ignore_ascii_case(name) => {
+                            Some(value.trim())
+                        }
+                        _ => None,
+                    }
+                })
+            }
+        }
+
+        impl StandIn {
+            pub async fn start(status: u16) -> Self {
+                let listener = TcpListener::bind("127.0.0.1:0")
+                    .await
+                    .expect("Failed to bind stand-in");
+                let port = listener.local_addr().expect("No local addr").port();
+                let (sender, request) = oneshot::channel();
+
+                let server = tokio::spawn(async move {
+                    let (mut socket, _) = listener.accept().await.expect("Failed to accept");
+                    let received = read_request(&mut socket).await;
+
+                    let response = format!(
+                        "HTTP/1.1 {} Stand-In\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                        status
+                    );
+                    socket
+                        .write_all(response.as_bytes())
+                        .await
+                        .expect("Failed to respond");
+
+                    let _ = sender.send(received);
+                });
+
+                Self {
+                    port,
+                    request,
+                    server,
+                }
+            }
+
+            pub fn url(&self) -> String {
+                format!("http://127.0.0.1:{}/", self.port)
+            }
+
+            pub async fn request(&mut self) -> ReceivedRequest {
+                (&mut self.request)
+                    .await
+                    .expect("Stand-in received no request")
+            }
+        }
+
+        impl Drop for StandIn {
+            fn drop(&mut self) {
+                self.server.abort();
+            }
+        }
+
+        /// Reads a request's head, then as much body as its `content-length`.
+        async fn read_request(socket: &mut tokio::net::TcpStream) -> ReceivedRequest {
+            let mut buffer = Vec::new();
+            let mut chunk = [0u8; 1024];
+
+            loop {
+                let read = socket.read(&mut chunk).await.expect("Failed to read");
+                assert!(read > 0, "Connection closed mid-request");
+                buffer.extend_from_slice(&chunk[..read]);
+
+                let text = String::from_utf8_lossy(&buffer).into_owned();
+                if let Some(head_end) = text.find("\r\n\r\n") {
+                    let head = String::from(&text[..head_end]);
+                    let partial = ReceivedRequest {
+                        head,
+                        body: String::new(),
+                    };
+                    let content_length = partial
+                        .header("content-length")
+                        .and_then(|length| length.parse::<usize>().ok())
+                        .unwrap_or(0);
+
+                    let body = &text[head_end + 4..];
+                    if body.len() >= content_length {
+                        return ReceivedRequest {
+                            body: String::from(body),
+                            ..partial
+                        };
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use std::time::Duration;
+
+use crate::{models::common::now_epoch_secs, storage::MongoManager};
+
+/// Deletes finished webhook deliveries in the background once they're old
+/// enough, so the delivery log doesn't grow forever.
+pub struct WebhookDeliverySweeper {
+    mongo: MongoManager,
+}
+
+impl WebhookDeliverySweeper {
+    /// How often to delete old deliveries.
+    const SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+    /// How long after its event a finished delivery stays in the log.
+    const RETENTION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+    pub fn new(mongo: MongoManager) -> Self {
+        Self { mongo }
+    }
+
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(WebhookDeliverySweeper::SWEEP_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            let created_before =
+                now_epoch_secs() - WebhookDeliverySweeper::RETENTION.as_secs() as i64;
+
+            match self
+                .mongo
+                .delete_finished_webhook_deliveries(created_before)
+                .await
+            {
+                Ok(0) => {}
+                Ok(deleted) => eprintln!("Deleted {} old webhook deliveries", deleted),
+                Err(mongo_err) => {
+                    eprintln!("Failed to delete old webhook deliveries: {:?}", mongo_err)
+                }
+            }
+        }
+    }
+}